@@ -24,9 +24,79 @@ _sanitize_filenames()
     return 0
   fi
 
+  if [[ $have_terminator -eq 0 && $prev == "--non-utf8" ]]; then
+    COMPREPLY=( $(compgen -W 'skip replace latin1 cp1252' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--on-conflict" ]]; then
+    COMPREPLY=( $(compgen -W 'skip suffix overwrite fail' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && ( $prev == "--undo" || $prev == "--journal" ) ]]; then
+    COMPREPLY=( $(compgen -f -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--format" ]]; then
+    COMPREPLY=( $(compgen -W 'human json ndjson tsv' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--target" ]]; then
+    COMPREPLY=( $(compgen -W 'windows macos fat32 posix s3' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--length-unit" ]]; then
+    COMPREPLY=( $(compgen -W 'bytes chars' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && ( $prev == "--max-length" || $prev == "--max-path-length" ) ]]; then
+    COMPREPLY=( $(compgen -W '255 143 4095' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--case" ]]; then
+    COMPREPLY=( $(compgen -W 'preserve lower upper snake kebab camel title' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--keep-extensions" ]]; then
+    COMPREPLY=( $(compgen -W '0 1 2 3' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--ext-case" ]]; then
+    COMPREPLY=( $(compgen -W 'preserve lower upper' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--rules" ]]; then
+    COMPREPLY=( $(compgen -f -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--sort" ]]; then
+    COMPREPLY=( $(compgen -W 'name none' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--symlinks" ]]; then
+    COMPREPLY=( $(compgen -W 'skip rename follow' -- "$cur") )
+    return 0
+  fi
+
+  if [[ $have_terminator -eq 0 && $prev == "--from-file" ]]; then
+    COMPREPLY=( $(compgen -f -- "$cur") )
+    return 0
+  fi
+
   # If we're completing an option (and not after `--`), offer flags.
   if [[ $have_terminator -eq 0 && $cur == -* ]]; then
-    local opts="--recursive -r --dry-run -n --replacement -c --full-sanitize -F --help -h --"
+    local opts="--recursive -r --dry-run -n --replacement -c --full-sanitize -F --non-utf8 --transliterate -T --on-conflict --journal --auto-journal --undo --format --target --max-length --length-unit --truncate-hash --max-path-length --case --keep-extensions --compound-ext --ext-case --ext-alias --fix-extensions --profile --no-config --map --keep --strip --rules --regex --regex-post --space-replacement --punct-replacement --bracket-replacement --quote-replacement --exclude --include --gitignore --max-depth --min-depth --sort --dirs-only --files-only --symlinks --fix-symlinks --one-file-system -x --force --jobs --from-file --null -0 --help -h --"
     COMPREPLY=( $(compgen -W "${opts}" -- "$cur") )
     return 0
   fi
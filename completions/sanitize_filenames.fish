@@ -1,5 +1,50 @@
 complete -c sanitize_filenames -s h -l help -d 'Show help message and exit'
 complete -c sanitize_filenames -s r -l recursive -d 'Recursively sanitize directories and their contents'
 complete -c sanitize_filenames -s n -l dry-run -d 'Show actions without renaming files'
-complete -c sanitize_filenames -s c -l replacement -d 'Replacement character to use' -r -a '_ - . +'
+complete -c sanitize_filenames -s c -l replacement -d 'Replacement for disallowed characters' -r -a '_ - . +'
 complete -c sanitize_filenames -s F -l full-sanitize -d 'Replace all non-alphanumeric characters (except _ and -) with the replacement character'
+complete -c sanitize_filenames -l non-utf8 -d 'How to handle names that are not valid UTF-8' -x -a 'skip replace latin1 cp1252'
+complete -c sanitize_filenames -s T -l transliterate -d 'Transliterate accented and non-Latin letters to ASCII, then apply full sanitization'
+complete -c sanitize_filenames -l on-conflict -d 'What to do when the new name is already taken' -x -a 'skip suffix overwrite fail'
+complete -c sanitize_filenames -l journal -d 'Record performed renames so they can be undone' -r -F
+complete -c sanitize_filenames -l auto-journal -d 'Record performed renames in a new journal under the state directory'
+complete -c sanitize_filenames -l undo -d 'Revert the renames recorded in a journal' -r -F
+complete -c sanitize_filenames -l format -d 'Output format' -x -a 'human json ndjson tsv'
+complete -c sanitize_filenames -l target -d 'Also enforce the naming rules of a filesystem' -x -a 'windows macos fat32 posix s3'
+complete -c sanitize_filenames -l max-length -d 'Truncate names longer than N, keeping the extension' -x
+complete -c sanitize_filenames -l length-unit -d 'Count bytes or chars for --max-length' -x -a 'bytes chars'
+complete -c sanitize_filenames -l truncate-hash -d 'Add a short hash of the original name to truncated names'
+complete -c sanitize_filenames -l max-path-length -d 'Skip renames whose full path would exceed N bytes' -x
+complete -c sanitize_filenames -l case -d 'Change the case of names' -x -a 'preserve lower upper snake kebab camel title'
+complete -c sanitize_filenames -l keep-extensions -d 'Treat up to N trailing segments as the extension' -x
+complete -c sanitize_filenames -l compound-ext -d 'Extra comma-separated compound extensions to keep whole' -x
+complete -c sanitize_filenames -l ext-case -d 'Case of extensions' -x -a 'preserve lower upper'
+complete -c sanitize_filenames -l ext-alias -d 'Rename extensions (FROM=TO list, or common)' -x
+complete -c sanitize_filenames -l fix-extensions -d 'Add or correct extensions to match file contents'
+complete -c sanitize_filenames -l profile -d 'Use [profile.NAME] from the config files' -x
+complete -c sanitize_filenames -l no-config -d 'Ignore config files'
+complete -c sanitize_filenames -l map -d 'Replace FROM with TO before the mode mapping' -x
+complete -c sanitize_filenames -l keep -d 'Never replace any of CHARS' -x
+complete -c sanitize_filenames -l strip -d 'Delete every one of CHARS' -x
+complete -c sanitize_filenames -l rules -d 'Read map, keep and strip rules from FILE' -r -F
+complete -c sanitize_filenames -l regex -d 'Rewrite the stem with a regular expression before the mapping' -x
+complete -c sanitize_filenames -l regex-post -d 'Rewrite the stem with a regular expression after the mapping' -x
+complete -c sanitize_filenames -l space-replacement -d 'Replace whitespace with STR instead' -x
+complete -c sanitize_filenames -l punct-replacement -d 'Replace punctuation and symbols with STR instead' -x
+complete -c sanitize_filenames -l bracket-replacement -d 'Replace brackets with STR instead' -x
+complete -c sanitize_filenames -l quote-replacement -d 'Replace quotes with STR instead' -x
+complete -c sanitize_filenames -l exclude -d 'When recursing, skip entries matching GLOB' -x
+complete -c sanitize_filenames -l include -d 'When recursing, only rename entries matching GLOB' -x
+complete -c sanitize_filenames -l gitignore -d 'Also honour .gitignore files when recursing'
+complete -c sanitize_filenames -l max-depth -d 'When recursing, go at most N levels below each target' -x
+complete -c sanitize_filenames -l min-depth -d 'When recursing, only rename entries at least N levels down' -x
+complete -c sanitize_filenames -l sort -d 'Order in which recursion visits entries' -x -a 'name none'
+complete -c sanitize_filenames -l dirs-only -d 'Only rename directories'
+complete -c sanitize_filenames -l files-only -d 'Only rename files and symlinks'
+complete -c sanitize_filenames -l symlinks -d 'What to do with symlinks' -x -a 'skip rename follow'
+complete -c sanitize_filenames -l fix-symlinks -d 'Repoint relative symlinks whose targets were renamed'
+complete -c sanitize_filenames -s x -l one-file-system -d 'When recursing, do not enter other filesystems'
+complete -c sanitize_filenames -l force -d 'Allow operating on /, $HOME and system directories'
+complete -c sanitize_filenames -l jobs -d 'Scan directory trees with N threads' -x
+complete -c sanitize_filenames -l from-file -d 'Also sanitize the paths listed in FILE (- for stdin)' -r -F
+complete -c sanitize_filenames -s 0 -l null -d 'Paths in the --from-file list are separated by NUL bytes'
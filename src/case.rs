@@ -0,0 +1,177 @@
+//! Case normalization for `--case`.
+//!
+//! Styles that join words need to know where words start. Besides
+//! separators, a new word starts at a lower-to-upper transition
+//! (`fileName`), at the end of an acronym (`HTTPServer`), and where
+//! letters and digits meet (`track12`).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaseStyle {
+    /// Leave case alone.
+    #[default]
+    Preserve,
+    Lower,
+    Upper,
+    /// `lower_words_joined_by_underscores`
+    Snake,
+    /// `lower-words-joined-by-hyphens`
+    Kebab,
+    /// `lowerCamelCase`
+    Camel,
+    /// `Capitalized_Words` joined by the replacement character.
+    Title,
+}
+
+impl CaseStyle {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "preserve" => Ok(Self::Preserve),
+            "lower" => Ok(Self::Lower),
+            "upper" => Ok(Self::Upper),
+            "snake" => Ok(Self::Snake),
+            "kebab" => Ok(Self::Kebab),
+            "camel" => Ok(Self::Camel),
+            "title" => Ok(Self::Title),
+            _ => Err(format!(
+                "Invalid case '{s}' (expected preserve, lower, upper, snake, kebab, camel or title)"
+            )),
+        }
+    }
+
+    /// Convert the stem of a sanitized name. Every character of
+    /// `replacement` counts as a separator. Anything that is only
+    /// separators is returned unchanged rather than emptied.
+    pub fn apply(self, stem: &str, replacement: &str) -> String {
+        let out = match self {
+            Self::Preserve => return stem.to_string(),
+            Self::Lower => return stem.to_lowercase(),
+            Self::Upper => return stem.to_uppercase(),
+            Self::Snake => {
+                join(&words(stem, replacement), "_", str::to_lowercase)
+            }
+            Self::Kebab => {
+                join(&words(stem, replacement), "-", str::to_lowercase)
+            }
+            Self::Title => {
+                join(&words(stem, replacement), replacement, capitalize)
+            }
+            Self::Camel => {
+                let words = words(stem, replacement);
+                let mut out = String::new();
+                for (idx, word) in words.iter().enumerate() {
+                    if idx == 0 {
+                        out.push_str(&word.to_lowercase());
+                    } else {
+                        out.push_str(&capitalize(word));
+                    }
+                }
+                out
+            }
+        };
+        if out.is_empty() {
+            stem.to_string()
+        } else {
+            out
+        }
+    }
+}
+
+fn is_separator(ch: char, replacement: &str) -> bool {
+    replacement.contains(ch)
+        || ch.is_whitespace()
+        || matches!(ch, '_' | '-' | '.')
+}
+
+/// Split `s` into words at separators, case transitions and
+/// letter/digit boundaries.
+fn words<'a>(s: &'a str, replacement: &str) -> Vec<&'a str> {
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+    let mut words = Vec::new();
+    let mut start: Option<usize> = None;
+
+    for (i, &(pos, ch)) in chars.iter().enumerate() {
+        if is_separator(ch, replacement) {
+            if let Some(begin) = start.take() {
+                words.push(&s[begin..pos]);
+            }
+            continue;
+        }
+
+        if let Some(begin) = start
+            && i > 0
+        {
+            let prev = chars[i - 1].1;
+            let next = chars.get(i + 1).map(|&(_, c)| c);
+            let boundary = (prev.is_lowercase() && ch.is_uppercase())
+                || (prev.is_uppercase()
+                    && ch.is_uppercase()
+                    && next.is_some_and(char::is_lowercase))
+                || (prev.is_alphabetic() && ch.is_ascii_digit())
+                || (prev.is_ascii_digit() && ch.is_alphabetic());
+            if boundary {
+                words.push(&s[begin..pos]);
+                start = Some(pos);
+            }
+        } else if start.is_none() {
+            start = Some(pos);
+        }
+    }
+    if let Some(begin) = start {
+        words.push(&s[begin..]);
+    }
+    words
+}
+
+fn join(
+    words: &[&str],
+    separator: &str,
+    convert: fn(&str) -> String,
+) -> String {
+    words
+        .iter()
+        .map(|word| convert(word))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.as_str().to_lowercase().chars())
+            .collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_words_at_case_and_digit_boundaries() {
+        assert_eq!(words("myFileName", "_"), ["my", "File", "Name"]);
+        assert_eq!(words("HTTPServer_log", "_"), ["HTTP", "Server", "log"]);
+        assert_eq!(words("track12final", "_"), ["track", "12", "final"]);
+        assert_eq!(words("--a  b--", "_"), ["a", "b"]);
+    }
+
+    #[test]
+    fn converts_between_styles() {
+        let stem = "My_FileName_v2";
+        assert_eq!(CaseStyle::Preserve.apply(stem, "_"), "My_FileName_v2");
+        assert_eq!(CaseStyle::Lower.apply(stem, "_"), "my_filename_v2");
+        assert_eq!(CaseStyle::Upper.apply(stem, "_"), "MY_FILENAME_V2");
+        assert_eq!(CaseStyle::Snake.apply(stem, "_"), "my_file_name_v_2");
+        assert_eq!(CaseStyle::Kebab.apply(stem, "_"), "my-file-name-v-2");
+        assert_eq!(CaseStyle::Camel.apply(stem, "_"), "myFileNameV2");
+        assert_eq!(CaseStyle::Title.apply(stem, "_"), "My_File_Name_V_2");
+        assert_eq!(CaseStyle::Title.apply("über alles", "-"), "Über-Alles");
+    }
+
+    #[test]
+    fn separator_only_stems_are_kept() {
+        assert_eq!(CaseStyle::Snake.apply("_", "_"), "_");
+    }
+}
@@ -0,0 +1,150 @@
+//! Per-class replacements for `--space-replacement`,
+//! `--punct-replacement`, `--bracket-replacement` and
+//! `--quote-replacement`.
+//!
+//! Each character the mode rejects belongs to one class. A class without
+//! its own replacement uses the main one, so with only
+//! `--space-replacement -` and `--punct-replacement ''`,
+//! "Rock & Roll: Live" becomes "Rock-Roll-Live".
+
+/// The class of a rejected character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Space,
+    /// `()[]{}<>`
+    Bracket,
+    /// Straight and curly quotes and backticks.
+    Quote,
+    /// Any other character that is not a letter or digit.
+    Punct,
+    /// Letters and digits the mode rejects, e.g. accented ones.
+    Other,
+}
+
+impl CharClass {
+    pub fn of(ch: char) -> Self {
+        if ch.is_whitespace() {
+            Self::Space
+        } else if matches!(ch, '(' | ')' | '[' | ']' | '{' | '}' | '<' | '>') {
+            Self::Bracket
+        } else if matches!(
+            ch,
+            '\'' | '"' | '`' | '‘' | '’' | '“' | '”' | '«' | '»'
+        ) {
+            Self::Quote
+        } else if ch.is_alphanumeric() {
+            Self::Other
+        } else {
+            Self::Punct
+        }
+    }
+}
+
+/// Replacements that override the main one for a class. `None` leaves
+/// the class to the next layer down, and in the end to the main one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClassReplacements {
+    pub space: Option<String>,
+    pub punct: Option<String>,
+    pub bracket: Option<String>,
+    pub quote: Option<String>,
+}
+
+impl ClassReplacements {
+    /// The class named `name` (`space`, `punct`, `bracket` or `quote`).
+    pub(crate) fn get_mut(
+        &mut self,
+        name: &str,
+    ) -> Option<&mut Option<String>> {
+        match name {
+            "space" => Some(&mut self.space),
+            "punct" => Some(&mut self.punct),
+            "bracket" => Some(&mut self.bracket),
+            "quote" => Some(&mut self.quote),
+            _ => None,
+        }
+    }
+
+    /// Layer `other` on top of `self`.
+    pub fn merge(&mut self, other: &ClassReplacements) {
+        for (mine, theirs) in [
+            (&mut self.space, &other.space),
+            (&mut self.punct, &other.punct),
+            (&mut self.bracket, &other.bracket),
+            (&mut self.quote, &other.quote),
+        ] {
+            if theirs.is_some() {
+                mine.clone_from(theirs);
+            }
+        }
+    }
+
+    /// The replacements that are set.
+    pub fn values(&self) -> impl Iterator<Item = &str> {
+        [&self.space, &self.punct, &self.bracket, &self.quote]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+    }
+
+    /// What `ch` is replaced with, given the main `replacement`.
+    pub fn for_char<'a>(&'a self, ch: char, replacement: &'a str) -> &'a str {
+        let class = match CharClass::of(ch) {
+            CharClass::Space => &self.space,
+            CharClass::Bracket => &self.bracket,
+            CharClass::Quote => &self.quote,
+            CharClass::Punct => &self.punct,
+            CharClass::Other => &None,
+        };
+        class.as_deref().unwrap_or(replacement)
+    }
+
+    /// Every non-empty replacement in use, `replacement` included,
+    /// longest first and without duplicates.
+    pub fn separators<'a>(&'a self, replacement: &'a str) -> Vec<&'a str> {
+        let mut separators: Vec<&str> =
+            self.values().chain([replacement]).collect();
+        separators.retain(|s| !s.is_empty());
+        separators.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        separators.dedup();
+        separators
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_characters() {
+        assert_eq!(CharClass::of(' '), CharClass::Space);
+        assert_eq!(CharClass::of('\t'), CharClass::Space);
+        assert_eq!(CharClass::of('['), CharClass::Bracket);
+        assert_eq!(CharClass::of('’'), CharClass::Quote);
+        assert_eq!(CharClass::of('&'), CharClass::Punct);
+        assert_eq!(CharClass::of('©'), CharClass::Punct);
+        assert_eq!(CharClass::of('é'), CharClass::Other);
+    }
+
+    #[test]
+    fn picks_and_merges_replacements() {
+        let mut classes = ClassReplacements {
+            space: Some("-".to_string()),
+            punct: Some(String::new()),
+            ..ClassReplacements::default()
+        };
+        assert_eq!(classes.for_char(' ', "_"), "-");
+        assert_eq!(classes.for_char(':', "_"), "");
+        assert_eq!(classes.for_char('(', "_"), "_");
+        assert_eq!(classes.separators("_"), ["-", "_"]);
+
+        classes.merge(&ClassReplacements {
+            space: Some("__".to_string()),
+            bracket: Some("-".to_string()),
+            ..ClassReplacements::default()
+        });
+        assert_eq!(classes.space.as_deref(), Some("__"));
+        assert_eq!(classes.punct.as_deref(), Some(""));
+        assert_eq!(classes.separators("_"), ["__", "-", "_"]);
+    }
+}
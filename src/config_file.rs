@@ -0,0 +1,490 @@
+//! Configuration files and named profiles.
+//!
+//! Settings are read from the user's `config.toml` and from the nearest
+//! `.sanitize_filenames.toml` above each target, in that order, with
+//! later files winning. Within a file, the top-level keys come first and
+//! the `[profile.NAME]` table chosen with `--profile` is layered on top.
+//! Options given on the command line always win.
+//!
+//! The files are TOML; see `ConfigFile::parse` for the keys.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use toml::Spanned;
+use toml::de::{DeString, DeTable, DeValue};
+
+use crate::SanitizeMode;
+use crate::case::CaseStyle;
+use crate::classes::ClassReplacements;
+use crate::regex::{RegexRule, RegexRules};
+use crate::rules::CharRules;
+
+/// Per-directory configuration file, looked up from each target upwards.
+pub const PROJECT_FILE: &str = ".sanitize_filenames.toml";
+
+/// Options that config files can set. `None` leaves the option to the
+/// next layer down.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub replacement: Option<String>,
+    pub mode: Option<SanitizeMode>,
+    pub case: Option<CaseStyle>,
+    /// Patterns for entries that recursion skips, as in `.gitignore`.
+    /// Patterns from every layer apply.
+    pub exclude: Vec<String>,
+    /// Glob patterns for the names recursion renames. Patterns from every
+    /// layer apply.
+    pub include: Vec<String>,
+    /// Whether recursion honours `.gitignore` files.
+    pub gitignore: Option<bool>,
+    /// `map`, `keep` and `strip`. Rules from every layer apply, with
+    /// mappings from upper layers winning.
+    pub rules: CharRules,
+    /// `regex` and `regex_post` rules, applied in layer order.
+    pub regex: RegexRules,
+    /// `space_replacement`, `punct_replacement`, `bracket_replacement`
+    /// and `quote_replacement`.
+    pub classes: ClassReplacements,
+}
+
+impl Settings {
+    /// Layer `other` on top of `self`.
+    pub fn merge(&mut self, other: &Settings) {
+        if other.replacement.is_some() {
+            self.replacement.clone_from(&other.replacement);
+        }
+        self.mode = other.mode.or(self.mode);
+        self.case = other.case.or(self.case);
+        self.exclude.extend(other.exclude.iter().cloned());
+        self.include.extend(other.include.iter().cloned());
+        self.gitignore = other.gitignore.or(self.gitignore);
+        self.rules.merge(&other.rules);
+        self.regex.merge(&other.regex);
+        self.classes.merge(&other.classes);
+    }
+
+    /// Settings from the `entries` of a table in `text`.
+    fn from_entries<'a, 'i: 'a>(
+        text: &str,
+        entries: impl IntoIterator<
+            Item = (&'a Spanned<DeString<'i>>, &'a Spanned<DeValue<'i>>),
+        >,
+    ) -> Result<Self, String> {
+        let mut settings = Settings::default();
+        for (key, value) in entries {
+            let line = line_at(text, key.span().start);
+            let at = |msg: String| format!("line {line}: {msg}");
+            let (key, value) = (key.get_ref().as_ref(), value.get_ref());
+            match key {
+                "replacement" => {
+                    let s = as_str(value, key).map_err(at)?;
+                    settings.replacement =
+                        Some(crate::validate_replacement(s).map_err(at)?);
+                }
+                "mode" => {
+                    let s = as_str(value, key).map_err(at)?;
+                    settings.mode = Some(SanitizeMode::parse(s).map_err(at)?);
+                }
+                "case" => {
+                    let s = as_str(value, key).map_err(at)?;
+                    settings.case = Some(CaseStyle::parse(s).map_err(at)?);
+                }
+                "map" => {
+                    for spec in as_strings(value, key).map_err(at)? {
+                        settings.rules.add_map(&spec).map_err(at)?;
+                    }
+                }
+                "keep" => {
+                    let chars = as_str(value, key).map_err(at)?;
+                    settings.rules.add_keep(chars).map_err(at)?;
+                }
+                "strip" => {
+                    settings.rules.add_strip(as_str(value, key).map_err(at)?)
+                }
+                "regex" | "regex_post" => {
+                    let rules = match key {
+                        "regex" => &mut settings.regex.pre,
+                        _ => &mut settings.regex.post,
+                    };
+                    for spec in as_strings(value, key).map_err(at)? {
+                        rules.push(RegexRule::parse(&spec).map_err(at)?);
+                    }
+                }
+                "space_replacement"
+                | "punct_replacement"
+                | "bracket_replacement"
+                | "quote_replacement" => {
+                    let s = as_str(value, key).map_err(at)?;
+                    let class = key.trim_end_matches("_replacement");
+                    let slot = settings
+                        .classes
+                        .get_mut(class)
+                        .expect("key names a class");
+                    *slot = Some(crate::validate_replacement(s).map_err(at)?);
+                }
+                "exclude" => {
+                    settings.exclude = as_strings(value, key).map_err(at)?
+                }
+                "include" => {
+                    settings.include = as_strings(value, key).map_err(at)?
+                }
+                "gitignore" => {
+                    settings.gitignore = Some(as_bool(value, key).map_err(at)?)
+                }
+                _ if matches!(value, DeValue::Table(_)) => {
+                    return Err(at(format!("Unknown table [{key}]")));
+                }
+                _ => return Err(at(format!("Unknown setting '{key}'"))),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// A parsed configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigFile {
+    pub settings: Settings,
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+impl ConfigFile {
+    /// Parse a config file. Its top-level keys are settings, named after
+    /// the long options they stand for with `_` for `-`: `replacement`,
+    /// `mode` (`legacy`, `full` or `transliterate`), `case`, `map`,
+    /// `keep`, `strip`, `regex`, `regex_post`, the `*_replacement`
+    /// classes, `exclude`, `include` and `gitignore`. Each table under
+    /// `profile` holds the same keys for one profile.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let document =
+            DeTable::parse(text).map_err(|err| match err.span() {
+                Some(span) => {
+                    format!(
+                        "line {}: {}",
+                        line_at(text, span.start),
+                        err.message()
+                    )
+                }
+                None => err.message().to_string(),
+            })?;
+        let document = document.get_ref();
+
+        let mut file = ConfigFile::default();
+        let mut top = Vec::new();
+        for (key, value) in document {
+            if key.get_ref() != "profile" {
+                top.push((key, value));
+                continue;
+            }
+            let line = line_at(text, key.span().start);
+            let DeValue::Table(profiles) = value.get_ref() else {
+                return Err(format!("line {line}: 'profile' must be a table"));
+            };
+            for (name, profile) in profiles {
+                let DeValue::Table(table) = profile.get_ref() else {
+                    let line = line_at(text, name.span().start);
+                    return Err(format!(
+                        "line {line}: Profile '{}' must be a table",
+                        name.get_ref()
+                    ));
+                };
+                file.profiles.insert(
+                    name.get_ref().to_string(),
+                    Settings::from_entries(text, table)?,
+                );
+            }
+        }
+        file.settings = Settings::from_entries(text, top)?;
+        Ok(file)
+    }
+
+    /// Read and parse the file at `path`; `None` if it does not exist.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+        Self::parse(&text).map(Some).map_err(|msg| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {msg}", path.display()),
+            )
+        })
+    }
+
+    /// The top-level settings with `profile`, if any, on top.
+    fn settings(&self, profile: Option<&str>) -> Settings {
+        let mut settings = self.settings.clone();
+        if let Some(overlay) = profile.and_then(|name| self.profiles.get(name))
+        {
+            settings.merge(overlay);
+        }
+        settings
+    }
+}
+
+/// `$XDG_CONFIG_HOME/sanitize_filenames/config.toml`, falling back to
+/// `~/.config/sanitize_filenames/config.toml`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| {
+            env::var_os("HOME")
+                .filter(|v| !v.is_empty())
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some(config_home.join("sanitize_filenames").join("config.toml"))
+}
+
+/// Finds and caches the config files that apply to each target.
+#[derive(Debug, Default)]
+pub struct ConfigFiles {
+    profile: Option<String>,
+    profile_found: bool,
+    /// Whether to look for project files.
+    projects_enabled: bool,
+    user: Option<ConfigFile>,
+    /// Parsed project files by directory, `None` where there is none.
+    projects: HashMap<PathBuf, Option<ConfigFile>>,
+}
+
+impl ConfigFiles {
+    /// Load the user config from `user`, if given, and look for project
+    /// files only if `projects` is set. With neither, no file is ever
+    /// read, so only the command line counts.
+    pub fn new(
+        profile: Option<String>,
+        user: Option<&Path>,
+        projects: bool,
+    ) -> io::Result<Self> {
+        let user = match user {
+            Some(path) => ConfigFile::load(path)?,
+            None => None,
+        };
+        Ok(Self {
+            profile,
+            profile_found: false,
+            projects_enabled: projects,
+            user,
+            projects: HashMap::new(),
+        })
+    }
+
+    /// Everything the config files say about `target`.
+    pub fn settings_for(&mut self, target: &Path) -> io::Result<Settings> {
+        let project = self.project_for(target)?;
+        let profile = self.profile.as_deref();
+
+        let mut settings = Settings::default();
+        for file in self.user.iter().chain(project.iter()) {
+            settings.merge(&file.settings(profile));
+            self.profile_found |=
+                profile.is_some_and(|name| file.profiles.contains_key(name));
+        }
+        Ok(settings)
+    }
+
+    /// Fail if a profile was asked for but none of the files looked at so
+    /// far define it.
+    pub fn check_profile(&self) -> io::Result<()> {
+        match &self.profile {
+            Some(name) if !self.profile_found => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Profile '{name}' is not defined in any config file"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The nearest project file in the directory of `target` (or the
+    /// target itself, for directories) or above it.
+    fn project_for(&mut self, target: &Path) -> io::Result<Option<ConfigFile>> {
+        if !self.projects_enabled {
+            return Ok(None);
+        }
+        let start = if target.is_dir() {
+            target.to_path_buf()
+        } else {
+            target.parent().unwrap_or(Path::new("")).to_path_buf()
+        };
+        let start = if start.as_os_str().is_empty() {
+            env::current_dir()?
+        } else {
+            start.canonicalize().unwrap_or(start)
+        };
+
+        let mut searched = Vec::new();
+        let mut found = None;
+        for dir in start.ancestors() {
+            if let Some(cached) = self.projects.get(dir) {
+                found = cached.clone();
+                break;
+            }
+            searched.push(dir.to_path_buf());
+            if let Some(file) = ConfigFile::load(&dir.join(PROJECT_FILE))? {
+                found = Some(file);
+                break;
+            }
+        }
+        for dir in searched {
+            self.projects.insert(dir, found.clone());
+        }
+        Ok(found)
+    }
+}
+
+/// What a value is, for error messages.
+fn type_name(value: &DeValue) -> &'static str {
+    match value {
+        DeValue::String(_) => "a string",
+        DeValue::Integer(_) => "an integer",
+        DeValue::Float(_) => "a float",
+        DeValue::Boolean(_) => "a boolean",
+        DeValue::Datetime(_) => "a datetime",
+        DeValue::Array(_) => "an array",
+        DeValue::Table(_) => "a table",
+    }
+}
+
+fn as_str<'a>(value: &'a DeValue, key: &str) -> Result<&'a str, String> {
+    match value {
+        DeValue::String(s) => Ok(s),
+        other => Err(format!(
+            "'{key}' must be a string, not {}",
+            type_name(other)
+        )),
+    }
+}
+
+fn as_bool(value: &DeValue, key: &str) -> Result<bool, String> {
+    match value {
+        DeValue::Boolean(b) => Ok(*b),
+        other => Err(format!(
+            "'{key}' must be a boolean, not {}",
+            type_name(other)
+        )),
+    }
+}
+
+fn as_strings(value: &DeValue, key: &str) -> Result<Vec<String>, String> {
+    let error = || format!("'{key}' must be an array of strings");
+    match value {
+        DeValue::Array(items) => items
+            .iter()
+            .map(|item| match item.get_ref() {
+                DeValue::String(s) => Ok(s.to_string()),
+                _ => Err(error()),
+            })
+            .collect(),
+        _ => Err(error()),
+    }
+}
+
+/// The line of `text` that the byte `offset` is on, counting from 1.
+fn line_at(text: &str, offset: usize) -> usize {
+    let offset = offset.min(text.len());
+    text.as_bytes()[..offset]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings_and_profiles() {
+        let text = r#"
+# Team policy
+replacement = "-"
+mode = 'full'
+exclude = [
+    ".git",
+    "node_modules", # vendored
+]
+
+[profile.music]
+case = "title"
+replacement = "_"
+map = ["&=and"]
+regex = ['\s*\[[^\]]*\]=']
+"#;
+        let file = ConfigFile::parse(text).unwrap();
+        assert_eq!(file.settings.replacement.as_deref(), Some("-"));
+        assert_eq!(file.settings.mode, Some(SanitizeMode::Full));
+        assert_eq!(file.settings.exclude, [".git", "node_modules"]);
+
+        let music = file.settings(Some("music"));
+        assert_eq!(music.replacement.as_deref(), Some("_"));
+        assert_eq!(music.case, Some(CaseStyle::Title));
+        assert_eq!(music.mode, Some(SanitizeMode::Full));
+        assert_eq!(music.rules.apply_map("R&B"), "RandB");
+        assert_eq!(music.regex.pre[0].apply("Song [live]"), "Song");
+        assert_eq!(file.settings(None).case, None);
+    }
+
+    #[test]
+    fn merge_prefers_the_upper_layer() {
+        let mut lower = Settings {
+            replacement: Some("-".to_string()),
+            case: Some(CaseStyle::Lower),
+            exclude: vec![".git".to_string()],
+            ..Settings::default()
+        };
+        lower.merge(&Settings {
+            replacement: Some(".".to_string()),
+            exclude: vec!["*.tmp".to_string()],
+            ..Settings::default()
+        });
+        assert_eq!(lower.replacement.as_deref(), Some("."));
+        assert_eq!(lower.case, Some(CaseStyle::Lower));
+        assert_eq!(lower.exclude, [".git", "*.tmp"]);
+    }
+
+    #[test]
+    fn accepts_any_toml_syntax() {
+        let text = r#"
+replacement = """
+-"""
+exclude = ['C:\', "tab\there \u00e9"]
+profile.quiet = { case = "lower" }
+
+[profile."two words"]
+mode = "full"
+"#;
+        let file = ConfigFile::parse(text).unwrap();
+        assert_eq!(file.settings.exclude, ["C:\\", "tab\there \u{e9}"]);
+        let quiet = file.settings(Some("quiet"));
+        assert_eq!(quiet.case, Some(CaseStyle::Lower));
+        let two_words = file.settings(Some("two words"));
+        assert_eq!(two_words.mode, Some(SanitizeMode::Full));
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let err = |text: &str| ConfigFile::parse(text).unwrap_err();
+        assert_eq!(
+            err("\nreplacment = '-'"),
+            "line 2: Unknown setting 'replacment'"
+        );
+        assert_eq!(err("mode = 'loud'").split(':').next(), Some("line 1"));
+        assert!(err("case = 3").contains("must be a string, not an integer"));
+        assert!(err("a = 1\nb = \"open").starts_with("line 2: "));
+        assert_eq!(err("[x]\n"), "line 1: Unknown table [x]");
+        assert!(err("mode = 'full'\nmode = 'full'").starts_with("line 2: "));
+        assert!(err("profile = 'music'").contains("must be a table"));
+        assert!(err("[profile]\nmusic = 1").contains("must be a table"));
+        assert!(err("replacement = '/'").contains("not allowed"));
+    }
+}
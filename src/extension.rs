@@ -0,0 +1,346 @@
+//! What counts as a file name's extension.
+//!
+//! Only the stem of a name is sanitized; the extension is reattached as
+//! it was. Besides the last dot-segment, known multi-part suffixes such
+//! as `.tar.gz` are kept whole, and segments that do not look like an
+//! extension (`v1.2`, `Part 1. The Beginning`) are left in the stem.
+//!
+//! The extension is then normalized on its own: `--ext-alias` maps
+//! spellings like `jpeg` to a canonical one and `--ext-case` fixes its
+//! case.
+
+/// Multi-part extensions that are always kept together.
+const COMPOUND: &[&str] = &[
+    "tar.gz",
+    "tar.bz2",
+    "tar.xz",
+    "tar.zst",
+    "tar.lz",
+    "tar.lz4",
+    "tar.lzma",
+    "tar.br",
+    "tar.z",
+    "pkg.tar.zst",
+    "pkg.tar.xz",
+    "d.ts",
+    "d.mts",
+    "d.cts",
+    "min.js",
+    "min.css",
+    "min.mjs",
+    "js.map",
+    "css.map",
+    "user.js",
+    "spec.js",
+    "spec.ts",
+    "test.js",
+    "test.ts",
+    "tar.gz.sig",
+    "tar.gz.asc",
+    "tar.xz.sig",
+    "tar.xz.asc",
+];
+
+/// Aliases enabled by `--ext-alias common`.
+const COMMON_ALIASES: &[(&str, &str)] = &[
+    ("jpeg", "jpg"),
+    ("jpe", "jpg"),
+    ("tiff", "tif"),
+    ("htm", "html"),
+    ("mpeg", "mpg"),
+];
+
+/// Longest single segment that still counts as an extension.
+const MAX_SEGMENT_LEN: usize = 16;
+
+/// Whether `segment` (without its dot) looks like an extension: short,
+/// made of letters, digits, `_` or `-`, and not just a number.
+pub fn looks_like_extension(segment: &str) -> bool {
+    !segment.is_empty()
+        && segment.chars().count() <= MAX_SEGMENT_LEN
+        && segment
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        && segment.chars().any(char::is_alphabetic)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExtensionCase {
+    #[default]
+    Preserve,
+    Lower,
+    Upper,
+}
+
+impl ExtensionCase {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "preserve" => Ok(Self::Preserve),
+            "lower" => Ok(Self::Lower),
+            "upper" => Ok(Self::Upper),
+            _ => Err(format!(
+                "Invalid extension case '{s}' (expected preserve, lower or upper)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionRules {
+    /// How many trailing segments may form an extension that is not in
+    /// the compound table. 0 means names have no extension at all.
+    pub max_parts: usize,
+    /// Compound extensions on top of the built-in table, lowercase and
+    /// without a leading dot.
+    pub compound: Vec<String>,
+    pub case: ExtensionCase,
+    /// `(from, to)` pairs, lowercase and without a leading dot. Earlier
+    /// entries win.
+    pub aliases: Vec<(String, String)>,
+}
+
+impl Default for ExtensionRules {
+    fn default() -> Self {
+        Self {
+            max_parts: 1,
+            compound: Vec::new(),
+            case: ExtensionCase::default(),
+            aliases: Vec::new(),
+        }
+    }
+}
+
+impl ExtensionRules {
+    /// Add the comma-separated compound extensions in `list`.
+    pub(crate) fn add_compound(&mut self, list: &str) -> Result<(), String> {
+        for ext in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let ext = ext.strip_prefix('.').unwrap_or(ext).to_lowercase();
+            if !ext
+                .split('.')
+                .all(|part| !part.is_empty() && !part.contains('/'))
+            {
+                return Err(format!("Invalid compound extension '{ext}'"));
+            }
+            self.compound.push(ext);
+        }
+        Ok(())
+    }
+
+    /// Add the comma-separated `from=to` aliases in `list`. The word
+    /// `common` adds the built-in table (`jpeg=jpg`, `tiff=tif`, ...).
+    pub(crate) fn add_aliases(&mut self, list: &str) -> Result<(), String> {
+        for entry in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            if entry == "common" {
+                self.aliases.extend(
+                    COMMON_ALIASES
+                        .iter()
+                        .map(|&(from, to)| (from.to_string(), to.to_string())),
+                );
+                continue;
+            }
+            let valid = |ext: &str| {
+                !ext.is_empty() && ext.split('.').all(looks_like_extension)
+            };
+            let pair = entry.split_once('=').map(|(from, to)| {
+                let from = from.trim();
+                let to = to.trim();
+                (
+                    from.strip_prefix('.').unwrap_or(from).to_lowercase(),
+                    to.strip_prefix('.').unwrap_or(to).to_string(),
+                )
+            });
+            match pair {
+                Some((from, to)) if valid(&from) && valid(&to) => {
+                    self.aliases.push((from, to))
+                }
+                _ => {
+                    return Err(format!(
+                        "Invalid extension alias '{entry}' (expected FROM=TO or common)"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the aliases and case rule to an extension that has already
+    /// been sanitized. An alias matches the whole extension or its last
+    /// segments, ignoring case; an all-uppercase match gets an uppercase
+    /// replacement.
+    pub fn normalize(&self, extension: &str) -> String {
+        let lower = extension.to_lowercase();
+        let mut out = extension.to_string();
+        if lower.len() == extension.len() {
+            let alias = self.aliases.iter().find(|(from, _)| {
+                lower == *from
+                    || (lower.ends_with(from.as_str())
+                        && lower.as_bytes()[lower.len() - from.len() - 1]
+                            == b'.')
+            });
+            if let Some((from, to)) = alias {
+                let start = extension.len() - from.len();
+                let matched = &extension[start..];
+                let to = if matched.chars().any(char::is_lowercase) {
+                    to.clone()
+                } else {
+                    to.to_uppercase()
+                };
+                out = format!("{}{to}", &extension[..start]);
+            }
+        }
+        match self.case {
+            ExtensionCase::Preserve => out,
+            ExtensionCase::Lower => out.to_lowercase(),
+            ExtensionCase::Upper => out.to_uppercase(),
+        }
+    }
+
+    /// The extension of the file name `name`, without its leading dot.
+    /// May contain dots for compound extensions; empty if there is none.
+    pub fn extension_of<'a>(&self, name: &'a str) -> &'a str {
+        self.find_extension(name, looks_like_extension)
+    }
+
+    /// Like `extension_of`, but a segment counts if it looks like an
+    /// extension once `clean` has sanitized it, so `a.tx t` has the
+    /// extension `tx t`. A segment that starts with whitespace is prose,
+    /// as in `Part 1. The Beginning`, not an extension.
+    pub fn extension_of_cleaned<'a>(
+        &self,
+        name: &'a str,
+        clean: impl Fn(&str) -> String,
+    ) -> &'a str {
+        self.find_extension(name, |segment| {
+            !segment.starts_with(char::is_whitespace)
+                && looks_like_extension(&clean(segment))
+        })
+    }
+
+    fn find_extension<'a>(
+        &self,
+        name: &'a str,
+        is_segment: impl Fn(&str) -> bool,
+    ) -> &'a str {
+        if self.max_parts == 0 {
+            return "";
+        }
+
+        let lower = name.to_lowercase();
+        // Lowercasing can change byte lengths, so only trust a match if it
+        // does not here.
+        if lower.len() == name.len() {
+            let best = COMPOUND
+                .iter()
+                .copied()
+                .chain(self.compound.iter().map(String::as_str))
+                .filter(|ext| {
+                    lower.len() > ext.len() + 1
+                        && lower.ends_with(ext)
+                        && lower.as_bytes()[lower.len() - ext.len() - 1] == b'.'
+                })
+                .max_by_key(|ext| ext.len());
+            if let Some(ext) = best {
+                return &name[name.len() - ext.len()..];
+            }
+        }
+
+        let mut start = name.len();
+        for _ in 0..self.max_parts {
+            let Some(dot) = name[..start].rfind('.') else {
+                break;
+            };
+            if !is_segment(&name[dot + 1..start]) {
+                break;
+            }
+            start = dot;
+        }
+        if start == name.len() {
+            ""
+        } else {
+            &name[start + 1..]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_known_compound_extensions() {
+        let rules = ExtensionRules::default();
+        assert_eq!(rules.extension_of("My Backup.tar.gz"), "tar.gz");
+        assert_eq!(rules.extension_of("types.d.ts"), "d.ts");
+        assert_eq!(rules.extension_of("app.MIN.JS"), "MIN.JS");
+        assert_eq!(rules.extension_of("song.m4a.wav"), "wav");
+        assert_eq!(rules.extension_of("tar.gz"), "gz");
+    }
+
+    #[test]
+    fn ignores_segments_that_are_not_extensions() {
+        let rules = ExtensionRules::default();
+        assert_eq!(rules.extension_of("release v1.2"), "");
+        assert_eq!(rules.extension_of("Part 1. The Beginning"), "");
+        assert_eq!(rules.extension_of("notes.averyveryverylongword"), "");
+        assert_eq!(rules.extension_of("Hello.World"), "World");
+        assert_eq!(rules.extension_of("photo.jpeg"), "jpeg");
+        assert_eq!(rules.extension_of("video.mp4"), "mp4");
+    }
+
+    #[test]
+    fn cleaned_segments_can_be_extensions() {
+        let rules = ExtensionRules::default();
+        let clean = |s: &str| s.replace(' ', "_");
+        assert_eq!(rules.extension_of_cleaned("a.tx t", clean), "tx t");
+        assert_eq!(rules.extension_of_cleaned("x.m p3", clean), "m p3");
+        assert_eq!(
+            rules.extension_of_cleaned("Part 1. The Beginning", clean),
+            ""
+        );
+        assert_eq!(rules.extension_of_cleaned("release v1.2", clean), "");
+    }
+
+    #[test]
+    fn keep_extensions_takes_several_segments() {
+        let rules = ExtensionRules {
+            max_parts: 2,
+            ..ExtensionRules::default()
+        };
+        assert_eq!(rules.extension_of("song.m4a.wav"), "m4a.wav");
+        assert_eq!(rules.extension_of("v1.2.zip"), "zip");
+
+        let none = ExtensionRules {
+            max_parts: 0,
+            ..ExtensionRules::default()
+        };
+        assert_eq!(none.extension_of("My Backup.tar.gz"), "");
+    }
+
+    #[test]
+    fn custom_compound_extensions() {
+        let mut rules = ExtensionRules::default();
+        rules.add_compound(".blend1, foo.bar").unwrap();
+        assert_eq!(rules.extension_of("scene.foo.bar"), "foo.bar");
+        assert!(rules.add_compound("a..b").is_err());
+    }
+
+    #[test]
+    fn normalizes_aliases_and_case() {
+        let mut rules = ExtensionRules::default();
+        rules.add_aliases("common, tgz=tar.gz").unwrap();
+        assert_eq!(rules.normalize("jpeg"), "jpg");
+        assert_eq!(rules.normalize("JPEG"), "JPG");
+        assert_eq!(rules.normalize("Htm"), "html");
+        assert_eq!(rules.normalize("tgz"), "tar.gz");
+        assert_eq!(rules.normalize("min.htm"), "min.html");
+        assert_eq!(rules.normalize("xjpeg"), "xjpeg");
+
+        rules.case = ExtensionCase::Lower;
+        assert_eq!(rules.normalize("JPEG"), "jpg");
+        assert_eq!(rules.normalize("Mp3"), "mp3");
+
+        assert!(rules.add_aliases("jpeg").is_err());
+        assert!(rules.add_aliases("jpeg=").is_err());
+        assert!(rules.add_aliases("a b=c").is_err());
+    }
+}
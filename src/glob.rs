@@ -0,0 +1,116 @@
+//! Shell-style wildcard patterns for `exclude`.
+//!
+//! `*` matches any run of characters, `?` a single character, and
+//! `[abc]`, `[a-z]` or `[!abc]` one character from (or not from) a set.
+//! A backslash makes the next character literal.
+
+/// Whether `name` matches `pattern` as a whole.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_from(&pattern, &name)
+}
+
+fn matches_from(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the most recent `*`: the pattern position
+    // after it and the name position it currently extends to.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match class_len(&pattern[p..]) {
+                Some(len) => {
+                    class_matches(&pattern[p..p + len], name[n]).then_some(len)
+                }
+                // An unterminated `[` is an ordinary character.
+                None => (name[n] == '[').then_some(1),
+            },
+            Some('\\') if p + 1 < pattern.len() => {
+                (pattern[p + 1] == name[n]).then_some(2)
+            }
+            Some(&ch) => (ch == name[n]).then_some(1),
+            None => None,
+        };
+        match (step, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            (None, Some((after_star, start))) => {
+                p = after_star;
+                n = start + 1;
+                backtrack = Some((after_star, start + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&ch| ch == '*')
+}
+
+/// Length of the bracket expression at the start of `pattern`, or
+/// `None` if it is not terminated. A `]` right after the opening `[` (or
+/// `[!`) is part of the set.
+fn class_len(pattern: &[char]) -> Option<usize> {
+    let mut i = 1;
+    if matches!(pattern.get(i), Some('!' | '^')) {
+        i += 1;
+    }
+    if pattern.get(i) == Some(&']') {
+        i += 1;
+    }
+    let close = pattern.get(i..)?.iter().position(|&c| c == ']')?;
+    Some(i + close + 1)
+}
+
+fn class_matches(class: &[char], ch: char) -> bool {
+    let negated = matches!(class[1], '!' | '^');
+    let body = &class[1 + usize::from(negated)..class.len() - 1];
+    let mut found = false;
+    let mut i = 0;
+    while i < body.len() {
+        if i + 2 < body.len() && body[i + 1] == '-' {
+            found |= (body[i]..=body[i + 2]).contains(&ch);
+            i += 3;
+        } else {
+            found |= body[i] == ch;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches(".git", ".git"));
+        assert!(!matches(".git", ".github"));
+        assert!(matches("*.tmp", "notes.tmp"));
+        assert!(!matches("*.tmp", "notes.tmp.txt"));
+        assert!(matches("node_*", "node_modules"));
+        assert!(matches("a*b*c", "aXXbYYbc"));
+        assert!(matches("track??.mp3", "track01.mp3"));
+        assert!(!matches("track??.mp3", "track1.mp3"));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn matches_classes_and_escapes() {
+        assert!(matches("[Tt]humbs.db", "Thumbs.db"));
+        assert!(matches("IMG_[0-9]*", "IMG_2024.jpg"));
+        assert!(!matches("IMG_[!0-9]*", "IMG_2024.jpg"));
+        assert!(matches("\\*star", "*star"));
+        assert!(!matches("\\*star", "bigstar"));
+        assert!(matches("[", "["));
+        assert!(matches("[]x]", "]"));
+    }
+}
@@ -0,0 +1,303 @@
+//! Which entries recursion skips: `--exclude`, `.sanitizeignore` files
+//! and, with `--gitignore`, `.gitignore` files.
+//!
+//! Patterns follow `.gitignore`: a pattern without a `/` matches a name
+//! at any depth, one with a `/` (other than a trailing one) matches the
+//! path relative to the directory of the file it is in, `**` matches any
+//! number of directories, a trailing `/` only matches directories and a
+//! leading `!` re-includes what an earlier pattern excluded. The last
+//! matching pattern wins, and an excluded directory is never entered.
+//!
+//! Ignore files apply to their own directory and everything below it,
+//! including those above a target up to the root of its repository.
+//! Version control metadata (`.git`, `.hg`, `.svn`) is excluded unless a
+//! pattern such as `!.git` re-includes it.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config_file::PROJECT_FILE;
+use crate::glob;
+
+/// Per-directory ignore file that is always read.
+pub const IGNORE_FILE: &str = ".sanitizeignore";
+
+/// Excluded before any other pattern is considered.
+const DEFAULT_EXCLUDES: [&str; 3] = [".git/", ".hg/", ".svn/"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    /// The pattern's `/`-separated segments.
+    segments: Vec<String>,
+    negated: bool,
+    dir_only: bool,
+    /// Matched against the whole relative path rather than the name.
+    anchored: bool,
+}
+
+impl Rule {
+    /// Parse one line; `None` for blank lines and comments.
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let line = if line.ends_with("\\ ") {
+            line
+        } else {
+            line.trim_end()
+        };
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            segments: pattern.split('/').map(str::to_string).collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &[String], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            match_segments(&self.segments, path)
+        } else {
+            path.last()
+                .is_some_and(|name| glob::matches(&self.segments[0], name))
+        }
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => {
+            path.first().is_some_and(|name| glob::matches(first, name))
+                && match_segments(rest, &path[1..])
+        }
+    }
+}
+
+/// Patterns and the directory they are relative to.
+#[derive(Debug, Clone)]
+struct RuleSet {
+    /// Where the patterns apply, as the walk spells it.
+    base: PathBuf,
+    /// Where `base` is relative to the directory the patterns were
+    /// written for, for files above the walk's root.
+    prefix: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    fn new(
+        base: &Path,
+        prefix: Vec<String>,
+        lines: &[impl AsRef<str>],
+    ) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            prefix,
+            rules: lines
+                .iter()
+                .filter_map(|l| Rule::parse(l.as_ref()))
+                .collect(),
+        }
+    }
+
+    /// `Some(excluded)` for the last rule matching `path`.
+    fn verdict(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let rest = path.strip_prefix(&self.base).ok()?;
+        let mut relative = self.prefix.clone();
+        relative.extend(names(rest));
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(&relative, is_dir))
+            .map(|rule| !rule.negated)
+    }
+}
+
+fn names(path: &Path) -> impl Iterator<Item = String> + '_ {
+    path.components().filter_map(|c| match c {
+        Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+        _ => None,
+    })
+}
+
+/// The ignore rules in effect at the current point of a walk.
+#[derive(Debug, Clone)]
+pub struct Ignores {
+    files: Vec<&'static str>,
+    /// Default excludes, then ignore files from the outermost directory
+    /// inwards.
+    sets: Vec<RuleSet>,
+    /// `--exclude` and config file patterns, which override the files.
+    excludes: RuleSet,
+}
+
+impl Ignores {
+    /// Rules for a walk from `root`, with `exclude` patterns relative to
+    /// it. Ignore files in the directories above `root` are read up to
+    /// the root of its repository; outside a repository, none are.
+    pub fn new(
+        root: &Path,
+        exclude: &[String],
+        gitignore: bool,
+    ) -> io::Result<Self> {
+        let mut files = vec![IGNORE_FILE];
+        if gitignore {
+            files.push(".gitignore");
+        }
+        let mut ignores = Self {
+            files,
+            sets: vec![RuleSet::new(root, Vec::new(), &DEFAULT_EXCLUDES)],
+            excludes: RuleSet::new(root, Vec::new(), exclude),
+        };
+
+        // A missing root is reported by the walk itself. Outside a
+        // repository, nothing above the root applies.
+        let Ok(canonical) = fs::canonicalize(root) else {
+            return Ok(ignores);
+        };
+        let Some(repo) =
+            canonical.ancestors().find(|dir| dir.join(".git").exists())
+        else {
+            return Ok(ignores);
+        };
+        let mut outer = Vec::new();
+        let mut prefix = Vec::new();
+        for dir in canonical.ancestors() {
+            if dir != canonical {
+                // Directories outside the walk may not be readable.
+                let lines = ignores.read(dir).unwrap_or_default();
+                outer.push(RuleSet::new(root, prefix.clone(), &lines));
+            }
+            if dir == repo {
+                break;
+            }
+            if let Some(name) = dir.file_name() {
+                prefix.insert(0, name.to_string_lossy().into_owned());
+            }
+        }
+        ignores.sets.extend(outer.into_iter().rev());
+        Ok(ignores)
+    }
+
+    fn read(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        for file in &self.files {
+            match fs::read_to_string(dir.join(file)) {
+                Ok(text) => lines.extend(text.lines().map(str::to_string)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Read the ignore files in `dir` before walking its entries.
+    /// Returns what `leave` needs to drop them again.
+    pub fn enter(&mut self, dir: &Path) -> io::Result<usize> {
+        let lines = self.read(dir)?;
+        if lines.is_empty() {
+            return Ok(0);
+        }
+        self.sets.push(RuleSet::new(dir, Vec::new(), &lines));
+        Ok(1)
+    }
+
+    /// Drop the rules `enter` added.
+    pub fn leave(&mut self, entered: usize) {
+        self.sets.truncate(self.sets.len() - entered);
+    }
+
+    /// Whether the walk skips `path`, and everything below it. Project
+    /// config files are always skipped.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path.file_name().is_some_and(|name| name == PROJECT_FILE) {
+            return true;
+        }
+        self.sets
+            .iter()
+            .chain([&self.excludes])
+            .rev()
+            .find_map(|set| set.verdict(path, is_dir))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(lines: &[&str]) -> RuleSet {
+        RuleSet::new(Path::new("root"), Vec::new(), lines)
+    }
+
+    fn ignored(set: &RuleSet, path: &str, is_dir: bool) -> bool {
+        set.verdict(&Path::new("root").join(path), is_dir)
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn matches_gitignore_patterns() {
+        let set = rules(&[
+            "# build output",
+            "*.tmp",
+            "build/",
+            "/top.txt",
+            "docs/*.pdf",
+            "**/cache/**",
+            "",
+        ]);
+        assert!(ignored(&set, "a.tmp", false));
+        assert!(ignored(&set, "deep/down/b.tmp", false));
+        assert!(ignored(&set, "build", true));
+        assert!(!ignored(&set, "build", false));
+        assert!(ignored(&set, "top.txt", false));
+        assert!(!ignored(&set, "sub/top.txt", false));
+        assert!(ignored(&set, "docs/manual.pdf", false));
+        assert!(!ignored(&set, "docs/old/manual.pdf", false));
+        assert!(ignored(&set, "a/cache/x/y", false));
+        assert!(!ignored(&set, "notes.txt", false));
+    }
+
+    #[test]
+    fn later_patterns_win() {
+        let set = rules(&["*.log", "!keep.log"]);
+        assert!(ignored(&set, "debug.log", false));
+        assert!(!ignored(&set, "keep.log", false));
+        assert_eq!(set.verdict(Path::new("root/keep.log"), false), Some(false));
+        assert_eq!(set.verdict(Path::new("root/x.txt"), false), None);
+        assert_eq!(set.verdict(Path::new("elsewhere/a.log"), false), None);
+    }
+
+    #[test]
+    fn prefixed_sets_match_from_their_own_directory() {
+        let set = RuleSet::new(
+            Path::new("root"),
+            vec!["sub".to_string()],
+            &["/sub/a.txt"],
+        );
+        assert!(ignored(&set, "a.txt", false));
+        assert!(!ignored(&set, "b/a.txt", false));
+    }
+}
@@ -0,0 +1,228 @@
+//! Journal of performed renames, used by `--journal` and `--undo`.
+//!
+//! The format is one rename per line, `OLD<TAB>NEW`, after a header
+//! line. Paths are absolute and escaped so that tabs, newlines and bytes
+//! that are not valid UTF-8 survive a round trip:
+//! `\\`, `\t`, `\n`, `\r` and `\xHH`.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HEADER: &str = "# sanitize_filenames journal v1";
+
+fn invalid_data(path: &Path, line: usize, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}:{line}: {msg}", path.display()),
+    )
+}
+
+pub(crate) fn escape(path: &Path) -> String {
+    let mut out = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            match ch {
+                '\\' => out.push_str("\\\\"),
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                _ => out.push(ch),
+            }
+        }
+        for byte in chunk.invalid() {
+            out.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        match iter.next()? {
+            b'\\' => bytes.push(b'\\'),
+            b't' => bytes.push(b'\t'),
+            b'n' => bytes.push(b'\n'),
+            b'r' => bytes.push(b'\r'),
+            b'x' => {
+                let hex = [iter.next()?, iter.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+    Some(PathBuf::from(os_string_from_bytes(bytes)?))
+}
+
+#[cfg(unix)]
+pub(crate) fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    String::from_utf8(bytes).ok().map(OsString::from)
+}
+
+/// Where `--auto-journal` writes:
+/// `$XDG_STATE_HOME/sanitize_filenames/`, falling back to
+/// `~/.local/state/sanitize_filenames/`.
+pub fn default_journal_path() -> Option<PathBuf> {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| {
+            env::var_os("HOME")
+                .filter(|v| !v.is_empty())
+                .map(|home| PathBuf::from(home).join(".local").join("state"))
+        })?;
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    Some(
+        state_home
+            .join("sanitize_filenames")
+            .join(format!("journal-{secs}-{}.tsv", std::process::id())),
+    )
+}
+
+/// An open journal that renames are appended to as they happen, so an
+/// interrupted run can still be undone.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: Option<File>,
+    entries: usize,
+}
+
+impl Journal {
+    /// The file is only created once the first rename is recorded.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            file: None,
+            entries: 0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of renames recorded so far.
+    pub fn len(&self) -> usize {
+        self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Create the file, if it is not open yet. Called before each rename
+    /// so that a journal which cannot be written stops the run before
+    /// anything is renamed.
+    pub fn open(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            if let Some(dir) = self.path.parent()
+                && !dir.as_os_str().is_empty()
+            {
+                fs::create_dir_all(dir)?;
+            }
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            if file.metadata()?.len() == 0 {
+                writeln!(file, "{HEADER}")?;
+            }
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().expect("journal file was just opened"))
+    }
+
+    pub fn record(&mut self, old: &Path, new: &Path) -> io::Result<()> {
+        let file = self.open()?;
+        writeln!(
+            file,
+            "{}\t{}",
+            escape(&std::path::absolute(old)?),
+            escape(&std::path::absolute(new)?)
+        )?;
+        file.flush()?;
+        self.entries += 1;
+        Ok(())
+    }
+}
+
+/// Read the `(old, new)` pairs of a journal, in the order they were
+/// performed.
+pub fn read_journal(path: &Path) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let lineno = idx + 1;
+        if lineno == 1 {
+            if line != HEADER {
+                return Err(invalid_data(
+                    path,
+                    lineno,
+                    "not a sanitize_filenames journal",
+                ));
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (old, new) = line
+            .split_once('\t')
+            .and_then(|(old, new)| Some((unescape(old)?, unescape(new)?)))
+            .ok_or_else(|| invalid_data(path, lineno, "malformed entry"))?;
+        entries.push((old, new));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_round_trips_awkward_names() {
+        for name in ["plain.txt", "tab\there", "new\nline\\x41", "back\\slash"]
+        {
+            let path = PathBuf::from("/tmp").join(name);
+            assert_eq!(unescape(&escape(&path)), Some(path));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escape_round_trips_non_utf8_names() {
+        use std::os::unix::ffi::OsStringExt;
+        let path = PathBuf::from(OsString::from_vec(b"/tmp/caf\xe9".to_vec()));
+        assert_eq!(escape(&path), "/tmp/caf\\xe9");
+        assert_eq!(unescape(&escape(&path)), Some(path));
+    }
+
+    #[test]
+    fn unescape_rejects_bad_escapes() {
+        assert_eq!(unescape("bad\\q"), None);
+        assert_eq!(unescape("bad\\x4"), None);
+        assert_eq!(unescape("trailing\\"), None);
+    }
+}
@@ -0,0 +1,219 @@
+//! Name length limits for `--max-length` and `--max-path-length`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LengthUnit {
+    /// UTF-8 bytes, as counted by `NAME_MAX` on ext4, XFS, Btrfs, ...
+    #[default]
+    Bytes,
+    /// Unicode scalar values.
+    Chars,
+}
+
+impl LengthUnit {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "bytes" => Ok(Self::Bytes),
+            "chars" => Ok(Self::Chars),
+            _ => Err(format!(
+                "Invalid length unit '{s}' (expected bytes or chars)"
+            )),
+        }
+    }
+
+    pub fn measure(self, s: &str) -> usize {
+        match self {
+            Self::Bytes => s.len(),
+            Self::Chars => s.chars().count(),
+        }
+    }
+
+    /// The longest prefix of `s` that is at most `max` long, cut on a
+    /// character boundary.
+    fn prefix(self, s: &str, max: usize) -> &str {
+        let end = match self {
+            Self::Bytes => {
+                let mut end = max.min(s.len());
+                while !s.is_char_boundary(end) {
+                    end -= 1;
+                }
+                end
+            }
+            Self::Chars => {
+                s.char_indices().nth(max).map_or(s.len(), |(i, _)| i)
+            }
+        };
+        &s[..end]
+    }
+}
+
+/// Limit on the length of a single file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthLimit {
+    pub max: usize,
+    pub unit: LengthUnit,
+    /// Append a short hash of the original name to truncated names, so
+    /// that names which only differ after the cut stay distinct.
+    pub hash: bool,
+}
+
+impl LengthLimit {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            unit: LengthUnit::default(),
+            hash: false,
+        }
+    }
+
+    /// Shorten `name` to fit the limit. Only the stem is cut, so
+    /// `extension` (without its dot) survives when it leaves room for at
+    /// least one character of stem; otherwise it is dropped. `original`
+    /// is what gets hashed.
+    pub fn fit(
+        &self,
+        name: &str,
+        extension: &str,
+        original: &[u8],
+        replacement: &str,
+    ) -> String {
+        let unit = self.unit;
+        if unit.measure(name) <= self.max {
+            return name.to_string();
+        }
+
+        let ext = match name.len().checked_sub(extension.len() + 1) {
+            Some(dot)
+                if !extension.is_empty()
+                    && dot > 0
+                    && name[dot..].starts_with('.') =>
+            {
+                &name[dot..]
+            }
+            _ => "",
+        };
+        let stem = &name[..name.len() - ext.len()];
+        // Half an extension would look like another file type, so one
+        // that leaves no room for the stem goes entirely.
+        let ext = if unit.measure(ext) >= self.max { "" } else { ext };
+
+        let mut tag = if self.hash {
+            format!("{replacement}{:08x}", fnv1a(original))
+        } else {
+            String::new()
+        };
+        if unit.measure(ext) + unit.measure(&tag) >= self.max {
+            tag.clear();
+        }
+        let reserved = unit.measure(ext) + unit.measure(&tag);
+
+        let cut = unit.prefix(stem, self.max - reserved);
+        // Don't leave a dangling separator, or a trailing dot or space,
+        // where the name was cut.
+        let trimmed = cut.trim_end_matches(|c| {
+            replacement.contains(c) || c == '.' || c == ' '
+        });
+        let stem = if trimmed.is_empty() { cut } else { trimmed };
+        format!("{stem}{tag}{ext}")
+    }
+
+    /// Cut `stem` so that, followed by `rest`, it fits the limit. Used
+    /// to make room for a collision suffix. `stem` is returned whole if
+    /// `rest` alone leaves no room for it.
+    pub fn fit_stem<'a>(
+        &self,
+        stem: &'a str,
+        rest: &str,
+        replacement: &str,
+    ) -> &'a str {
+        let unit = self.unit;
+        let reserved = unit.measure(rest);
+        if unit.measure(stem) + reserved <= self.max || reserved >= self.max {
+            return stem;
+        }
+        let cut = unit.prefix(stem, self.max - reserved);
+        let trimmed = cut.trim_end_matches(|c| {
+            replacement.contains(c) || c == '.' || c == ' '
+        });
+        if trimmed.is_empty() { cut } else { trimmed }
+    }
+}
+
+/// 32-bit FNV-1a. Stable across releases, so the same input always gets
+/// the same truncated name.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &b| {
+        (hash ^ u32::from(b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_names_are_untouched() {
+        let limit = LengthLimit::new(10);
+        assert_eq!(limit.fit("short.txt", "txt", b"", "_"), "short.txt");
+    }
+
+    #[test]
+    fn truncates_stem_and_keeps_extension() {
+        let limit = LengthLimit::new(12);
+        assert_eq!(
+            limit.fit("a_very_long_title.html", "html", b"", "_"),
+            "a_very.html"
+        );
+    }
+
+    #[test]
+    fn cuts_on_character_boundaries() {
+        let limit = LengthLimit::new(9);
+        // Each 'é' is two bytes; a byte limit must not split one.
+        assert_eq!(limit.fit("ééééé.txt", "txt", b"", "_"), "éé.txt");
+
+        let chars = LengthLimit {
+            unit: LengthUnit::Chars,
+            ..LengthLimit::new(6)
+        };
+        assert_eq!(chars.fit("ééééé.txt", "txt", b"", "_"), "éé.txt");
+    }
+
+    #[test]
+    fn hash_keeps_truncated_names_distinct() {
+        let limit = LengthLimit {
+            hash: true,
+            ..LengthLimit::new(20)
+        };
+        let a = limit.fit("chapter_one_part_a.txt", "txt", b"one a", "_");
+        let b = limit.fit("chapter_one_part_b.txt", "txt", b"one b", "_");
+        assert_eq!(a.len(), 20);
+        assert!(a.starts_with("chapter_") && a.ends_with(".txt"));
+        assert_ne!(a, b);
+        assert_eq!(
+            a,
+            limit.fit("chapter_one_part_a.txt", "txt", b"one a", "_")
+        );
+    }
+
+    #[test]
+    fn fit_stem_leaves_room_for_the_rest() {
+        let limit = LengthLimit::new(12);
+        assert_eq!(limit.fit_stem("abcdefgh", "_1.txt", "_"), "abcdef");
+        assert_eq!(limit.fit_stem("abcde_gh", "_1.txt", "_"), "abcde");
+        assert_eq!(limit.fit_stem("abc", "_1.txt", "_"), "abc");
+        assert_eq!(limit.fit_stem("abc", "_1.longextension", "_"), "abc");
+    }
+
+    #[test]
+    fn oversized_extension_is_dropped() {
+        let limit = LengthLimit::new(5);
+        assert_eq!(
+            limit.fit("a.longextension", "longextension", b"", "_"),
+            "a"
+        );
+        assert_eq!(
+            limit.fit("abcd_efg.longextension", "longextension", b"", "_"),
+            "abcd"
+        );
+    }
+}
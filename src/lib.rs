@@ -1,10 +1,49 @@
 use std::env;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
 use crate::color::Palette;
+use crate::config_file::ConfigFiles;
+use crate::journal::Journal;
+use crate::output::OutputFormat;
+use crate::regex::RegexRule;
+use crate::report::{Event, HumanReporter, Reporter};
+use crate::symlinks::LinkFix;
+pub use crate::case::CaseStyle;
+pub use crate::classes::ClassReplacements;
+pub use crate::config_file::Settings;
+pub use crate::extension::{ExtensionCase, ExtensionRules};
+pub use crate::length::{LengthLimit, LengthUnit};
+pub use crate::target::TargetFs;
+pub use crate::regex::RegexRules;
+pub use crate::rules::CharRules;
+pub use crate::symlinks::SymlinkPolicy;
+pub use crate::plan::{
+    PlanError, Planner, RenameKind, RenameOp, RenamePlan, SkipReason,
+};
+
+pub mod case;
+pub mod classes;
+pub mod config_file;
+pub mod extension;
+pub mod glob;
+pub mod ignore;
+pub mod journal;
+pub mod length;
+pub mod output;
+pub mod plan;
+pub mod regex;
+pub mod report;
+pub mod rules;
+pub mod safety;
+pub mod sniff;
+pub mod symlinks;
+pub mod target;
+mod transliterate;
+mod walk;
 
 pub mod color {
     use std::env;
@@ -52,7 +91,7 @@ pub mod color {
 
     // Honors the NO_COLOR convention (https://no-color.org/).
     fn colors_allowed() -> bool {
-        !env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+        env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
     }
 
     pub fn for_stdout() -> Palette {
@@ -72,19 +111,380 @@ pub mod color {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+mod encoding {
+    // Windows-1252 assigns printable characters to most of 0x80..=0x9F,
+    // where ISO-8859-1 has C1 controls. Unassigned bytes fall back to the
+    // corresponding C1 control, matching the WHATWG decoder.
+    const WINDOWS_1252_HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ',
+        '\u{8D}', 'Ž', '\u{8F}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—',
+        '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+    ];
+
+    pub fn decode_latin1(bytes: &[u8]) -> String {
+        bytes.iter().map(|&b| char::from(b)).collect()
+    }
+
+    pub fn decode_windows_1252(bytes: &[u8]) -> String {
+        bytes
+            .iter()
+            .map(|&b| match b {
+                0x80..=0x9F => WINDOWS_1252_HIGH[usize::from(b - 0x80)],
+                _ => char::from(b),
+            })
+            .collect()
+    }
+
+    /// Decode UTF-8, substituting `replacement` for every invalid byte
+    /// sequence instead of U+FFFD.
+    pub fn decode_utf8_replacing(bytes: &[u8], replacement: &str) -> String {
+        let mut out = String::with_capacity(bytes.len());
+        for chunk in bytes.utf8_chunks() {
+            out.push_str(chunk.valid());
+            if !chunk.invalid().is_empty() {
+                out.push_str(replacement);
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SanitizeMode {
     Legacy,
     Full,
+    /// Like `Full`, but first transliterates accented and non-Latin
+    /// letters to ASCII (é -> e, ß -> ss, Ж -> Zh).
+    Transliterate,
+}
+
+impl SanitizeMode {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "legacy" => Ok(Self::Legacy),
+            "full" => Ok(Self::Full),
+            "transliterate" => Ok(Self::Transliterate),
+            _ => Err(format!(
+                "Invalid mode '{s}' (expected legacy, full or transliterate)"
+            )),
+        }
+    }
+}
+
+/// What to do with file names that are not valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonUtf8Policy {
+    /// Leave the name untouched and report it.
+    #[default]
+    Skip,
+    /// Replace each invalid byte sequence with the replacement.
+    Replace,
+    /// Decode the name as ISO-8859-1 (Latin-1).
+    Latin1,
+    /// Decode the name as Windows-1252.
+    Windows1252,
+}
+
+impl NonUtf8Policy {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "replace" => Ok(Self::Replace),
+            "latin1" | "iso-8859-1" => Ok(Self::Latin1),
+            "cp1252" | "windows-1252" => Ok(Self::Windows1252),
+            _ => Err(format!(
+                "Invalid non-UTF-8 policy '{s}' (expected skip, replace, latin1 or cp1252)"
+            )),
+        }
+    }
+
+    /// Decode a raw file name. Valid UTF-8 is always returned as-is;
+    /// `None` means the name is invalid and the policy is `Skip`.
+    fn decode(self, name: &OsStr, replacement: &str) -> Option<String> {
+        if let Some(s) = name.to_str() {
+            return Some(s.to_string());
+        }
+        let bytes = name.as_encoded_bytes();
+        match self {
+            Self::Skip => None,
+            Self::Replace => {
+                Some(encoding::decode_utf8_replacing(bytes, replacement))
+            }
+            Self::Latin1 => Some(encoding::decode_latin1(bytes)),
+            Self::Windows1252 => Some(encoding::decode_windows_1252(bytes)),
+        }
+    }
+}
+
+/// What to do when the sanitized name is already taken, either on disk
+/// or by an earlier rename in the same run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Leave the file alone and report it.
+    #[default]
+    Skip,
+    /// Append `_1`, `_2`, ... (using the replacement character) before
+    /// the extension until the name is free.
+    Suffix,
+    /// Rename anyway, replacing whatever is there.
+    Overwrite,
+    /// Stop with an error.
+    Fail,
+}
+
+impl ConflictPolicy {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "suffix" => Ok(Self::Suffix),
+            "overwrite" => Ok(Self::Overwrite),
+            "fail" => Ok(Self::Fail),
+            _ => Err(format!(
+                "Invalid conflict policy '{s}' (expected skip, suffix, overwrite or fail)"
+            )),
+        }
+    }
+}
+
+/// The order recursion visits the entries of a directory in, which also
+/// decides the order of renames and which one gets a collision suffix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    /// By name, so that runs are reproducible.
+    #[default]
+    Name,
+    /// In whatever order the filesystem lists them.
+    None,
+}
+
+impl SortOrder {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "name" => Ok(Self::Name),
+            "none" => Ok(Self::None),
+            _ => Err(format!(
+                "Invalid sort order '{s}' (expected name or none)"
+            )),
+        }
+    }
+}
+
+/// Which kinds of entries are renamed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EntryFilter {
+    #[default]
+    All,
+    DirsOnly,
+    FilesOnly,
+}
+
+impl EntryFilter {
+    /// Whether an entry is renamed; symlinks count as files.
+    pub fn allows(self, is_dir: bool) -> bool {
+        match self {
+            Self::All => true,
+            Self::DirsOnly => is_dir,
+            Self::FilesOnly => !is_dir,
+        }
+    }
+}
+
+/// Everything that decides what a single name is sanitized to.
+#[derive(Debug, Clone)]
+pub struct SanitizeOptions {
+    /// Stands in for every run of characters the mode rejects. May be
+    /// several characters long, or empty to delete them.
+    pub replacement: String,
+    /// Replacements for spaces, punctuation, brackets and quotes that
+    /// override `replacement`.
+    pub classes: ClassReplacements,
+    pub mode: SanitizeMode,
+    /// User-defined mappings, applied before the mode's.
+    pub rules: CharRules,
+    /// `--regex` and `--regex-post` rules, applied to the stem before and
+    /// after the mapping.
+    pub regex: RegexRules,
+    pub non_utf8: NonUtf8Policy,
+    /// Applied to the stem; `extensions.case` covers the extension.
+    pub case: CaseStyle,
+    pub extensions: ExtensionRules,
+    /// Add or correct extensions to match the file's content.
+    pub fix_extensions: bool,
+    /// Filesystem whose naming rules the result must also satisfy.
+    pub target_fs: Option<TargetFs>,
+    pub max_length: Option<LengthLimit>,
+    /// Renames whose new absolute path is longer than this many bytes
+    /// are skipped.
+    pub max_path_length: Option<usize>,
+    /// `.gitignore`-style patterns for entries that recursion skips,
+    /// along with everything below them.
+    pub exclude: Vec<String>,
+    /// If not empty, recursion only renames entries whose names match
+    /// one of these glob patterns. Directories are entered either way.
+    pub include: Vec<String>,
+    /// Also read `.gitignore` files while recursing.
+    pub gitignore: bool,
+    /// Recursion renames nothing shallower than this; a target is at
+    /// depth 0.
+    pub min_depth: usize,
+    /// Recursion enters no directory at this depth or deeper.
+    pub max_depth: Option<usize>,
+    pub sort: SortOrder,
+    pub entries: EntryFilter,
+    pub symlinks: SymlinkPolicy,
+    /// Recursion does not enter directories on other filesystems.
+    pub one_file_system: bool,
+    /// Links may lead recursion into protected roots such as `$HOME`.
+    pub force: bool,
+    /// Threads that scan a tree; 0 for one per CPU.
+    pub jobs: usize,
+}
+
+impl SanitizeOptions {
+    pub fn new(replacement: &str, mode: SanitizeMode) -> Self {
+        Self {
+            replacement: replacement.to_string(),
+            classes: ClassReplacements::default(),
+            mode,
+            rules: CharRules::default(),
+            regex: RegexRules::default(),
+            non_utf8: NonUtf8Policy::default(),
+            case: CaseStyle::default(),
+            extensions: ExtensionRules::default(),
+            fix_extensions: false,
+            target_fs: None,
+            max_length: None,
+            max_path_length: None,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            gitignore: false,
+            min_depth: 0,
+            max_depth: None,
+            sort: SortOrder::default(),
+            entries: EntryFilter::default(),
+            symlinks: SymlinkPolicy::default(),
+            one_file_system: false,
+            force: false,
+            jobs: 1,
+        }
+    }
+
+    /// Whether recursion renames the entry `name`.
+    pub fn includes(&self, name: &OsStr) -> bool {
+        let name = name.to_string_lossy();
+        self.include.is_empty()
+            || self.include.iter().any(|pattern| glob::matches(pattern, &name))
+    }
+
+    /// Whether recursion renames `path`, found at `depth`. Targets are
+    /// not subject to `include`.
+    pub(crate) fn renames(
+        &self,
+        path: &Path,
+        depth: usize,
+        is_dir: bool,
+    ) -> bool {
+        depth >= self.min_depth
+            && self.entries.allows(is_dir)
+            && (depth == 0
+                || path.file_name().is_none_or(|name| self.includes(name)))
+    }
 }
 
+/// `replacement`, `full_sanitize`, `transliterate` and `case` are
+/// defaults that config files may override; `settings` holds the values
+/// given on the command line, which they may not.
 #[derive(Debug, Clone)]
 pub struct Config {
     pub recursive: bool,
     pub dry_run: bool,
-    pub replacement: char,
-    pub targets: Vec<String>,
+    pub replacement: String,
+    pub targets: Vec<OsString>,
+    /// Also read targets from this file, or standard input for `-`.
+    pub from_file: Option<PathBuf>,
+    /// Targets in `from_file` are separated by NUL bytes, not newlines.
+    pub null: bool,
     pub full_sanitize: bool,
+    pub transliterate: bool,
+    pub non_utf8: NonUtf8Policy,
+    pub on_conflict: ConflictPolicy,
+    /// Record performed renames in this file.
+    pub journal: Option<PathBuf>,
+    /// Instead of sanitizing, revert the renames recorded in this journal.
+    pub undo: Option<PathBuf>,
+    pub format: OutputFormat,
+    pub case: CaseStyle,
+    pub extensions: ExtensionRules,
+    pub fix_extensions: bool,
+    pub target_fs: Option<TargetFs>,
+    pub max_length: Option<usize>,
+    pub length_unit: LengthUnit,
+    pub truncate_hash: bool,
+    pub max_path_length: Option<usize>,
+    pub min_depth: usize,
+    pub max_depth: Option<usize>,
+    pub sort: SortOrder,
+    pub entries: EntryFilter,
+    pub symlinks: SymlinkPolicy,
+    /// After renaming, repoint relative symlinks at their targets' new
+    /// names.
+    pub fix_symlinks: bool,
+    pub one_file_system: bool,
+    pub jobs: usize,
+    /// Operate on `/`, `$HOME` and other protected directories too.
+    pub force: bool,
+    /// `[profile.NAME]` to apply from config files.
+    pub profile: Option<String>,
+    /// The user's config file, read before any project file.
+    pub user_config: Option<PathBuf>,
+    /// Read the nearest `.sanitize_filenames.toml` above each target.
+    ///
+    /// Both are off for a `Config` built in code, which therefore does
+    /// only what it says; `parse_args` turns them on unless
+    /// `--no-config` is given.
+    pub project_config: bool,
+    pub settings: Settings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            recursive: false,
+            dry_run: false,
+            replacement: "_".to_string(),
+            targets: Vec::new(),
+            from_file: None,
+            null: false,
+            full_sanitize: false,
+            transliterate: false,
+            non_utf8: NonUtf8Policy::default(),
+            on_conflict: ConflictPolicy::default(),
+            journal: None,
+            undo: None,
+            format: OutputFormat::default(),
+            case: CaseStyle::default(),
+            extensions: ExtensionRules::default(),
+            fix_extensions: false,
+            target_fs: None,
+            max_length: None,
+            length_unit: LengthUnit::default(),
+            truncate_hash: false,
+            max_path_length: None,
+            min_depth: 0,
+            max_depth: None,
+            sort: SortOrder::default(),
+            entries: EntryFilter::default(),
+            symlinks: SymlinkPolicy::default(),
+            fix_symlinks: false,
+            one_file_system: false,
+            jobs: 1,
+            force: false,
+            profile: None,
+            user_config: None,
+            project_config: false,
+            settings: Settings::default(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -109,7 +509,43 @@ pub fn print_usage(mut w: impl Write, p: Palette) -> io::Result<()> {
     )?;
     writeln!(
         w,
-        "  {bold}{green}-c{reset}, {bold}{green}--replacement{reset} {cyan}CHAR{reset} Replacement character to use (default: {cyan}_{reset})"
+        "  {bold}{green}-c{reset}, {bold}{green}--replacement{reset} {cyan}STR{reset}  Replace rejected characters with {cyan}STR{reset} (default: {cyan}_{reset});"
+    )?;
+    writeln!(
+        w,
+        "                          may be longer than one character, or empty to delete them"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--space-replacement{reset} {cyan}STR{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          Replace whitespace with {cyan}STR{reset} instead"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--punct-replacement{reset} {cyan}STR{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          Replace punctuation with {cyan}STR{reset} instead"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--bracket-replacement{reset} {cyan}STR{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          Replace brackets with {cyan}STR{reset} instead"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--quote-replacement{reset} {cyan}STR{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          Replace quotes with {cyan}STR{reset} instead"
     )?;
     writeln!(
         w,
@@ -121,1352 +557,4582 @@ pub fn print_usage(mut w: impl Write, p: Palette) -> io::Result<()> {
     )?;
     writeln!(
         w,
-        "  {bold}{green}-h{reset}, {bold}{green}--help{reset}             Show this help message and exit"
+        "  {bold}{green}-T{reset}, {bold}{green}--transliterate{reset}    Like {bold}{green}--full-sanitize{reset}, but first convert accented and"
     )?;
-    writeln!(w)?;
     writeln!(
         w,
-        "Provide one or more files or directories to sanitize their names in-place."
+        "                          non-Latin letters to ASCII (e.g. 'é' -> 'e', 'Ж' -> 'Zh')"
     )?;
     writeln!(
         w,
-        "Use '{cyan}--{reset}' to stop option parsing when filenames begin with '-'."
+        "      {bold}{green}--map{reset} {cyan}FROM=TO{reset}      Replace {cyan}FROM{reset} with {cyan}TO{reset} first, e.g. {cyan}'&=and'{reset}"
     )?;
-    writeln!(w)?;
-    writeln!(w, "{bold}{yellow}Examples:{reset}")?;
     writeln!(
         w,
-        "  {dim}# sanitize a single file in the current directory{reset}"
+        "      {bold}{green}--keep{reset} {cyan}CHARS{reset}       Never replace any of {cyan}CHARS{reset}"
     )?;
-    writeln!(w, "  {green}sanitize_filenames \"My File.txt\"{reset}")?;
-    writeln!(w)?;
-    writeln!(w, "  {dim}# preview changes without renaming{reset}")?;
     writeln!(
         w,
-        "  {green}sanitize_filenames --dry-run \"My File.txt\"{reset}"
+        "      {bold}{green}--strip{reset} {cyan}CHARS{reset}      Delete every one of {cyan}CHARS{reset}"
     )?;
-    writeln!(w)?;
     writeln!(
         w,
-        "  {dim}# sanitize recursively and use '-' as the separator{reset}"
+        "      {bold}{green}--rules{reset} {cyan}FILE{reset}       Read {green}--map{reset}, {green}--keep{reset} and {green}--strip{reset} rules from {cyan}FILE{reset}"
     )?;
     writeln!(
         w,
-        "  {green}sanitize_filenames --recursive --replacement - ~/Downloads{reset}"
+        "      {bold}{green}--regex{reset} {cyan}PAT=REPL{reset}   Rewrite the stem with a regular expression before"
     )?;
-    writeln!(w)?;
     writeln!(
         w,
-        "  {dim}# sanitize a file whose name starts with a dash{reset}"
+        "                          the mapping; {cyan}$1{reset} in {cyan}REPL{reset} inserts group 1"
     )?;
-    writeln!(w, "  {green}sanitize_filenames -- --weird name.mp3{reset}")?;
-    Ok(())
-}
-
-fn validate_replacement(s: &str) -> Result<char, String> {
-    if s.is_empty() {
-        return Err("Replacement character cannot be empty".to_string());
-    }
-
-    let mut chars = s.chars();
-    let ch = chars
-        .next()
-        .ok_or_else(|| "Replacement character cannot be empty".to_string())?;
-    if chars.next().is_some() {
-        return Err("Replacement character must be a single character".to_string());
-    }
-
-    // Mirror the Ruby script: disallow the path separator.
-    let illegal = ['/'];
-    if illegal.contains(&ch) {
-        return Err(format!("Replacement character '{}' is not allowed", ch));
-    }
-
-    Ok(ch)
-}
-
-pub fn parse_args(args: &[String]) -> Result<Config, CliError> {
-    let mut recursive = false;
-    let mut dry_run = false;
-    let mut replacement = '_';
-    let mut full_sanitize = false;
-    let mut targets: Vec<String> = Vec::new();
-
-    let mut i = 0;
-    let mut end_of_opts = false;
-
-    while i < args.len() {
-        let arg = &args[i];
-
-        if end_of_opts {
-            if arg != "." && arg != ".." {
-                targets.push(arg.clone());
-            }
-            i += 1;
-            continue;
-        }
-
-        if arg == "--" {
-            end_of_opts = true;
-            i += 1;
-            continue;
-        }
-
-        if !arg.starts_with('-') || arg == "-" {
-            if arg != "." && arg != ".." {
-                targets.push(arg.clone());
-            }
-            i += 1;
-            continue;
-        }
-
-        match arg.as_str() {
-            "-h" | "--help" => {
-                return Err(CliError::Help);
-            }
-            "-r" | "--recursive" => {
-                recursive = true;
-                i += 1;
-            }
-            "-n" | "--dry-run" => {
-                dry_run = true;
-                i += 1;
-            }
-            "-F" | "--full-sanitize" => {
-                full_sanitize = true;
-                i += 1;
-            }
-            "-c" => {
-                let value = args.get(i + 1).ok_or_else(|| {
-                    CliError::Message("Option '-c' requires an argument".to_string())
-                })?;
-                replacement =
-                    validate_replacement(value).map_err(CliError::Message)?;
-                i += 2;
-            }
-            "--replacement" => {
-                let value = args.get(i + 1).ok_or_else(|| {
-                    CliError::Message(
-                        "Option '--replacement' requires an argument".to_string(),
-                    )
-                })?;
-                replacement =
-                    validate_replacement(value).map_err(CliError::Message)?;
-                i += 2;
-            }
-            _ => {
-                if let Some(rest) = arg.strip_prefix("-c") {
-                    if rest.is_empty() {
-                        return Err(CliError::Message(
-                            "Option '-c' requires an argument".to_string(),
-                        ));
-                    }
-                    replacement = validate_replacement(rest)
-                        .map_err(CliError::Message)?;
-                    i += 1;
-                } else if let Some(rest) = arg.strip_prefix("--replacement=") {
-                    if rest.is_empty() {
-                        return Err(CliError::Message(
-                            "Option '--replacement' requires an argument"
-                                .to_string(),
-                        ));
-                    }
-                    replacement = validate_replacement(rest)
-                        .map_err(CliError::Message)?;
-                    i += 1;
-                } else {
-                    return Err(CliError::Message(format!(
-                        "Unknown option: {arg}"
-                    )));
-                }
-            }
-        }
-    }
-
-    Ok(Config {
-        recursive,
-        dry_run,
-        replacement,
-        targets,
-        full_sanitize,
-    })
-}
-
-fn has_dot(name: &str) -> bool {
-    name.split('.').count() > 1
-}
-
-fn is_hidden(name: &str) -> bool {
-    name.starts_with('.')
-}
-
-fn is_directory(path_str: &str) -> bool {
-    Path::new(path_str).is_dir()
-}
-
-fn has_extension(path_str: &str) -> bool {
-    has_dot(path_str) && !is_directory(path_str) && !is_hidden(path_str)
-}
-
-fn extract_extension(path_str: &str) -> String {
-    if has_extension(path_str) {
-        match path_str.rsplit('.').next() {
-            Some(ext) => ext.to_string(),
-            None => String::new(),
-        }
-    } else {
-        String::new()
-    }
-}
-
-fn sanitize_component(
-    name: &str,
-    replacement: char,
-    extension: &str,
-    mode: SanitizeMode,
-) -> String {
-    // First pass: map characters according to the selected mode.
-    let mut tmp = String::with_capacity(name.len());
-    for ch in name.chars() {
-        let mapped = match mode {
-            SanitizeMode::Legacy => match ch {
-                '×' => 'x',
-                c if c.is_whitespace()
-                    || matches!(
-                        c,
-                        '.' | ',' | '"' | ':' | '?' | '\'' | '#'
-                            | ';' | '&' | '*' | '\\'
-                    ) =>
-                {
-                    replacement
-                }
-                '(' | ')' | '[' | ']' => replacement,
-                _ => ch,
-            },
-            SanitizeMode::Full => {
-                if ch.is_ascii_alphanumeric() || ch == '_' || ch == '-' {
-                    ch
-                } else {
-                    replacement
-                }
-            }
-        };
-        tmp.push(mapped);
-    }
-
-    // Collapse multiple replacement characters into one.
-    let mut collapsed = String::with_capacity(tmp.len());
-    let mut prev_was_repl = false;
-    for ch in tmp.chars() {
-        if ch == replacement {
-            if !prev_was_repl {
-                collapsed.push(ch);
-                prev_was_repl = true;
-            }
-        } else {
-            collapsed.push(ch);
-            prev_was_repl = false;
-        }
+    writeln!(
+        w,
+        "      {bold}{green}--regex-post{reset} {cyan}PAT=REPL{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          Like {green}--regex{reset}, but after the mapping"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--non-utf8{reset} {cyan}POLICY{reset}  How to handle names that are not valid UTF-8:"
+    )?;
+    writeln!(
+        w,
+        "                          {cyan}skip{reset} (default), {cyan}replace{reset}, {cyan}latin1{reset} or {cyan}cp1252{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--on-conflict{reset} {cyan}POLICY{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          What to do when the new name is already taken:"
+    )?;
+    writeln!(
+        w,
+        "                          {cyan}skip{reset} (default), {cyan}suffix{reset}, {cyan}overwrite{reset} or {cyan}fail{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--journal{reset} {cyan}FILE{reset}     Record renames in {cyan}FILE{reset} so they can be undone"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--auto-journal{reset}     Record renames in a new journal under"
+    )?;
+    writeln!(
+        w,
+        "                         {cyan}$XDG_STATE_HOME/sanitize_filenames/{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--undo{reset} {cyan}JOURNAL{reset}     Revert the renames recorded in {cyan}JOURNAL{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--format{reset} {cyan}FORMAT{reset}    Output format: {cyan}human{reset} (default), {cyan}json{reset}, {cyan}ndjson{reset} or {cyan}tsv{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--case{reset} {cyan}STYLE{reset}       Change the case of names: {cyan}preserve{reset} (default), {cyan}lower{reset},"
+    )?;
+    writeln!(
+        w,
+        "                          {cyan}upper{reset}, {cyan}snake{reset}, {cyan}kebab{reset}, {cyan}camel{reset} or {cyan}title{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--keep-extensions{reset} {cyan}N{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          Treat up to {cyan}N{reset} trailing dot-segments as the extension (default 1)"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--compound-ext{reset} {cyan}LIST{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          Extra multi-part extensions to keep whole, e.g. {cyan}tar.zst,d.ts{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--ext-case{reset} {cyan}CASE{reset}    Case of extensions: {cyan}preserve{reset} (default), {cyan}lower{reset} or {cyan}upper{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--ext-alias{reset} {cyan}LIST{reset}   Rename extensions, e.g. {cyan}jpeg=jpg,htm=html{reset}; {cyan}common{reset} adds"
+    )?;
+    writeln!(
+        w,
+        "                          jpeg, jpe, tiff, htm and mpeg"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--fix-extensions{reset}   Add or correct extensions to match file contents"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--target{reset} {cyan}FS{reset}        Also enforce the naming rules of {cyan}FS{reset}: {cyan}windows{reset}, {cyan}macos{reset},"
+    )?;
+    writeln!(
+        w,
+        "                          {cyan}fat32{reset}, {cyan}posix{reset} or {cyan}s3{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--max-length{reset} {cyan}N{reset}     Truncate names longer than {cyan}N{reset}, keeping the extension"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--length-unit{reset} {cyan}UNIT{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          Count {cyan}bytes{reset} (default) or {cyan}chars{reset} for {green}--max-length{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--truncate-hash{reset}    Add a short hash of the original name to truncated names"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--max-path-length{reset} {cyan}N{reset}"
+    )?;
+    writeln!(
+        w,
+        "                          Skip renames whose full path would exceed {cyan}N{reset} bytes"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--profile{reset} {cyan}NAME{reset}     Use {cyan}[profile.NAME]{reset} from the config files"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--no-config{reset}        Ignore config files"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--exclude{reset} {cyan}GLOB{reset}     When recursing, skip entries matching {cyan}GLOB{reset} and"
+    )?;
+    writeln!(
+        w,
+        "                          everything below them (as in {cyan}.gitignore{reset})"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--include{reset} {cyan}GLOB{reset}     When recursing, only rename entries matching {cyan}GLOB{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--gitignore{reset}        Also honour {cyan}.gitignore{reset} files, not just {cyan}.sanitizeignore{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--max-depth{reset} {cyan}N{reset}      When recursing, go at most {cyan}N{reset} levels below each target"
+    )?;
+    writeln!(
+        w,
+        "                          (0 renames only the targets themselves)"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--min-depth{reset} {cyan}N{reset}      When recursing, only rename entries at least {cyan}N{reset} levels down"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--sort{reset} {cyan}ORDER{reset}       Visit entries by {cyan}name{reset} (default) or as listed ({cyan}none{reset})"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--dirs-only{reset}        Only rename directories"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--files-only{reset}       Only rename files (and symlinks)"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--from-file{reset} {cyan}FILE{reset}   Also sanitize the paths listed in {cyan}FILE{reset}, one per line;"
+    )?;
+    writeln!(
+        w,
+        "                          {cyan}-{reset} reads them from standard input"
+    )?;
+    writeln!(
+        w,
+        "  {bold}{green}-0{reset}, {bold}{green}--null{reset}             Paths in the list are separated by NUL bytes, as"
+    )?;
+    writeln!(
+        w,
+        "                          printed by {cyan}find -print0{reset}"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--jobs{reset} {cyan}N{reset}           Scan directory trees with {cyan}N{reset} threads (0: one per CPU);"
+    )?;
+    writeln!(
+        w,
+        "                          the renames and output stay in the same order"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--symlinks{reset} {cyan}POLICY{reset}  What to do with symlinks: {cyan}skip{reset}, {cyan}rename{reset} (default)"
+    )?;
+    writeln!(
+        w,
+        "                          or {cyan}follow{reset} (rename, and enter links to directories)"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--fix-symlinks{reset}     Repoint relative symlinks whose targets were renamed"
+    )?;
+    writeln!(
+        w,
+        "  {bold}{green}-x{reset}, {bold}{green}--one-file-system{reset}  When recursing, do not enter other filesystems"
+    )?;
+    writeln!(
+        w,
+        "      {bold}{green}--force{reset}            Allow operating on {cyan}/{reset}, {cyan}$HOME{reset} and system directories"
+    )?;
+    writeln!(
+        w,
+        "  {bold}{green}-h{reset}, {bold}{green}--help{reset}             Show this help message and exit"
+    )?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "Provide one or more files or directories to sanitize their names in-place."
+    )?;
+    writeln!(
+        w,
+        "Use '{cyan}--{reset}' to stop option parsing when filenames begin with '-'."
+    )?;
+    writeln!(w)?;
+    writeln!(w, "{bold}{yellow}Examples:{reset}")?;
+    writeln!(
+        w,
+        "  {dim}# sanitize a single file in the current directory{reset}"
+    )?;
+    writeln!(w, "  {green}sanitize_filenames \"My File.txt\"{reset}")?;
+    writeln!(w)?;
+    writeln!(w, "  {dim}# preview changes without renaming{reset}")?;
+    writeln!(
+        w,
+        "  {green}sanitize_filenames --dry-run \"My File.txt\"{reset}"
+    )?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "  {dim}# sanitize recursively and use '-' as the separator{reset}"
+    )?;
+    writeln!(
+        w,
+        "  {green}sanitize_filenames --recursive --replacement - ~/Downloads{reset}"
+    )?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "  {dim}# sanitize a file whose name starts with a dash{reset}"
+    )?;
+    writeln!(w, "  {green}sanitize_filenames -- --weird name.mp3{reset}")?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "  {dim}# sanitize every file that find turns up, whatever its name{reset}"
+    )?;
+    writeln!(
+        w,
+        "  {green}find . -name '*.mp3' -print0 | sanitize_filenames --from-file - -0{reset}"
+    )?;
+    Ok(())
+}
+
+/// Check a replacement. Any string is allowed, including an empty one,
+/// as long as it cannot split or end a name.
+fn validate_replacement(s: &str) -> Result<String, String> {
+    // Mirror the Ruby script: disallow the path separator.
+    let illegal = ['/', '\0'];
+    if let Some(ch) = s.chars().find(|ch| illegal.contains(ch)) {
+        return Err(format!(
+            "Replacement character '{}' is not allowed",
+            ch.escape_default()
+        ));
+    }
+    Ok(s.to_string())
+}
+
+/// What stands in for a name, or a part of one, that must not end up
+/// empty: the replacement, or `_` if that is empty or would name the
+/// current or parent directory.
+pub(crate) fn filler(replacement: &str) -> &str {
+    if matches!(replacement, "" | "." | "..") {
+        "_"
+    } else {
+        replacement
+    }
+}
+
+fn parse_count(name: &str, value: &str) -> Result<usize, String> {
+    value.parse::<usize>().map_err(|_| {
+        format!("Option '{name}' requires a number, got '{value}'")
+    })
+}
+
+fn parse_length(name: &str, value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "Option '{name}' requires a positive number, got '{value}'"
+        )),
+    }
+}
+
+/// Fetch the value following option `name` at `args[i]`.
+fn option_value<'a, S: AsRef<OsStr>>(
+    args: &'a [S],
+    i: usize,
+    name: &str,
+) -> Result<&'a str, CliError> {
+    let value = args.get(i + 1).ok_or_else(|| {
+        CliError::Message(format!("Option '{name}' requires an argument"))
+    })?;
+    value.as_ref().to_str().ok_or_else(|| {
+        CliError::Message(format!("Option '{name}' requires a UTF-8 argument"))
+    })
+}
+
+pub fn parse_args<S: AsRef<OsStr>>(args: &[S]) -> Result<Config, CliError> {
+    let mut recursive = false;
+    let mut dry_run = false;
+    let mut replacement: Option<String> = None;
+    let mut full_sanitize = false;
+    let mut transliterate = false;
+    let mut non_utf8 = NonUtf8Policy::default();
+    let mut on_conflict = ConflictPolicy::default();
+    let mut journal: Option<PathBuf> = None;
+    let mut undo: Option<PathBuf> = None;
+    let mut format = OutputFormat::default();
+    let mut case: Option<CaseStyle> = None;
+    let mut extensions = ExtensionRules::default();
+    let mut fix_extensions = false;
+    let mut target_fs: Option<TargetFs> = None;
+    let mut max_length: Option<usize> = None;
+    let mut length_unit = LengthUnit::default();
+    let mut truncate_hash = false;
+    let mut max_path_length: Option<usize> = None;
+    let mut rules = CharRules::default();
+    let mut regex = RegexRules::default();
+    let mut classes = ClassReplacements::default();
+    let mut profile: Option<String> = None;
+    let mut no_config = false;
+    let mut exclude: Vec<String> = Vec::new();
+    let mut include: Vec<String> = Vec::new();
+    let mut gitignore: Option<bool> = None;
+    let mut min_depth = 0;
+    let mut max_depth: Option<usize> = None;
+    let mut sort = SortOrder::default();
+    let mut entries = EntryFilter::default();
+    let mut symlinks = SymlinkPolicy::default();
+    let mut fix_symlinks = false;
+    let mut one_file_system = false;
+    let mut jobs = 1;
+    let mut force = false;
+    let mut from_file: Option<PathBuf> = None;
+    let mut null = false;
+    let mut targets: Vec<OsString> = Vec::new();
+
+    let mut i = 0;
+    let mut end_of_opts = false;
+
+    while i < args.len() {
+        let arg_os = args[i].as_ref();
+
+        if end_of_opts {
+            if arg_os != "." && arg_os != ".." {
+                targets.push(arg_os.to_os_string());
+            }
+            i += 1;
+            continue;
+        }
+
+        if arg_os == "--" {
+            end_of_opts = true;
+            i += 1;
+            continue;
+        }
+
+        // Options are always ASCII, so anything that is not valid UTF-8
+        // and does not start with '-' can only be a target.
+        let arg = match arg_os.to_str() {
+            Some(arg) => arg,
+            None if !arg_os.as_encoded_bytes().starts_with(b"-") => {
+                targets.push(arg_os.to_os_string());
+                i += 1;
+                continue;
+            }
+            None => {
+                return Err(CliError::Message(format!(
+                    "Unknown option: {}",
+                    arg_os.to_string_lossy()
+                )));
+            }
+        };
+
+        if !arg.starts_with('-') || arg == "-" {
+            if arg != "." && arg != ".." {
+                targets.push(arg_os.to_os_string());
+            }
+            i += 1;
+            continue;
+        }
+
+        match arg {
+            "-h" | "--help" => {
+                return Err(CliError::Help);
+            }
+            "-r" | "--recursive" => {
+                recursive = true;
+                i += 1;
+            }
+            "-n" | "--dry-run" => {
+                dry_run = true;
+                i += 1;
+            }
+            "-F" | "--full-sanitize" => {
+                full_sanitize = true;
+                i += 1;
+            }
+            "-T" | "--transliterate" => {
+                transliterate = true;
+                i += 1;
+            }
+            "-c" | "--replacement" => {
+                let value = option_value(args, i, arg)?;
+                replacement = Some(
+                    validate_replacement(value).map_err(CliError::Message)?,
+                );
+                i += 2;
+            }
+            "--non-utf8" => {
+                let value = option_value(args, i, arg)?;
+                non_utf8 =
+                    NonUtf8Policy::parse(value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--on-conflict" => {
+                let value = option_value(args, i, arg)?;
+                on_conflict =
+                    ConflictPolicy::parse(value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--journal" => {
+                let value = args.get(i + 1).ok_or_else(|| {
+                    CliError::Message(
+                        "Option '--journal' requires an argument".to_string(),
+                    )
+                })?;
+                if value.as_ref().is_empty() {
+                    return Err(CliError::Message(
+                        "Option '--journal' requires a file name".to_string(),
+                    ));
+                }
+                journal = Some(PathBuf::from(value.as_ref()));
+                i += 2;
+            }
+            "--auto-journal" => {
+                journal = Some(journal::default_journal_path().ok_or_else(|| {
+                    CliError::Message(
+                        "Cannot determine a default journal location; use --journal FILE"
+                            .to_string(),
+                    )
+                })?);
+                i += 1;
+            }
+            "--undo" => {
+                let value = args.get(i + 1).ok_or_else(|| {
+                    CliError::Message(
+                        "Option '--undo' requires an argument".to_string(),
+                    )
+                })?;
+                if value.as_ref().is_empty() {
+                    return Err(CliError::Message(
+                        "Option '--undo' requires a file name".to_string(),
+                    ));
+                }
+                undo = Some(PathBuf::from(value.as_ref()));
+                i += 2;
+            }
+            "--format" => {
+                let value = option_value(args, i, arg)?;
+                format = OutputFormat::parse(value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--case" => {
+                let value = option_value(args, i, arg)?;
+                case =
+                    Some(CaseStyle::parse(value).map_err(CliError::Message)?);
+                i += 2;
+            }
+            "--keep-extensions" => {
+                let value = option_value(args, i, arg)?;
+                extensions.max_parts =
+                    parse_count(arg, value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--compound-ext" => {
+                let value = option_value(args, i, arg)?;
+                extensions.add_compound(value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--ext-case" => {
+                let value = option_value(args, i, arg)?;
+                extensions.case =
+                    ExtensionCase::parse(value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--ext-alias" => {
+                let value = option_value(args, i, arg)?;
+                extensions.add_aliases(value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--map" | "--keep" | "--strip" | "--rules" => {
+                let value = option_value(args, i, arg)?;
+                add_rule(&mut rules, arg, value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--regex" | "--regex-post" => {
+                let value = option_value(args, i, arg)?;
+                add_regex(&mut regex, arg, value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--space-replacement"
+            | "--punct-replacement"
+            | "--bracket-replacement"
+            | "--quote-replacement" => {
+                let value = option_value(args, i, arg)?;
+                set_class_replacement(&mut classes, arg, value)
+                    .map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--profile" => {
+                profile = Some(option_value(args, i, arg)?.to_string());
+                i += 2;
+            }
+            "--no-config" => {
+                no_config = true;
+                i += 1;
+            }
+            "--exclude" => {
+                exclude.push(option_value(args, i, arg)?.to_string());
+                i += 2;
+            }
+            "--include" => {
+                include.push(option_value(args, i, arg)?.to_string());
+                i += 2;
+            }
+            "--gitignore" => {
+                gitignore = Some(true);
+                i += 1;
+            }
+            "--min-depth" | "--max-depth" => {
+                let value = option_value(args, i, arg)?;
+                let n = parse_count(arg, value).map_err(CliError::Message)?;
+                if arg == "--min-depth" {
+                    min_depth = n;
+                } else {
+                    max_depth = Some(n);
+                }
+                i += 2;
+            }
+            "--sort" => {
+                let value = option_value(args, i, arg)?;
+                sort = SortOrder::parse(value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--dirs-only" | "--files-only" => {
+                let filter = if arg == "--dirs-only" {
+                    EntryFilter::DirsOnly
+                } else {
+                    EntryFilter::FilesOnly
+                };
+                if entries != EntryFilter::All && entries != filter {
+                    return Err(CliError::Message(
+                        "Options '--dirs-only' and '--files-only' cannot be combined"
+                            .to_string(),
+                    ));
+                }
+                entries = filter;
+                i += 1;
+            }
+            "--symlinks" => {
+                let value = option_value(args, i, arg)?;
+                symlinks =
+                    SymlinkPolicy::parse(value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--fix-symlinks" => {
+                fix_symlinks = true;
+                i += 1;
+            }
+            "-x" | "--one-file-system" => {
+                one_file_system = true;
+                i += 1;
+            }
+            "--force" => {
+                force = true;
+                i += 1;
+            }
+            "--from-file" => {
+                let value = args.get(i + 1).ok_or_else(|| {
+                    CliError::Message(
+                        "Option '--from-file' requires an argument".to_string(),
+                    )
+                })?;
+                from_file = Some(PathBuf::from(value.as_ref()));
+                i += 2;
+            }
+            "-0" | "--null" => {
+                null = true;
+                i += 1;
+            }
+            "--jobs" => {
+                let value = option_value(args, i, arg)?;
+                jobs = parse_count(arg, value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--fix-extensions" => {
+                fix_extensions = true;
+                i += 1;
+            }
+            "--max-length" | "--max-path-length" => {
+                let value = option_value(args, i, arg)?;
+                let n = parse_length(arg, value).map_err(CliError::Message)?;
+                if arg == "--max-length" {
+                    max_length = Some(n);
+                } else {
+                    max_path_length = Some(n);
+                }
+                i += 2;
+            }
+            "--length-unit" => {
+                let value = option_value(args, i, arg)?;
+                length_unit =
+                    LengthUnit::parse(value).map_err(CliError::Message)?;
+                i += 2;
+            }
+            "--truncate-hash" => {
+                truncate_hash = true;
+                i += 1;
+            }
+            "--target" => {
+                let value = option_value(args, i, arg)?;
+                target_fs =
+                    Some(TargetFs::parse(value).map_err(CliError::Message)?);
+                i += 2;
+            }
+            _ => {
+                if let Some(rest) = arg.strip_prefix("-c") {
+                    if rest.is_empty() {
+                        return Err(CliError::Message(
+                            "Option '-c' requires an argument".to_string(),
+                        ));
+                    }
+                    replacement = Some(
+                        validate_replacement(rest).map_err(CliError::Message)?,
+                    );
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--replacement=") {
+                    replacement = Some(
+                        validate_replacement(rest).map_err(CliError::Message)?,
+                    );
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--non-utf8=") {
+                    non_utf8 = NonUtf8Policy::parse(rest)
+                        .map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--on-conflict=") {
+                    on_conflict = ConflictPolicy::parse(rest)
+                        .map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--journal=") {
+                    if rest.is_empty() {
+                        return Err(CliError::Message(
+                            "Option '--journal=' requires a file name"
+                                .to_string(),
+                        ));
+                    }
+                    journal = Some(PathBuf::from(rest));
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--undo=") {
+                    if rest.is_empty() {
+                        return Err(CliError::Message(
+                            "Option '--undo=' requires a file name".to_string(),
+                        ));
+                    }
+                    undo = Some(PathBuf::from(rest));
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--format=") {
+                    format =
+                        OutputFormat::parse(rest).map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--case=") {
+                    case = Some(
+                        CaseStyle::parse(rest).map_err(CliError::Message)?,
+                    );
+                    i += 1;
+                } else if let Some(rest) =
+                    arg.strip_prefix("--keep-extensions=")
+                {
+                    extensions.max_parts =
+                        parse_count("--keep-extensions", rest)
+                            .map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--compound-ext=") {
+                    extensions.add_compound(rest).map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some((
+                    name @ ("--map" | "--keep" | "--strip" | "--rules"),
+                    rest,
+                )) = arg.split_once('=')
+                {
+                    add_rule(&mut rules, name, rest)
+                        .map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some((
+                    name @ ("--regex" | "--regex-post"),
+                    rest,
+                )) = arg.split_once('=')
+                {
+                    add_regex(&mut regex, name, rest)
+                        .map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some((
+                    name @ ("--space-replacement"
+                    | "--punct-replacement"
+                    | "--bracket-replacement"
+                    | "--quote-replacement"),
+                    rest,
+                )) = arg.split_once('=')
+                {
+                    set_class_replacement(&mut classes, name, rest)
+                        .map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--exclude=") {
+                    exclude.push(rest.to_string());
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--include=") {
+                    include.push(rest.to_string());
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--min-depth=") {
+                    min_depth = parse_count("--min-depth", rest)
+                        .map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--max-depth=") {
+                    max_depth = Some(
+                        parse_count("--max-depth", rest)
+                            .map_err(CliError::Message)?,
+                    );
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--sort=") {
+                    sort = SortOrder::parse(rest).map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--symlinks=") {
+                    symlinks = SymlinkPolicy::parse(rest)
+                        .map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--from-file=") {
+                    from_file = Some(PathBuf::from(rest));
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--jobs=") {
+                    jobs = parse_count("--jobs", rest)
+                        .map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--profile=") {
+                    profile = Some(rest.to_string());
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--ext-case=") {
+                    extensions.case =
+                        ExtensionCase::parse(rest).map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--ext-alias=") {
+                    extensions.add_aliases(rest).map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--max-length=") {
+                    max_length = Some(
+                        parse_length("--max-length", rest)
+                            .map_err(CliError::Message)?,
+                    );
+                    i += 1;
+                } else if let Some(rest) =
+                    arg.strip_prefix("--max-path-length=")
+                {
+                    max_path_length = Some(
+                        parse_length("--max-path-length", rest)
+                            .map_err(CliError::Message)?,
+                    );
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--length-unit=") {
+                    length_unit =
+                        LengthUnit::parse(rest).map_err(CliError::Message)?;
+                    i += 1;
+                } else if let Some(rest) = arg.strip_prefix("--target=") {
+                    target_fs =
+                        Some(TargetFs::parse(rest).map_err(CliError::Message)?);
+                    i += 1;
+                } else {
+                    return Err(CliError::Message(format!(
+                        "Unknown option: {arg}"
+                    )));
+                }
+            }
+        }
+    }
+
+    if null && from_file.is_none() {
+        return Err(CliError::Message(
+            "Option '--null' requires '--from-file'".to_string(),
+        ));
+    }
+
+    if undo.is_some() && (!targets.is_empty() || from_file.is_some()) {
+        return Err(CliError::Message(
+            "Option '--undo' cannot be combined with targets".to_string(),
+        ));
+    }
+
+    if max_depth.is_some_and(|max| min_depth > max) {
+        return Err(CliError::Message(
+            "Option '--min-depth' cannot be greater than '--max-depth'"
+                .to_string(),
+        ));
+    }
+
+    if let Some(target_fs) = target_fs {
+        let given = replacement.iter().map(String::as_str);
+        for replacement in given.chain(classes.values()) {
+            check_target_replacement(target_fs, replacement)
+                .map_err(CliError::Message)?;
+        }
+    }
+
+    let mode = if transliterate {
+        Some(SanitizeMode::Transliterate)
+    } else if full_sanitize {
+        Some(SanitizeMode::Full)
+    } else {
+        None
+    };
+    let settings = Settings {
+        replacement: replacement.clone(),
+        mode,
+        case,
+        exclude,
+        include,
+        gitignore,
+        rules,
+        regex,
+        classes,
+    };
+
+    Ok(Config {
+        recursive,
+        dry_run,
+        replacement: replacement.unwrap_or_else(|| "_".to_string()),
+        targets,
+        from_file,
+        null,
+        full_sanitize,
+        transliterate,
+        non_utf8,
+        on_conflict,
+        journal,
+        undo,
+        format,
+        case: case.unwrap_or_default(),
+        extensions,
+        fix_extensions,
+        target_fs,
+        max_length,
+        length_unit,
+        truncate_hash,
+        max_path_length,
+        min_depth,
+        max_depth,
+        sort,
+        entries,
+        symlinks,
+        fix_symlinks,
+        one_file_system,
+        jobs,
+        force,
+        profile,
+        user_config: config_file::user_config_path().filter(|_| !no_config),
+        project_config: !no_config,
+        settings,
+    })
+}
+
+/// Add the rule given with `option` (`--map`, `--keep`, `--strip` or
+/// `--rules`) to `rules`.
+fn add_rule(
+    rules: &mut CharRules,
+    option: &str,
+    value: &str,
+) -> Result<(), String> {
+    match option {
+        "--map" => rules.add_map(value),
+        "--keep" => rules.add_keep(value),
+        "--strip" => {
+            rules.add_strip(value);
+            Ok(())
+        }
+        _ => rules.add_path(Path::new(value)),
+    }
+}
+
+/// Add the `--regex` or `--regex-post` rule `spec` to `regex`.
+fn add_regex(
+    regex: &mut RegexRules,
+    option: &str,
+    spec: &str,
+) -> Result<(), String> {
+    let rule = RegexRule::parse(spec)?;
+    match option {
+        "--regex" => regex.pre.push(rule),
+        _ => regex.post.push(rule),
+    }
+    Ok(())
+}
+
+/// Set the replacement for the class named by `option`, e.g.
+/// `--space-replacement`.
+fn set_class_replacement(
+    classes: &mut ClassReplacements,
+    option: &str,
+    value: &str,
+) -> Result<(), String> {
+    let class = option
+        .trim_start_matches("--")
+        .trim_end_matches("-replacement");
+    let slot = classes.get_mut(class).expect("option names a class");
+    *slot = Some(validate_replacement(value)?);
+    Ok(())
+}
+
+fn check_target_replacement(
+    target_fs: TargetFs,
+    replacement: &str,
+) -> Result<(), String> {
+    if replacement.chars().all(|ch| target_fs.allows(ch)) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Replacement '{replacement}' is not allowed on {}",
+            target_fs.name()
+        ))
+    }
+}
+
+fn has_dot(name: &str) -> bool {
+    name.split('.').count() > 1
+}
+
+fn is_hidden(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+fn is_directory(path_str: &str) -> bool {
+    Path::new(path_str).is_dir()
+}
+
+/// Only the final component counts, so `./a.txt` is not hidden.
+fn has_extension(path_str: &str) -> bool {
+    let name = Path::new(path_str)
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or(path_str);
+    has_dot(name) && !is_directory(path_str) && !is_hidden(name)
+}
+
+/// The extension of `path_str` as it is on disk. Segments are judged
+/// by what they sanitize to, so a dirty extension is still found.
+fn extract_extension(path_str: &str, options: &SanitizeOptions) -> String {
+    if has_extension(path_str) {
+        let name = path_str.rsplit('/').next().unwrap_or(path_str);
+        options
+            .extensions
+            .extension_of_cleaned(name, |s| sanitize_component(s, options))
+            .to_string()
+    } else {
+        String::new()
+    }
+}
+
+fn sanitize_component(name: &str, options: &SanitizeOptions) -> String {
+    let SanitizeOptions {
+        ref replacement,
+        ref classes,
+        mode,
+        ref rules,
+        ..
+    } = *options;
+    let separators = classes.separators(replacement);
+    let mapped = rules.apply_map(name);
+    let transliterated;
+    let name = if let SanitizeMode::Transliterate = mode {
+        transliterated = transliterate::transliterate(&mapped);
+        transliterated.as_str()
+    } else {
+        mapped.as_str()
+    };
+
+    // First pass: map characters according to the selected mode.
+    // Replacements themselves pass through, so that sanitizing twice
+    // gives the same result even if they contain characters the mode
+    // rejects.
+    let mut tmp = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(ch) = rest.chars().next() {
+        if let Some(sep) = separators.iter().find(|sep| rest.starts_with(**sep))
+        {
+            tmp.push_str(sep);
+            rest = &rest[sep.len()..];
+            continue;
+        }
+        rest = &rest[ch.len_utf8()..];
+        if rules.strips(ch) {
+            continue;
+        }
+        if rules.keeps(ch) {
+            tmp.push(ch);
+            continue;
+        }
+        let mapped = match mode {
+            SanitizeMode::Legacy => match ch {
+                '×' => Some('x'),
+                c if c.is_whitespace()
+                    || matches!(
+                        c,
+                        '.' | ',' | '"' | ':' | '?' | '\'' | '#'
+                            | ';' | '&' | '*' | '\\'
+                    ) =>
+                {
+                    None
+                }
+                '(' | ')' | '[' | ']' => None,
+                _ => Some(ch),
+            },
+            SanitizeMode::Full | SanitizeMode::Transliterate => {
+                let allowed =
+                    ch.is_ascii_alphanumeric() || ch == '_' || ch == '-';
+                allowed.then_some(ch)
+            }
+        };
+        match mapped {
+            Some(ch) => tmp.push(ch),
+            None => tmp.push_str(classes.for_char(ch, replacement)),
+        }
+    }
+
+    // Collapse runs of each replacement into one.
+    let mut collapsed = tmp;
+    for sep in &separators {
+        let doubled = sep.repeat(2);
+        while collapsed.contains(&doubled) {
+            collapsed = collapsed.replace(&doubled, sep);
+        }
+    }
+
+    // Trim any leading or trailing replacements to avoid introducing
+    // sanitized names that start or end with them.
+    let mut trimmed = collapsed.as_str();
+    while let Some(sep) = separators.iter().find(|s| trimmed.starts_with(**s)) {
+        trimmed = &trimmed[sep.len()..];
+    }
+    while let Some(sep) = separators.iter().find(|s| trimmed.ends_with(**s)) {
+        trimmed = &trimmed[..trimmed.len() - sep.len()];
+    }
+    if trimmed.is_empty() && !name.is_empty() {
+        // Keep a single replacement for inputs that were entirely
+        // replaced so the filename does not become empty.
+        filler(replacement).to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// With `--fix-extensions`, the extension `input` should have instead of
+/// `extension`, if its content is recognized and does not match. Hidden
+/// files are left alone.
+fn fix_extension(
+    input: &Path,
+    fname: &str,
+    extension: &str,
+    options: &SanitizeOptions,
+) -> Option<String> {
+    if !options.fix_extensions || is_hidden(fname) || !input.is_file() {
+        return None;
+    }
+    let kind = sniff::sniff(input)?;
+    if kind.accepts(extension) {
+        return None;
+    }
+    let shouting = extension.chars().any(char::is_uppercase)
+        && !extension.chars().any(char::is_lowercase);
+    Some(if shouting {
+        kind.extension.to_uppercase()
+    } else {
+        kind.extension.to_string()
+    })
+}
+
+/// Map each segment of `extension` like a stem, then apply the alias and
+/// case rules. `None` if a segment ends up as nothing but the
+/// replacement.
+fn sanitize_extension(
+    extension: &str,
+    options: &SanitizeOptions,
+) -> Option<String> {
+    if extension.is_empty() {
+        return Some(String::new());
+    }
+    let mut segments = Vec::new();
+    for segment in extension.split('.') {
+        let sanitized = sanitize_component(segment, options);
+        if sanitized == filler(&options.replacement) {
+            return None;
+        }
+        segments.push(sanitized);
+    }
+    Some(options.extensions.normalize(&segments.join(".")))
+}
+
+pub fn sanitized_filename(
+    input_file: &str,
+    replacement: &str,
+    mode: SanitizeMode,
+) -> String {
+    let options = SanitizeOptions::new(replacement, mode);
+    // A `&str` is always valid UTF-8, so the name is never skipped.
+    sanitized_path(Path::new(input_file), &options)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|| input_file.to_string())
+}
+
+/// Sanitize the final component of `input`, leaving any parent
+/// directories byte-for-byte intact. Returns `None` when the name is not
+/// valid UTF-8 and `options.non_utf8` is `NonUtf8Policy::Skip`.
+pub fn sanitized_path(
+    input: &Path,
+    options: &SanitizeOptions,
+) -> Option<PathBuf> {
+    let Some(fname_os) = input.file_name() else {
+        return Some(input.to_path_buf());
+    };
+    let fname = options.non_utf8.decode(fname_os, &options.replacement)?;
+
+    let parent = input
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty() && p.as_os_str() != ".");
+
+    let path_str = match (input.to_str(), parent) {
+        (Some(s), _) => s.to_string(),
+        (None, Some(parent)) => {
+            format!("{}/{fname}", parent.to_string_lossy())
+        }
+        (None, None) => fname.clone(),
+    };
+    // `path_str` may be a decoded copy that does not exist on disk, so
+    // check for a directory on the real path first.
+    let extension = if input.is_dir() {
+        String::new()
+    } else {
+        extract_extension(&path_str, options)
+    };
+    // Hidden names have no extension. Inside a directory, including
+    // `./`, they keep their leading dot.
+    let in_dir = input.parent().is_some_and(|p| !p.as_os_str().is_empty());
+    let body = if in_dir && is_hidden(&fname) {
+        &fname[1..]
+    } else {
+        &fname
+    };
+    let stem = match fname.len().checked_sub(extension.len() + 1) {
+        Some(end) if !extension.is_empty() && fname.ends_with(&extension) => {
+            &fname[..end]
+        }
+        _ if !extension.is_empty() => "",
+        _ => body,
+    };
+    let extension = match fix_extension(input, &fname, &extension, options) {
+        Some(fixed) => fixed,
+        None => extension,
+    };
+    // If the extension does not survive sanitizing, treat the name as
+    // having none.
+    let (stem, extension) = match sanitize_extension(&extension, options) {
+        Some(extension) => (stem, extension),
+        None => (body, String::new()),
+    };
+
+    // Regex rules that would leave nothing of the stem are ignored.
+    let rewritten = RegexRules::apply(&options.regex.pre, stem);
+    let stem = if rewritten.is_empty() && !stem.is_empty() {
+        stem
+    } else {
+        &rewritten
+    };
+    let mut result = sanitize_component(stem, options);
+    let rewritten = RegexRules::apply(&options.regex.post, &result);
+    if !rewritten.is_empty() {
+        result = rewritten;
+    }
+    if options.case != CaseStyle::Preserve {
+        result = options.case.apply(&result, &options.replacement);
+    }
+    if body.len() < fname.len() {
+        result.insert(0, '.');
+    }
+
+    if !extension.is_empty() {
+        if !result.is_empty() || parent.is_some() {
+            result.push('.');
+        }
+        result.push_str(&extension);
+    }
+
+    if let Some(target_fs) = options.target_fs {
+        result = target_fs.apply(&result, &options.replacement);
+    }
+
+    if let Some(limit) = &options.max_length {
+        // The target may have changed the extension, but not its number
+        // of segments.
+        let parts = extension.matches('.').count() + 1;
+        let ext = match result.rmatch_indices('.').nth(parts - 1) {
+            Some((dot, _)) if !extension.is_empty() => &result[dot + 1..],
+            _ => "",
+        };
+        result = limit.fit(
+            &result,
+            ext,
+            fname_os.as_encoded_bytes(),
+            &options.replacement,
+        );
+    }
+
+    if result == "." || result == ".." {
+        result = filler(&options.replacement).to_string();
+    }
+
+    // Reattach any parent directories, if present.
+    Some(match parent {
+        Some(parent) => parent.join(result),
+        None => PathBuf::from(result),
+    })
+}
+
+pub fn rename_path(old: &Path, new: &Path, dry_run: bool) -> io::Result<PathBuf> {
+    RenameSession::new(dry_run, ConflictPolicy::Skip, "_").rename(old, new)
+}
+
+/// Applies rename plans: unless this is a dry run, performs the renames
+/// and records them in the journal, and reports every operation to its
+/// `Reporter`.
+pub struct RenameSession<'r> {
+    pub dry_run: bool,
+    /// Shared by every plan built in this session, so that two inputs
+    /// which sanitize to the same name are detected even in a dry run.
+    pub planner: Planner,
+    journal: Option<Journal>,
+    reporter: Box<dyn Reporter + 'r>,
+}
+
+impl fmt::Debug for RenameSession<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenameSession")
+            .field("dry_run", &self.dry_run)
+            .field("planner", &self.planner)
+            .field("journal", &self.journal)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'r> RenameSession<'r> {
+    /// A session that reports to standard output with `HumanReporter`.
+    pub fn new(
+        dry_run: bool,
+        on_conflict: ConflictPolicy,
+        separator: &str,
+    ) -> Self {
+        let mut planner = Planner::new(on_conflict, separator);
+        planner.probe = !dry_run;
+        Self {
+            dry_run,
+            planner,
+            journal: None,
+            reporter: Box::new(HumanReporter::stdout()),
+        }
+    }
+
+    /// Send events to `reporter` instead. Pass `&mut reporter` to keep
+    /// hold of it after the session is gone.
+    pub fn set_reporter(&mut self, reporter: impl Reporter + 'r) {
+        self.reporter = Box::new(reporter);
+    }
+
+    /// Flush output that is only complete at the end of a run.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.reporter.finish()
+    }
+
+    /// Record every rename performed from now on in `journal`.
+    pub fn set_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Plan a single rename and apply it straight away.
+    pub fn rename(&mut self, old: &Path, new: &Path) -> io::Result<PathBuf> {
+        let op = self
+            .planner
+            .plan_rename(old, new)
+            .map_err(|err| self.report_failure(err))?;
+        self.apply_op(&op)?;
+        Ok(op.final_path().to_path_buf())
+    }
+
+    /// Apply every operation of `plan` in order. Callers should
+    /// `validate` the plan first.
+    pub fn apply(&mut self, plan: &RenamePlan) -> io::Result<()> {
+        for op in &plan.ops {
+            self.apply_op(op)?;
+        }
+        Ok(())
+    }
+
+    /// Report every problem that stops `plan` from being applied.
+    pub fn report_problems(
+        &mut self,
+        problems: &[PlanError],
+    ) -> io::Result<()> {
+        for problem in problems {
+            self.reporter.problem(problem, self.dry_run)?;
+        }
+        Ok(())
+    }
+
+    /// Report `err` if it is a `PlanError`, such as the one
+    /// `ConflictPolicy::Fail` gives, and hand it back.
+    pub fn report_failure(&mut self, err: io::Error) -> io::Error {
+        let problem = err.get_ref().and_then(|e| e.downcast_ref::<PlanError>());
+        if let Some(problem) = problem {
+            // The run stops with `err` either way.
+            let _ = self.reporter.problem(problem, self.dry_run);
+        }
+        err
+    }
+
+    /// Repoint the symlinks in `fixes`, which `symlinks::plan_fixes`
+    /// worked out before the plan was applied.
+    pub fn relink(&mut self, fixes: &[LinkFix]) -> io::Result<()> {
+        for fix in fixes {
+            if !self.dry_run {
+                fix.apply()?;
+            }
+            self.reporter.relink(fix, self.dry_run)?;
+        }
+        Ok(())
+    }
+
+    /// Perform `op` and then report it, so that the event reflects the
+    /// outcome, including failures.
+    fn apply_op(&mut self, op: &RenameOp) -> io::Result<()> {
+        let result = self.perform(op);
+        self.reporter.report(&Event {
+            op,
+            dry_run: self.dry_run,
+            error: result.as_ref().err(),
+        })?;
+        result
+    }
+
+    fn perform(&mut self, op: &RenameOp) -> io::Result<()> {
+        if !self.dry_run && op.is_rename() {
+            if let Some(journal) = &mut self.journal
+                && let Err(err) = journal.open()
+            {
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!(
+                        "Cannot open journal '{}': {err}",
+                        journal.path().display()
+                    ),
+                ));
+            }
+            rename_entry(&op.from, &op.to)?;
+            if let Some(journal) = &mut self.journal {
+                // The rename has happened, so say so: it cannot be
+                // undone with this journal.
+                journal.record(&op.from, &op.to).map_err(|err| {
+                    io::Error::new(
+                        err.kind(),
+                        format!(
+                            "Renamed, but could not record it in journal '{}': {err}",
+                            journal.path().display()
+                        ),
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `fs::rename`, except that case-only renames go through a temporary
+/// name, since case-insensitive filesystems may otherwise ignore them.
+fn rename_entry(from: &Path, to: &Path) -> io::Result<()> {
+    if !plan::is_case_only(from, to) {
+        return fs::rename(from, to);
+    }
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(from.file_name().unwrap_or_default());
+    tmp_name.push(format!(".sanitize_filenames-{}", std::process::id()));
+    let tmp = from.with_file_name(tmp_name);
+    fs::rename(from, &tmp)?;
+    fs::rename(&tmp, to).inspect_err(|_| {
+        let _ = fs::rename(&tmp, from);
+    })
+}
+
+/// Plan the sanitization of `path` itself (not its contents) and return
+/// where it will end up.
+fn plan_path(
+    path: &Path,
+    options: &SanitizeOptions,
+    planner: &mut Planner,
+    plan: &mut RenamePlan,
+) -> io::Result<PathBuf> {
+    let op = plan_op(path, sanitized_path(path, options), planner)?;
+    let final_path = op.final_path().to_path_buf();
+    plan.ops.push(op);
+    Ok(final_path)
+}
+
+/// The operation that renames `path` to `new_path`, as worked out by
+/// `sanitized_path`.
+pub(crate) fn plan_op(
+    path: &Path,
+    new_path: Option<PathBuf>,
+    planner: &mut Planner,
+) -> io::Result<RenameOp> {
+    match new_path {
+        Some(new_path) => planner.plan_rename(path, &new_path),
+        None => Ok(RenameOp {
+            from: path.to_path_buf(),
+            to: path.to_path_buf(),
+            kind: RenameKind::Skip(SkipReason::NotUtf8),
+        }),
+    }
+}
+
+/// Whether a target given without `--recursive` is renamed.
+fn renames_target(path: &Path, options: &SanitizeOptions) -> io::Result<bool> {
+    // Missing targets are reported by the planner.
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return Ok(true);
+    };
+    let file_type = meta.file_type();
+    if file_type.is_symlink() && options.symlinks == SymlinkPolicy::Skip {
+        return Ok(false);
+    }
+    Ok(options.entries.allows(file_type.is_dir()))
+}
+
+/// Work out every rename for `targets` without touching the filesystem.
+/// With `recursive`, directory targets are planned together with their
+/// contents.
+pub fn build_plan<P: AsRef<Path>>(
+    targets: &[P],
+    recursive: bool,
+    options: &SanitizeOptions,
+    planner: &mut Planner,
+) -> io::Result<RenamePlan> {
+    planner.extensions = options.extensions.clone();
+    planner.max_length = options.max_length;
+    let mut plan = RenamePlan::new();
+    for target in targets {
+        let path = target.as_ref();
+        if recursive {
+            walk::plan_tree(path, options, planner, &mut plan)?;
+        } else if renames_target(path, options)? {
+            if path.is_symlink() {
+                plan.links.push(path.to_path_buf());
+            }
+            plan_path(path, options, planner, &mut plan)?;
+        }
+    }
+    if let Some(max) = options.max_path_length {
+        plan.skip_long_paths(max);
+    }
+    plan.skip_read_only();
+    Ok(plan)
+}
+
+/// Validate `plan` and apply it with `session`.
+pub fn apply_plan(
+    plan: &RenamePlan,
+    session: &mut RenameSession,
+) -> io::Result<()> {
+    if let Err(problems) = plan.validate() {
+        session.report_problems(&problems)?;
+        return Err(plan::problems_to_io_error(&problems));
+    }
+    session.apply(plan)
+}
+
+pub fn sanitize_directory_tree(
+    path: &Path,
+    dry_run: bool,
+    replacement: &str,
+    mode: SanitizeMode,
+) -> io::Result<PathBuf> {
+    sanitize_directory_tree_with(
+        path,
+        &SanitizeOptions::new(replacement, mode),
+        &mut RenameSession::new(dry_run, ConflictPolicy::Skip, replacement),
+    )
+}
+
+pub fn sanitize_directory_tree_with(
+    path: &Path,
+    options: &SanitizeOptions,
+    session: &mut RenameSession,
+) -> io::Result<PathBuf> {
+    let plan = build_plan(&[path], true, options, &mut session.planner)
+        .map_err(|err| session.report_failure(err))?;
+    apply_plan(&plan, session)?;
+    // The target is planned last, unless the options leave it alone.
+    Ok(plan
+        .ops
+        .last()
+        .filter(|op| op.from == path)
+        .map_or_else(|| path.to_path_buf(), |op| op.final_path().to_path_buf()))
+}
+
+/// Revert the renames recorded in `journal_path`, newest first. Each
+/// entry goes through `RenameSession::rename`, so entries whose new name
+/// is gone or whose old name has been reused are reported and skipped.
+/// An old name is only ever restored if it is free: the session's
+/// conflict policy is set to `ConflictPolicy::Skip`, whatever it was.
+pub fn undo_journal(
+    journal_path: &Path,
+    session: &mut RenameSession,
+) -> io::Result<()> {
+    session.planner.on_conflict = ConflictPolicy::Skip;
+    let entries = journal::read_journal(journal_path)?;
+    for (old, new) in entries.iter().rev() {
+        session.rename(new, old)?;
+    }
+    Ok(())
+}
+
+fn run_with_args<S: AsRef<OsStr>>(args: &[S]) -> i32 {
+    let config = match parse_args(args) {
+        Ok(cfg) => cfg,
+        Err(CliError::Help) => {
+            let _ = print_usage(io::stdout(), color::for_stdout());
+            return 0;
+        }
+        Err(CliError::Message(msg)) => {
+            let e = color::for_stderr();
+            eprintln!("{}{}error:{} {msg}", e.bold, e.red, e.reset);
+            let _ = print_usage(io::stderr(), e);
+            return 1;
+        }
+    };
+
+    if config.targets.is_empty()
+        && config.undo.is_none()
+        && config.from_file.is_none()
+    {
+        let e = color::for_stderr();
+        eprintln!(
+            "{}{}error:{} No files or directories specified",
+            e.bold, e.red, e.reset
+        );
+        let _ = print_usage(io::stderr(), e);
+        return 1;
+    }
+
+    if let Err(err) = run(config) {
+        let e = color::for_stderr();
+        eprintln!("{}{}error:{} {err}", e.bold, e.red, e.reset);
+        return 1;
+    }
+
+    0
+}
+
+/// Read the targets listed in `input`, one per line or, with `null`,
+/// separated by NUL bytes. Names are taken byte for byte; empty ones,
+/// `.` and `..` are dropped, as on the command line.
+pub fn read_target_list(
+    mut input: impl io::Read,
+    null: bool,
+) -> io::Result<Vec<OsString>> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let separator = if null { b'\0' } else { b'\n' };
+    bytes
+        .split(|&b| b == separator)
+        .filter(|name| !matches!(*name, b"" | b"." | b".."))
+        .map(|name| {
+            journal::os_string_from_bytes(name.to_vec()).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "target names must be valid UTF-8 on this platform",
+                )
+            })
+        })
+        .collect()
+}
+
+pub fn run_from_env() -> i32 {
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    run_with_args(&args)
+}
+
+pub fn run(mut config: Config) -> io::Result<()> {
+    if let Some(list) = &config.from_file {
+        let targets = if list == Path::new("-") {
+            read_target_list(io::stdin().lock(), config.null)
+        } else {
+            fs::File::open(list)
+                .and_then(|file| read_target_list(file, config.null))
+        }
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Cannot read target list '{}': {err}", list.display()),
+            )
+        })?;
+        config.targets.extend(targets);
+    }
+
+    let mut session = RenameSession::new(
+        config.dry_run,
+        config.on_conflict,
+        &config.replacement,
+    );
+    // Names meant for a case-insensitive filesystem must not differ
+    // only in case, whatever the local filesystem does.
+    if config.target_fs.is_some_and(TargetFs::case_insensitive) {
+        session.planner.fold_case = Some(true);
+    }
+    if let Some(path) = &config.journal {
+        session.set_journal(Journal::new(path));
+    }
+    session.set_reporter(output::stdout_reporter(config.format));
+
+    let result = match &config.undo {
+        Some(journal_path) => undo_journal(journal_path, &mut session),
+        None => sanitize_targets(&config, &mut session),
+    };
+    let finished = session.finish();
+
+    if let Some(journal) = session.journal().filter(|j| !j.is_empty()) {
+        // Structured output owns stdout, so the note goes to stderr there.
+        if config.format == OutputFormat::Human {
+            let p = color::for_stdout();
+            println!(
+                "{}Recorded {} rename(s) in '{}'; revert with --undo{}",
+                p.dim,
+                journal.len(),
+                journal.path().display(),
+                p.reset
+            );
+        } else {
+            eprintln!(
+                "Recorded {} rename(s) in '{}'; revert with --undo",
+                journal.len(),
+                journal.path().display()
+            );
+        }
+    }
+
+    result.and(finished)
+}
+
+/// The options for `config` with `files`, the settings from config
+/// files, layered between its defaults and its command-line settings.
+fn sanitize_options(
+    config: &Config,
+    files: &Settings,
+) -> io::Result<SanitizeOptions> {
+    let mode = if config.transliterate {
+        SanitizeMode::Transliterate
+    } else if config.full_sanitize {
+        SanitizeMode::Full
+    } else {
+        SanitizeMode::Legacy
+    };
+    let mut settings = Settings {
+        replacement: Some(config.replacement.clone()),
+        mode: Some(mode),
+        case: Some(config.case),
+        ..Settings::default()
+    };
+    settings.merge(files);
+    settings.merge(&config.settings);
+
+    let replacement = settings
+        .replacement
+        .unwrap_or_else(|| config.replacement.clone());
+    if let Some(target_fs) = config.target_fs {
+        let main = [replacement.as_str()].into_iter();
+        for replacement in main.chain(settings.classes.values()) {
+            check_target_replacement(target_fs, replacement).map_err(|msg| {
+                io::Error::new(io::ErrorKind::InvalidInput, msg)
+            })?;
+        }
+    }
+    Ok(SanitizeOptions {
+        non_utf8: config.non_utf8,
+        case: settings.case.unwrap_or(config.case),
+        extensions: config.extensions.clone(),
+        fix_extensions: config.fix_extensions,
+        target_fs: config.target_fs,
+        max_length: config.max_length.map(|max| LengthLimit {
+            max,
+            unit: config.length_unit,
+            hash: config.truncate_hash,
+        }),
+        max_path_length: config.max_path_length,
+        exclude: settings.exclude,
+        include: settings.include,
+        gitignore: settings.gitignore.unwrap_or(false),
+        min_depth: config.min_depth,
+        max_depth: config.max_depth,
+        sort: config.sort,
+        entries: config.entries,
+        symlinks: config.symlinks,
+        one_file_system: config.one_file_system,
+        force: config.force,
+        jobs: config.jobs,
+        rules: settings.rules,
+        regex: settings.regex,
+        classes: settings.classes,
+        ..SanitizeOptions::new(&replacement, settings.mode.unwrap_or(mode))
+    })
+}
+
+/// Plan every target with the options its config files call for, then
+/// apply the whole plan.
+fn sanitize_targets(
+    config: &Config,
+    session: &mut RenameSession,
+) -> io::Result<()> {
+    let mut files = ConfigFiles::new(
+        config.profile.clone(),
+        config.user_config.as_deref(),
+        config.project_config,
+    )?;
+    // Lists from `find` name a directory before its contents, but the
+    // contents have to be renamed first, while their paths still exist.
+    // So each target moves in front of its ancestors; unrelated targets
+    // keep the order they were given in, which decides collisions.
+    let mut targets: Vec<&Path> = Vec::with_capacity(config.targets.len());
+    for target in config.targets.iter().map(Path::new) {
+        let ancestor = targets.iter().position(|placed| {
+            target != *placed && target.starts_with(placed)
+        });
+        targets.insert(ancestor.unwrap_or(targets.len()), target);
+    }
+    let mut plan = RenamePlan::new();
+    for target in targets {
+        if !config.force {
+            safety::check_target(target)?;
+        }
+        let options = sanitize_options(config, &files.settings_for(target)?)?;
+        session.planner.separator = filler(&options.replacement).to_string();
+        let target_plan = build_plan(
+            &[target],
+            config.recursive,
+            &options,
+            &mut session.planner,
+        )
+        .map_err(|err| session.report_failure(err))?;
+        plan.ops.extend(target_plan.ops);
+        plan.links.extend(target_plan.links);
+    }
+    files.check_profile()?;
+    let fixes = if config.fix_symlinks {
+        symlinks::plan_fixes(&plan)
+    } else {
+        Vec::new()
+    };
+    apply_plan(&plan, session)?;
+    session.relink(&fixes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_dir() -> PathBuf {
+        let mut base = env::temp_dir();
+        let unique = format!(
+            "sanitize_filenames_test_{}_{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        );
+        base.push(unique);
+        fs::create_dir_all(&base).unwrap();
+        base
+    }
+
+    #[test]
+    fn print_usage_includes_sections() {
+        let mut buf: Vec<u8> = Vec::new();
+        print_usage(&mut buf, Palette::plain()).expect("print_usage failed");
+        let output = String::from_utf8(buf).expect("usage is valid UTF-8");
+        assert!(output.contains("Usage: sanitize_filenames [options] [FILES...]"));
+        assert!(output.contains("Options:"));
+        assert!(output.contains("Examples:"));
+    }
+
+    #[test]
+    fn print_usage_emits_ansi_when_palette_is_ansi() {
+        let mut buf: Vec<u8> = Vec::new();
+        print_usage(&mut buf, Palette::ansi()).expect("print_usage failed");
+        let output = String::from_utf8(buf).expect("usage is valid UTF-8");
+        assert!(output.contains("\x1b["), "expected ANSI escape codes");
+    }
+
+    #[test]
+    fn print_usage_omits_ansi_when_palette_is_plain() {
+        let mut buf: Vec<u8> = Vec::new();
+        print_usage(&mut buf, Palette::plain()).expect("print_usage failed");
+        let output = String::from_utf8(buf).expect("usage is valid UTF-8");
+        assert!(!output.contains('\x1b'), "plain palette must not emit escapes");
+    }
+
+    #[test]
+    fn has_dot_recognizes_periods() {
+        assert!(!has_dot("file"));
+        assert!(has_dot("file.txt"));
+        assert!(has_dot(".hidden"));
+        assert!(has_dot("a.b.c"));
+    }
+
+    #[test]
+    fn is_hidden_detects_leading_dot_only() {
+        assert!(is_hidden(".gitignore"));
+        assert!(!is_hidden("file"));
+        assert!(!is_hidden("dir/.git"));
+    }
+
+    #[test]
+    fn is_directory_matches_filesystem() {
+        let base = temp_dir();
+        let dir = base.join("dir");
+        let file = base.join("file.txt");
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&file, "test").unwrap();
+
+        assert!(is_directory(dir.to_str().unwrap()));
+        assert!(!is_directory(file.to_str().unwrap()));
+
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn has_extension_ignores_dirs_and_hidden_files() {
+        let base = temp_dir();
+        let dir_with_dot = base.join("dir.with.dot");
+        fs::create_dir_all(&dir_with_dot).unwrap();
+
+        assert!(!has_extension(dir_with_dot.to_str().unwrap()));
+        assert!(has_extension("file.txt"));
+        assert!(has_extension("archive.tar.gz"));
+        assert!(!has_extension(".gitignore"));
+
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn extract_extension_handles_files_and_dirs() {
+        let base = temp_dir();
+        let dir_with_dot = base.join("dir.with.dot");
+        fs::create_dir_all(&dir_with_dot).unwrap();
+
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        assert_eq!(extract_extension("file.txt", &options), "txt");
+        assert_eq!(extract_extension("archive.tar.gz", &options), "tar.gz");
+        assert_eq!(
+            extract_extension(
+                &format!("{}/{}", dir_with_dot.to_string_lossy(), "file.dat"),
+                &options
+            ),
+            "dat"
+        );
+        assert_eq!(
+            extract_extension(dir_with_dot.to_str().unwrap(), &options),
+            ""
+        );
+        assert_eq!(extract_extension(".gitignore", &options), "");
+
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn sanitize_component_collapses_repeated_replacements() {
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        let result = sanitize_component("Hello   World", &options);
+        assert_eq!(result, "Hello_World");
+    }
+
+    #[test]
+    fn sanitize_component_maps_special_characters() {
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        let result =
+            sanitize_component("August Gold Q&A Audio.m4a", &options);
+        assert_eq!(result, "August_Gold_Q_A_Audio_m4a");
+    }
+
+    #[test]
+    fn sanitize_component_maps_multiplication_sign() {
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        let result = sanitize_component("size 4×4", &options);
+        assert_eq!(result, "size_4x4");
+    }
+
+    #[test]
+    fn parse_args_sets_flags_and_targets() {
+        let args = vec![
+            "-r".to_string(),
+            "--dry-run".to_string(),
+            "file1".to_string(),
+            ".".to_string(),
+            "..".to_string(),
+            "dir2".to_string(),
+        ];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert!(cfg.recursive);
+        assert!(cfg.dry_run);
+        assert_eq!(cfg.replacement, "_");
+        assert!(!cfg.full_sanitize);
+        assert_eq!(cfg.targets, vec![OsString::from("file1"), OsString::from("dir2")]);
+    }
+
+    #[test]
+    fn parse_args_replacement_forms() {
+        let args_short = vec!["-c".to_string(), "+".to_string(), "file".to_string()];
+        let cfg_short = parse_args(&args_short).expect("parse_args failed");
+        assert_eq!(cfg_short.replacement, "+");
+        assert_eq!(cfg_short.targets, vec![OsString::from("file")]);
+        assert!(!cfg_short.full_sanitize);
+
+        let args_short_inline = vec!["-c+".to_string(), "file".to_string()];
+        let cfg_short_inline =
+            parse_args(&args_short_inline).expect("parse_args failed");
+        assert_eq!(cfg_short_inline.replacement, "+");
+        assert_eq!(cfg_short_inline.targets, vec![OsString::from("file")]);
+        assert!(!cfg_short_inline.full_sanitize);
+
+        let args_long_inline =
+            vec!["--replacement=+".to_string(), "file".to_string()];
+        let cfg_long_inline =
+            parse_args(&args_long_inline).expect("parse_args failed");
+        assert_eq!(cfg_long_inline.replacement, "+");
+        assert_eq!(cfg_long_inline.targets, vec![OsString::from("file")]);
+        assert!(!cfg_long_inline.full_sanitize);
+    }
+
+    #[test]
+    fn parse_args_missing_replacement_argument() {
+        let args_short = vec!["-c".to_string()];
+        match parse_args(&args_short) {
+            Err(CliError::Message(msg)) => {
+                assert!(msg.contains("Option '-c' requires an argument"))
+            }
+            _ => panic!("expected error for missing -c argument"),
+        }
+
+        let args_long = vec!["--replacement".to_string()];
+        match parse_args(&args_long) {
+            Err(CliError::Message(msg)) => {
+                assert!(msg.contains("Option '--replacement' requires an argument"))
+            }
+            _ => panic!("expected error for missing --replacement argument"),
+        }
+    }
+
+    #[test]
+    fn parse_args_unknown_option_yields_error() {
+        let args = vec!["--unknown".to_string()];
+        match parse_args(&args) {
+            Err(CliError::Message(msg)) => {
+                assert!(msg.contains("Unknown option: --unknown"))
+            }
+            _ => panic!("expected error for unknown option"),
+        }
+    }
+
+    #[test]
+    fn parse_args_allows_single_dash_target() {
+        let args = vec!["-".to_string()];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert!(!cfg.full_sanitize);
+        assert_eq!(cfg.targets, vec![OsString::from("-")]);
+    }
+
+    #[test]
+    fn parse_args_full_sanitize_flags() {
+        let args = vec!["--full-sanitize".to_string(), "file".to_string()];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert!(cfg.full_sanitize);
+        assert_eq!(cfg.targets, vec![OsString::from("file")]);
+
+        let args_short = vec!["-F".to_string(), "other".to_string()];
+        let cfg_short = parse_args(&args_short).expect("parse_args failed");
+        assert!(cfg_short.full_sanitize);
+        assert_eq!(cfg_short.targets, vec![OsString::from("other")]);
+    }
+
+    #[test]
+    fn sanitized_basic_cases() {
+        assert_eq!(
+            sanitized_filename("×", "_", SanitizeMode::Legacy),
+            "x"
+        );
+        assert_eq!(
+            sanitized_filename("Hello", "_", SanitizeMode::Legacy),
+            "Hello"
+        );
+        assert_eq!(
+            sanitized_filename("hello.wav", "_", SanitizeMode::Legacy),
+            "hello.wav"
+        );
+        assert_eq!(
+            sanitized_filename("Hello World", "_", SanitizeMode::Legacy),
+            "Hello_World"
+        );
+        assert_eq!(
+            sanitized_filename("Hello.World", "_", SanitizeMode::Legacy),
+            "Hello.World"
+        );
+        assert_eq!(
+            sanitized_filename("hello world.wav", "_", SanitizeMode::Legacy),
+            "hello_world.wav"
+        );
+        assert_eq!(
+            sanitized_filename("Hello.world.wav", "_", SanitizeMode::Legacy),
+            "Hello_world.wav"
+        );
+        assert_eq!(
+            sanitized_filename("hello? + world.wav", "_", SanitizeMode::Legacy),
+            "hello_+_world.wav"
+        );
+        assert_eq!(
+            sanitized_filename(
+                "Bart_banner_14_5_×_2_5_in.png",
+                "_",
+                SanitizeMode::Legacy
+            ),
+            "Bart_banner_14_5_x_2_5_in.png"
+        );
+        assert_eq!(
+            sanitized_filename(
+                "hello? &&*()#@+ world.wav",
+                "_",
+                SanitizeMode::Legacy
+            ),
+            "hello_@+_world.wav"
+        );
+        assert_eq!(
+            sanitized_filename(
+                "August Gold Q&A Audio.m4a.wav",
+                "_",
+                SanitizeMode::Legacy
+            ),
+            "August_Gold_Q_A_Audio_m4a.wav"
+        );
+        assert_eq!(
+            sanitized_filename(
+                "nested/dir/file name.txt",
+                "_",
+                SanitizeMode::Legacy
+            ),
+            "nested/dir/file_name.txt"
+        );
+        assert_eq!(
+            sanitized_filename(
+                "/absolute/path/Hello World.txt",
+                "_",
+                SanitizeMode::Legacy
+            ),
+            "/absolute/path/Hello_World.txt"
+        );
+        assert_eq!(
+            sanitized_filename(
+            "relative/./path/Hello World.txt",
+            "_",
+            SanitizeMode::Legacy
+        ),
+        "relative/./path/Hello_World.txt"
+    );
+}
+
+    #[test]
+    fn sanitized_trims_edge_replacements() {
+        assert_eq!(
+            sanitized_filename(
+                "🐾_The_Adventures_of_Marshal_Poppy_The_Great_Sarsaparilla_Heist.md",
+                "_",
+                SanitizeMode::Full
+            ),
+            "The_Adventures_of_Marshal_Poppy_The_Great_Sarsaparilla_Heist.md"
+        );
+
+        assert_eq!(
+            sanitized_filename("  spaced  ", "_", SanitizeMode::Legacy),
+            "spaced"
+        );
+
+        assert_eq!(
+            sanitized_filename(
+                "_The_Adventures_of_Marshal_Poppy_The_Great_Sarsaparilla_Heist_.md",
+                "_",
+                SanitizeMode::Legacy
+            ),
+            "The_Adventures_of_Marshal_Poppy_The_Great_Sarsaparilla_Heist.md"
+        );
+
+        assert_eq!(
+            sanitized_filename(
+                "nested/  spaced  .txt",
+                "_",
+                SanitizeMode::Legacy
+            ),
+            "nested/spaced.txt"
+        );
+    }
+
+    #[test]
+    fn custom_replacement() {
+        assert_eq!(
+            sanitized_filename(
+                "Hello World.txt",
+                "-",
+                SanitizeMode::Legacy
+            ),
+            "Hello-World.txt"
+        );
+    }
+
+    #[test]
+    fn full_sanitize_outputs_only_whitelisted_chars() {
+        let input = "Hello World! @#[](){}=+,.×é";
+        let output =
+            sanitized_filename(input, "_", SanitizeMode::Full);
+        let path = Path::new(&output);
+        let fname = path.file_name().unwrap().to_string_lossy();
+        let base = fname.split('.').next().unwrap();
+        for ch in base.chars() {
+            assert!(
+                ch.is_ascii_alphanumeric() || ch == '_' || ch == '-',
+                "found disallowed character {:?} in output {:?}",
+                ch,
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn cli_replacement_option() {
+        let args = vec!["--replacement".to_string(), "-".to_string()];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.replacement, "-");
+        assert!(cfg.targets.is_empty());
+        assert!(!cfg.full_sanitize);
+    }
+
+    #[test]
+    fn invalid_replacement_rejected() {
+        let err = validate_replacement("/").unwrap_err();
+        assert!(
+            err.contains("Replacement character '/' is not allowed"),
+            "unexpected error message: {err}"
+        );
+    }
+
+    #[test]
+    fn recursive_directory() {
+        let tmp = temp_dir();
+        let root = tmp.join("dir one");
+        let sub = root.join("sub dir");
+        fs::create_dir_all(&sub).unwrap();
+        let file = sub.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let sanitized_root =
+            sanitize_directory_tree(&root, false, "_", SanitizeMode::Legacy)
+                .unwrap();
+
+        let expected_root = tmp.join("dir_one");
+        let expected_sub = expected_root.join("sub_dir");
+        let expected_file = expected_sub.join("file_name.txt");
+
+        assert_eq!(sanitized_root, expected_root);
+        assert!(expected_sub.is_dir());
+        assert!(expected_file.is_file());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn recursive_custom_replacement() {
+        let tmp = temp_dir();
+        let root = tmp.join("dir one");
+        let sub = root.join("sub dir");
+        fs::create_dir_all(&sub).unwrap();
+        let file = sub.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let sanitized_root =
+            sanitize_directory_tree(&root, false, "-", SanitizeMode::Legacy)
+                .unwrap();
+
+        let expected_root = tmp.join("dir-one");
+        let expected_sub = expected_root.join("sub-dir");
+        let expected_file = expected_sub.join("file-name.txt");
+
+        assert_eq!(sanitized_root, expected_root);
+        assert!(expected_sub.is_dir());
+        assert!(expected_file.is_file());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn recursive_directory_with_nested_content() {
+        let tmp = temp_dir();
+        let root = tmp.join("Root Dir");
+        let child_one = root.join("Child One");
+        let child_two = root.join("Second & Child");
+        let grand_one = child_one.join("Grand Child(1)");
+        let grand_two = child_two.join("Grand (Final)");
+
+        fs::create_dir_all(&grand_one).unwrap();
+        fs::create_dir_all(&grand_two).unwrap();
+
+        let files = [
+            root.join("Root File?.txt"),
+            child_one.join("Clip (A).mov"),
+            child_one.join("Clip (B).mov"),
+            grand_one.join("Take #1.wav"),
+            child_two.join("Audio (Draft).wav"),
+            grand_two.join("Mix #2?.wav"),
+        ];
+        for path in &files {
+            fs::write(path, "test").unwrap();
+        }
+
+        let sanitized_root =
+            sanitize_directory_tree(&root, false, "_", SanitizeMode::Legacy)
+                .unwrap();
+
+        let expected_root = PathBuf::from(sanitized_filename(
+            root.to_str().unwrap(),
+            "_",
+            SanitizeMode::Legacy,
+        ));
+        let expected_child_one = PathBuf::from(sanitized_filename(
+            expected_root.join("Child One").to_str().unwrap(),
+            "_",
+            SanitizeMode::Legacy,
+        ));
+        let expected_child_two = PathBuf::from(sanitized_filename(
+            expected_root.join("Second & Child").to_str().unwrap(),
+            "_",
+            SanitizeMode::Legacy,
+        ));
+        let expected_grand_one = PathBuf::from(sanitized_filename(
+            expected_child_one.join("Grand Child(1)").to_str().unwrap(),
+            "_",
+            SanitizeMode::Legacy,
+        ));
+        let expected_grand_two = PathBuf::from(sanitized_filename(
+            expected_child_two.join("Grand (Final)").to_str().unwrap(),
+            "_",
+            SanitizeMode::Legacy,
+        ));
+
+        let expected_files = [
+            PathBuf::from(sanitized_filename(
+                expected_root.join("Root File?.txt").to_str().unwrap(),
+                "_",
+                SanitizeMode::Legacy,
+            )),
+            PathBuf::from(sanitized_filename(
+                expected_child_one.join("Clip (A).mov").to_str().unwrap(),
+                "_",
+                SanitizeMode::Legacy,
+            )),
+            PathBuf::from(sanitized_filename(
+                expected_child_one.join("Clip (B).mov").to_str().unwrap(),
+                "_",
+                SanitizeMode::Legacy,
+            )),
+            PathBuf::from(sanitized_filename(
+                expected_grand_one.join("Take #1.wav").to_str().unwrap(),
+                "_",
+                SanitizeMode::Legacy,
+            )),
+            PathBuf::from(sanitized_filename(
+                expected_child_two.join("Audio (Draft).wav").to_str().unwrap(),
+                "_",
+                SanitizeMode::Legacy,
+            )),
+            PathBuf::from(sanitized_filename(
+                expected_grand_two.join("Mix #2?.wav").to_str().unwrap(),
+                "_",
+                SanitizeMode::Legacy,
+            )),
+        ];
+
+        assert_eq!(sanitized_root, expected_root);
+        for dir in &[
+            &expected_child_one,
+            &expected_child_two,
+            &expected_grand_one,
+            &expected_grand_two,
+        ] {
+            assert!(dir.is_dir(), "expected directory {:?} to exist", dir);
+        }
+        for file in &expected_files {
+            assert!(file.is_file(), "expected file {:?} to exist", file);
+        }
+        for dir in &[&root, &child_one, &child_two, &grand_one, &grand_two] {
+            assert!(
+                !dir.exists(),
+                "expected original path {:?} to be gone",
+                dir
+            );
+        }
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn sanitize_directory_tree_handles_nonexistent_root() {
+        let tmp = temp_dir();
+        let missing = tmp.join("does_not_exist");
+
+        let result =
+            sanitize_directory_tree(&missing, false, "_", SanitizeMode::Legacy)
+                .unwrap();
+        assert_eq!(result, missing);
+        assert!(!missing.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn sanitize_directory_tree_sanitizes_single_file() {
+        let tmp = temp_dir();
+        let file = tmp.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let result =
+            sanitize_directory_tree(&file, false, "_", SanitizeMode::Legacy)
+                .unwrap();
+        let expected = tmp.join("file_name.txt");
+
+        assert_eq!(result, expected);
+        assert!(!file.exists());
+        assert!(expected.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn dry_run_does_not_rename() {
+        let tmp = temp_dir();
+        let file = tmp.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let desired = PathBuf::from(sanitized_filename(
+            file.to_str().unwrap(),
+            "_",
+            SanitizeMode::Legacy,
+        ));
+        let result = rename_path(&file, &desired, true).unwrap();
+
+        assert_eq!(result, desired);
+        assert!(file.exists());
+        assert!(!desired.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn rename_path_noop_when_old_equals_new() {
+        let tmp = temp_dir();
+        let path = tmp.join("same.txt");
+        fs::write(&path, "test").unwrap();
+
+        let result = rename_path(&path, &path, false).unwrap();
+
+        assert_eq!(result, path);
+        assert!(path.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn rename_path_skips_when_old_missing() {
+        let tmp = temp_dir();
+        let old = tmp.join("missing.txt");
+        let new_path = tmp.join("new.txt");
+
+        let result = rename_path(&old, &new_path, false).unwrap();
+
+        assert_eq!(result, old);
+        assert!(!old.exists());
+        assert!(!new_path.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn rename_path_skips_when_new_exists() {
+        let tmp = temp_dir();
+        let old = tmp.join("old.txt");
+        let new_path = tmp.join("new.txt");
+
+        fs::write(&old, "test").unwrap();
+        fs::write(&new_path, "other").unwrap();
+
+        let result = rename_path(&old, &new_path, false).unwrap();
+
+        assert_eq!(result, old);
+        assert!(old.exists());
+        assert!(new_path.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn rename_path_renames_when_possible() {
+        let tmp = temp_dir();
+        let old = tmp.join("old name.txt");
+        let new_path = tmp.join("new_name.txt");
+
+        fs::write(&old, "test").unwrap();
+
+        let result = rename_path(&old, &new_path, false).unwrap();
+
+        assert_eq!(result, new_path);
+        assert!(!old.exists());
+        assert!(new_path.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
     }
 
-    // Remove trailing "<replacement><extension>" (without dot) if present.
-    if !extension.is_empty() {
-        let suffix = format!("{replacement}{extension}");
-        if collapsed.ends_with(&suffix) {
-            let new_len = collapsed.len().saturating_sub(suffix.len());
-            collapsed.truncate(new_len);
+    #[test]
+    fn run_non_recursive_renames_target_files() {
+        let tmp = temp_dir();
+        let file = tmp.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let original = file.to_str().unwrap().to_string();
+        let config = Config {
+            recursive: false,
+            dry_run: false,
+            replacement: "_".to_string(),
+            targets: vec![original.clone().into()],
+            full_sanitize: false,
+            ..Config::default()
+        };
+
+        run(config).unwrap();
+
+        let expected_path = PathBuf::from(sanitized_filename(
+            &original,
+            "_",
+            SanitizeMode::Legacy,
+        ));
+        assert!(!file.exists());
+        assert!(expected_path.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn run_non_recursive_does_not_recurse_into_directories() {
+        let tmp = temp_dir();
+        let root = tmp.join("dir one");
+        let sub = root.join("sub dir");
+        fs::create_dir_all(&sub).unwrap();
+        let file = sub.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let root_str = root.to_str().unwrap().to_string();
+        let config = Config {
+            recursive: false,
+            dry_run: false,
+            replacement: "_".to_string(),
+            targets: vec![root_str.clone().into()],
+            full_sanitize: false,
+            ..Config::default()
+        };
+
+        run(config).unwrap();
+
+        let expected_root = PathBuf::from(sanitized_filename(
+            &root_str,
+            "_",
+            SanitizeMode::Legacy,
+        ));
+        assert!(!root.exists());
+        assert!(expected_root.exists());
+
+        let expected_sub = expected_root.join("sub dir");
+        let expected_file = expected_sub.join("file name.txt");
+        assert!(
+            expected_sub.is_dir(),
+            "expected non-recursive run to leave subdirectory name unchanged"
+        );
+        assert!(
+            expected_file.is_file(),
+            "expected non-recursive run to leave file name unchanged"
+        );
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn run_recursive_respects_dry_run() {
+        let tmp = temp_dir();
+        let root = tmp.join("dir one");
+        let sub = root.join("sub dir");
+        fs::create_dir_all(&sub).unwrap();
+        let file = sub.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let root_str = root.to_str().unwrap().to_string();
+        let config = Config {
+            recursive: true,
+            dry_run: true,
+            replacement: "_".to_string(),
+            targets: vec![root_str.clone().into()],
+            full_sanitize: false,
+            ..Config::default()
+        };
+
+        run(config).unwrap();
+
+        assert!(root.exists());
+        assert!(sub.exists());
+        assert!(file.exists());
+
+        let expected_root = PathBuf::from(sanitized_filename(
+            &root_str,
+            "_",
+            SanitizeMode::Legacy,
+        ));
+        assert!(!expected_root.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn run_with_args_help_returns_zero() {
+        let args = vec!["--help".to_string()];
+        let code = run_with_args(&args);
+        assert_eq!(code, 0);
+    }
+
+    #[test]
+    fn run_with_args_reports_missing_targets() {
+        let args: Vec<String> = Vec::new();
+        let code = run_with_args(&args);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn run_with_args_propagates_parse_error() {
+        let args = vec!["--unknown".to_string()];
+        let code = run_with_args(&args);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn run_with_args_successfully_sanitizes_file() {
+        let tmp = temp_dir();
+        let file = tmp.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+        let file_str = file.to_str().unwrap().to_string();
+        let args = vec!["--no-config".to_string(), file_str.clone()];
+
+        let code = run_with_args(&args);
+        assert_eq!(code, 0);
+
+        let expected = PathBuf::from(sanitized_filename(
+            &file_str,
+            "_",
+            SanitizeMode::Legacy,
+        ));
+        assert!(!file.exists());
+        assert!(expected.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn recursive_dry_run_does_not_rename() {
+        let tmp = temp_dir();
+        let root = tmp.join("dir one");
+        let sub = root.join("sub dir");
+        fs::create_dir_all(&sub).unwrap();
+        let file = sub.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let sanitized_root =
+            sanitize_directory_tree(&root, true, "_", SanitizeMode::Legacy)
+                .unwrap();
+        let expected_root = PathBuf::from(sanitized_filename(
+            root.to_str().unwrap(),
+            "_",
+            SanitizeMode::Legacy,
+        ));
+
+        assert_eq!(sanitized_root, expected_root);
+        assert!(root.exists());
+        assert!(!expected_root.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn option_terminator_handles_dash_prefixed_filename() {
+        let tmp = temp_dir();
+        let file = tmp.join("-file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let file_str = file.to_str().unwrap().to_string();
+        let args = vec![
+            "--dry-run".to_string(),
+            "--".to_string(),
+            file_str.clone(),
+        ];
+        let cfg = parse_args(&args).expect("parse_args failed");
+
+        assert!(cfg.dry_run);
+        assert_eq!(cfg.replacement, "_");
+        assert!(!cfg.full_sanitize);
+        assert_eq!(cfg.targets, vec![OsString::from(&file_str)]);
+
+        let desired = PathBuf::from(sanitized_filename(
+            &file_str,
+            &cfg.replacement,
+            SanitizeMode::Legacy,
+        ));
+        rename_path(Path::new(&file_str), &desired, cfg.dry_run).unwrap();
+
+        assert!(Path::new(&file_str).exists());
+        assert!(!desired.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[cfg(unix)]
+    fn os(bytes: &[u8]) -> OsString {
+        use std::os::unix::ffi::OsStrExt;
+        OsStr::from_bytes(bytes).to_os_string()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_are_skipped_by_default() {
+        let tmp = temp_dir();
+        let file = tmp.join(os(b"Caf\xe9 M\xfcller.mp3"));
+        fs::write(&file, "test").unwrap();
+
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        assert_eq!(sanitized_path(&file, &options), None);
+
+        let mut session =
+            RenameSession::new(false, ConflictPolicy::Skip, "_");
+        let result =
+            sanitize_directory_tree_with(&tmp, &options, &mut session)
+                .unwrap();
+        assert_eq!(result, tmp);
+        assert!(file.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_decode_legacy_encodings() {
+        let tmp = temp_dir();
+        let file = tmp.join(os(b"Caf\xe9 M\xfcller.mp3"));
+        fs::write(&file, "test").unwrap();
+
+        let latin1 = SanitizeOptions {
+            non_utf8: NonUtf8Policy::Latin1,
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        let expected = tmp.join("Café_Müller.mp3");
+        assert_eq!(sanitized_path(&file, &latin1), Some(expected.clone()));
+
+        let mut session =
+            RenameSession::new(false, ConflictPolicy::Skip, "_");
+        sanitize_directory_tree_with(&file, &latin1, &mut session).unwrap();
+        assert!(!file.exists());
+        assert!(expected.exists());
+
+        let cp1252 = SanitizeOptions {
+            non_utf8: NonUtf8Policy::Windows1252,
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        assert_eq!(
+            sanitized_path(&tmp.join(os(b"\x93quoted\x94 \x80")), &cp1252),
+            Some(tmp.join("“quoted”_€"))
+        );
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_replace_uses_replacement_character() {
+        let options = SanitizeOptions {
+            non_utf8: NonUtf8Policy::Replace,
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        let input = PathBuf::from(os(b"dir/\xff\xfeabc \x82def.txt"));
+        assert_eq!(
+            sanitized_path(&input, &options),
+            Some(PathBuf::from("dir/abc_def.txt"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_parent_directories_are_preserved() {
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        let input = PathBuf::from(os(b"\xe9t\xe9/file name.txt"));
+        let expected = PathBuf::from(os(b"\xe9t\xe9/file_name.txt"));
+        assert_eq!(sanitized_path(&input, &options), Some(expected));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_args_accepts_non_utf8_targets() {
+        let raw = os(b"caf\xe9.txt");
+        let args = vec![
+            OsString::from("--non-utf8"),
+            OsString::from("latin1"),
+            raw.clone(),
+        ];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.non_utf8, NonUtf8Policy::Latin1);
+        assert_eq!(cfg.targets, vec![raw]);
+    }
+
+    #[test]
+    fn parse_args_non_utf8_policies() {
+        let cases = [
+            ("skip", NonUtf8Policy::Skip),
+            ("replace", NonUtf8Policy::Replace),
+            ("latin1", NonUtf8Policy::Latin1),
+            ("cp1252", NonUtf8Policy::Windows1252),
+        ];
+        for (value, expected) in cases {
+            let args = vec![format!("--non-utf8={value}"), "f".to_string()];
+            let cfg = parse_args(&args).expect("parse_args failed");
+            assert_eq!(cfg.non_utf8, expected);
+        }
+
+        let args = vec!["--non-utf8".to_string(), "ebcdic".to_string()];
+        match parse_args(&args) {
+            Err(CliError::Message(msg)) => {
+                assert!(msg.contains("Invalid non-UTF-8 policy 'ebcdic'"))
+            }
+            _ => panic!("expected error for unknown policy"),
         }
     }
 
-    // Trim any leading or trailing replacement characters to avoid
-    // introducing sanitized names that start or end with them.
-    let trimmed = collapsed.trim_matches(replacement).to_string();
-    if trimmed.is_empty() && !collapsed.is_empty() {
-        // Preserve a single replacement character for inputs that were
-        // entirely replaced so the filename does not become empty.
-        collapsed.chars().next().into_iter().collect()
-    } else {
-        trimmed
+    #[test]
+    fn transliterate_mode_keeps_letters_readable() {
+        assert_eq!(
+            sanitized_filename(
+                "Café Müller.mp3",
+                "_",
+                SanitizeMode::Transliterate
+            ),
+            "Cafe_Muller.mp3"
+        );
+        assert_eq!(
+            sanitized_filename(
+                "music/Жанна Фриске – Ла-ла-ла.flac",
+                "_",
+                SanitizeMode::Transliterate
+            ),
+            "music/Zhanna_Friske_-_La-la-la.flac"
+        );
+        assert_eq!(
+            sanitized_filename(
+                "Straße 4×4 ©.txt",
+                "_",
+                SanitizeMode::Transliterate
+            ),
+            "Strasse_4x4_c.txt"
+        );
+        assert_eq!(
+            sanitized_filename(
+                "Ελληνικά.txt",
+                "_",
+                SanitizeMode::Transliterate
+            ),
+            "Ellinika.txt"
+        );
     }
-}
-
-pub fn sanitized_filename(
-    input_file: &str,
-    replacement: char,
-    mode: SanitizeMode,
-) -> String {
-    let extension = extract_extension(input_file);
 
-    let path = Path::new(input_file);
-    let fname_os: &OsStr = path.file_name().unwrap_or_else(|| OsStr::new(""));
-    let fname = fname_os.to_string_lossy();
-
-    let mut result =
-        sanitize_component(&fname, replacement, &extension, mode);
-
-    // Reattach any parent directories, if present.
-    if let Some(parent) = path.parent() {
-        let parent_str = parent.to_string_lossy();
-        if !parent_str.is_empty() && parent_str != "." {
-            let mut buf = PathBuf::from(parent_str.as_ref());
-            buf.push(&result);
-            result = buf.to_string_lossy().to_string();
+    #[test]
+    fn parse_args_transliterate_flags() {
+        for flag in ["-T", "--transliterate"] {
+            let args = vec![flag.to_string(), "file".to_string()];
+            let cfg = parse_args(&args).expect("parse_args failed");
+            assert!(cfg.transliterate);
+            assert!(!cfg.full_sanitize);
         }
     }
 
-    let mut final_path = result;
-    if !extension.is_empty() {
-        if !final_path.is_empty() {
-            final_path.push('.');
+    #[test]
+    fn rename_session_suffixes_names_taken_on_disk() {
+        let tmp = temp_dir();
+        let existing = tmp.join("a_b.txt");
+        let also_existing = tmp.join("a_b_1.txt");
+        let old = tmp.join("a b.txt");
+        for path in [&existing, &also_existing, &old] {
+            fs::write(path, "test").unwrap();
         }
-        final_path.push_str(&extension);
-    }
 
-    final_path
-}
+        let mut session =
+            RenameSession::new(false, ConflictPolicy::Suffix, "_");
+        let result = session.rename(&old, &existing).unwrap();
 
-pub fn rename_path(old: &Path, new: &Path, dry_run: bool) -> io::Result<PathBuf> {
-    let p = color::for_stdout();
-    if old == new {
-        println!(
-            "{}Old name and new name are the same for '{}'.  Not changing{}",
-            p.dim,
-            old.display(),
-            p.reset
-        );
-        return Ok(new.to_path_buf());
-    } else if !old.exists() {
-        println!(
-            "{}Old file name '{}' does not exist.  Skipping{}",
-            p.yellow,
-            old.display(),
-            p.reset
-        );
-        return Ok(old.to_path_buf());
-    } else if new.exists() && old != new {
-        println!(
-            "{}New file name '{}' already exists!  Skipping{}",
-            p.yellow,
-            new.display(),
-            p.reset
-        );
-        return Ok(old.to_path_buf());
-    }
-
-    let (action, action_color) = if dry_run {
-        ("Would change", p.cyan)
-    } else {
-        ("Changing", p.green)
-    };
-    println!(
-        "{action_color}{action}{reset} '{old_path}' to '{bold}{new_path}{reset}'",
-        action_color = action_color,
-        action = action,
-        reset = p.reset,
-        old_path = old.display(),
-        bold = p.bold,
-        new_path = new.display(),
-    );
+        assert_eq!(result, tmp.join("a_b_2.txt"));
+        assert!(!old.exists());
+        assert!(result.exists());
 
-    if !dry_run {
-        fs::rename(old, new)?;
-    }
+        let dir = tmp.join("some dir");
+        fs::create_dir(&dir).unwrap();
+        fs::create_dir(tmp.join("some_dir.x")).unwrap();
+        let result = session.rename(&dir, &tmp.join("some_dir.x")).unwrap();
+        assert_eq!(result, tmp.join("some_dir.x_1"));
 
-    Ok(new.to_path_buf())
-}
+        fs::remove_dir_all(tmp).unwrap();
+    }
 
-pub fn sanitize_directory_tree(
-    path: &Path,
-    dry_run: bool,
-    replacement: char,
-    mode: SanitizeMode,
-) -> io::Result<PathBuf> {
-    if !path.exists() {
-        let p = color::for_stdout();
-        println!(
-            "{}Old file name '{}' does not exist.  Skipping{}",
-            p.yellow,
-            path.display(),
-            p.reset
+    #[test]
+    fn rename_session_detects_collisions_between_planned_renames() {
+        let tmp = temp_dir();
+        let first = tmp.join("a b.txt");
+        let second = tmp.join("a,b.txt");
+        fs::write(&first, "one").unwrap();
+        fs::write(&second, "two").unwrap();
+        let target = tmp.join("a_b.txt");
+
+        let mut skip = RenameSession::new(true, ConflictPolicy::Skip, "_");
+        assert_eq!(skip.rename(&first, &target).unwrap(), target);
+        assert_eq!(skip.rename(&second, &target).unwrap(), second);
+
+        let mut suffix =
+            RenameSession::new(true, ConflictPolicy::Suffix, "-");
+        assert_eq!(suffix.rename(&first, &target).unwrap(), target);
+        assert_eq!(
+            suffix.rename(&second, &target).unwrap(),
+            tmp.join("a_b-1.txt")
         );
-        return Ok(path.to_path_buf());
+        assert!(first.exists());
+        assert!(second.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
     }
 
-    let meta = fs::symlink_metadata(path)?;
-    let file_type = meta.file_type();
+    #[test]
+    fn rename_session_overwrite_and_fail_policies() {
+        let tmp = temp_dir();
+        let old = tmp.join("old name.txt");
+        let new_path = tmp.join("old_name.txt");
+        fs::write(&old, "new contents").unwrap();
+        fs::write(&new_path, "old contents").unwrap();
 
-    if !(file_type.is_dir() && !file_type.is_symlink()) {
-        let new_name = sanitized_filename(
-            &path.to_string_lossy(),
-            replacement,
-            mode,
-        );
-        let new_path = PathBuf::from(new_name);
-        return rename_path(path, &new_path, dry_run);
-    }
+        let mut fail = RenameSession::new(false, ConflictPolicy::Fail, "_");
+        let err = fail.rename(&old, &new_path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(old.exists());
 
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
-        let child_path = entry.path();
-        let child_meta = fs::symlink_metadata(&child_path)?;
-        let child_type = child_meta.file_type();
+        let mut overwrite =
+            RenameSession::new(false, ConflictPolicy::Overwrite, "_");
+        assert_eq!(overwrite.rename(&old, &new_path).unwrap(), new_path);
+        assert!(!old.exists());
+        assert_eq!(fs::read_to_string(&new_path).unwrap(), "new contents");
 
-        if child_type.is_dir() && !child_type.is_symlink() {
-            sanitize_directory_tree(&child_path, dry_run, replacement, mode)?;
-        } else {
-            let new_name = sanitized_filename(
-                &child_path.to_string_lossy(),
-                replacement,
-                mode,
-            );
-            let new_path = PathBuf::from(new_name);
-            rename_path(&child_path, &new_path, dry_run)?;
-        }
+        fs::remove_dir_all(tmp).unwrap();
     }
 
-    let new_name =
-        sanitized_filename(&path.to_string_lossy(), replacement, mode);
-    let new_path = PathBuf::from(new_name);
-    rename_path(path, &new_path, dry_run)
-}
-
-fn run_with_args(args: &[String]) -> i32 {
-    let config = match parse_args(args) {
-        Ok(cfg) => cfg,
-        Err(CliError::Help) => {
-            let _ = print_usage(io::stdout(), color::for_stdout());
-            return 0;
-        }
-        Err(CliError::Message(msg)) => {
-            let e = color::for_stderr();
-            eprintln!("{}{}error:{} {msg}", e.bold, e.red, e.reset);
-            let _ = print_usage(io::stderr(), e);
-            return 1;
+    #[test]
+    fn run_overwrite_policy_keeps_first_of_colliding_sources() {
+        let tmp = temp_dir();
+        let root = tmp.join("root");
+        fs::create_dir_all(&root).unwrap();
+        for name in ["a b.txt", "a,b.txt", "a_b.txt"] {
+            fs::write(root.join(name), name).unwrap();
         }
-    };
 
-    if config.targets.is_empty() {
-        let e = color::for_stderr();
-        eprintln!(
-            "{}{}error:{} No files or directories specified",
-            e.bold, e.red, e.reset
-        );
-        let _ = print_usage(io::stderr(), e);
-        return 1;
-    }
+        let config = Config {
+            recursive: true,
+            targets: vec![root.clone().into()],
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Config::default()
+        };
+        run(config).unwrap();
 
-    if let Err(err) = run(config) {
-        let e = color::for_stderr();
-        eprintln!("{}{}error:{} {err}", e.bold, e.red, e.reset);
-        return 1;
+        // "a b.txt" replaces the file that was there; "a,b.txt" would
+        // replace it in turn, so it is skipped instead.
+        assert!(!root.join("a b.txt").exists());
+        let contents = |name| fs::read_to_string(root.join(name)).unwrap();
+        assert_eq!(contents("a_b.txt"), "a b.txt");
+        assert_eq!(contents("a,b.txt"), "a,b.txt");
+
+        fs::remove_dir_all(tmp).unwrap();
     }
 
-    0
-}
+    #[test]
+    fn run_suffix_policy_sanitizes_whole_tree() {
+        let tmp = temp_dir();
+        let root = tmp.join("root");
+        fs::create_dir_all(&root).unwrap();
+        for name in ["a b.txt", "a,b.txt", "a;b.txt"] {
+            fs::write(root.join(name), name).unwrap();
+        }
 
-pub fn run_from_env() -> i32 {
-    let args: Vec<String> = env::args().skip(1).collect();
-    run_with_args(&args)
-}
+        let config = Config {
+            recursive: true,
+            targets: vec![root.clone().into()],
+            on_conflict: ConflictPolicy::Suffix,
+            ..Config::default()
+        };
+        run(config).unwrap();
 
-pub fn run(config: Config) -> io::Result<()> {
-    let mode = if config.full_sanitize {
-        SanitizeMode::Full
-    } else {
-        SanitizeMode::Legacy
-    };
+        let mut names: Vec<String> = fs::read_dir(&root)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["a_b.txt", "a_b_1.txt", "a_b_2.txt"]);
 
-    for target in &config.targets {
-        let path = Path::new(target);
-        if config.recursive {
-            let _ = sanitize_directory_tree(
-                path,
-                config.dry_run,
-                config.replacement,
-                mode,
-            )?;
-        } else {
-            let new_name =
-                sanitized_filename(target, config.replacement, mode);
-            let new_path = PathBuf::from(new_name);
-            let _ = rename_path(path, &new_path, config.dry_run)?;
-        }
+        fs::remove_dir_all(tmp).unwrap();
     }
 
-    Ok(())
-}
+    #[test]
+    fn run_fail_policy_returns_error() {
+        let tmp = temp_dir();
+        fs::write(tmp.join("a b.txt"), "one").unwrap();
+        fs::write(tmp.join("a_b.txt"), "two").unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::time::{SystemTime, UNIX_EPOCH};
+        let args = vec![
+            "--no-config".to_string(),
+            "--on-conflict".to_string(),
+            "fail".to_string(),
+            tmp.join("a b.txt").to_str().unwrap().to_string(),
+        ];
+        assert_eq!(run_with_args(&args), 1);
+        assert!(tmp.join("a b.txt").exists());
 
-    fn temp_dir() -> PathBuf {
-        let mut base = env::temp_dir();
-        let unique = format!(
-            "sanitize_filenames_test_{}_{}",
-            std::process::id(),
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
-        );
-        base.push(unique);
-        fs::create_dir_all(&base).unwrap();
-        base
+        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn print_usage_includes_sections() {
-        let mut buf: Vec<u8> = Vec::new();
-        print_usage(&mut buf, Palette::plain()).expect("print_usage failed");
-        let output = String::from_utf8(buf).expect("usage is valid UTF-8");
-        assert!(output.contains("Usage: sanitize_filenames [options] [FILES...]"));
-        assert!(output.contains("Options:"));
-        assert!(output.contains("Examples:"));
+    fn parse_args_on_conflict_policies() {
+        let args = vec!["--on-conflict=suffix".to_string(), "f".to_string()];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.on_conflict, ConflictPolicy::Suffix);
+
+        let args = vec!["--on-conflict".to_string(), "merge".to_string()];
+        match parse_args(&args) {
+            Err(CliError::Message(msg)) => {
+                assert!(msg.contains("Invalid conflict policy 'merge'"))
+            }
+            _ => panic!("expected error for unknown conflict policy"),
+        }
     }
 
     #[test]
-    fn print_usage_emits_ansi_when_palette_is_ansi() {
-        let mut buf: Vec<u8> = Vec::new();
-        print_usage(&mut buf, Palette::ansi()).expect("print_usage failed");
-        let output = String::from_utf8(buf).expect("usage is valid UTF-8");
-        assert!(output.contains("\x1b["), "expected ANSI escape codes");
+    fn journal_and_undo_restore_original_names() {
+        let tmp = temp_dir();
+        let root = tmp.join("dir one");
+        let sub = root.join("sub dir");
+        fs::create_dir_all(&sub).unwrap();
+        let file = sub.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+        let journal_path = tmp.join("state").join("journal.tsv");
+
+        let config = Config {
+            recursive: true,
+            targets: vec![root.clone().into()],
+            journal: Some(journal_path.clone()),
+            ..Config::default()
+        };
+        run(config).unwrap();
+
+        assert!(!root.exists());
+        assert!(tmp.join("dir_one/sub_dir/file_name.txt").exists());
+        assert_eq!(journal::read_journal(&journal_path).unwrap().len(), 3);
+
+        let args = vec![
+            "--undo".to_string(),
+            journal_path.to_str().unwrap().to_string(),
+        ];
+        assert_eq!(run_with_args(&args), 0);
+
+        assert!(file.exists());
+        assert!(!tmp.join("dir_one").exists());
+
+        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn print_usage_omits_ansi_when_palette_is_plain() {
-        let mut buf: Vec<u8> = Vec::new();
-        print_usage(&mut buf, Palette::plain()).expect("print_usage failed");
-        let output = String::from_utf8(buf).expect("usage is valid UTF-8");
-        assert!(!output.contains('\x1b'), "plain palette must not emit escapes");
+    fn undo_skips_entries_whose_old_name_is_taken() {
+        let tmp = temp_dir();
+        let old = tmp.join("a b.txt");
+        fs::write(&old, "original").unwrap();
+        let journal_path = tmp.join("journal.tsv");
+
+        let mut session =
+            RenameSession::new(false, ConflictPolicy::Skip, "_");
+        session.set_journal(Journal::new(&journal_path));
+        let new_path = session.rename(&old, &tmp.join("a_b.txt")).unwrap();
+        assert_eq!(session.journal().unwrap().len(), 1);
+
+        fs::write(&old, "reused").unwrap();
+        let mut undo = RenameSession::new(false, ConflictPolicy::Skip, "_");
+        undo_journal(&journal_path, &mut undo).unwrap();
+
+        assert_eq!(fs::read_to_string(&old).unwrap(), "reused");
+        assert!(new_path.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn has_dot_recognizes_periods() {
-        assert!(!has_dot("file"));
-        assert!(has_dot("file.txt"));
-        assert!(has_dot(".hidden"));
-        assert!(has_dot("a.b.c"));
+    fn undo_never_overwrites_or_suffixes_reused_names() {
+        for policy in [ConflictPolicy::Suffix, ConflictPolicy::Overwrite] {
+            let tmp = temp_dir();
+            let old = tmp.join("a b.txt");
+            fs::write(&old, "original").unwrap();
+            let journal_path = tmp.join("journal.tsv");
+
+            let mut session = RenameSession::new(false, policy, "_");
+            session.set_journal(Journal::new(&journal_path));
+            let new_path = session.rename(&old, &tmp.join("a_b.txt")).unwrap();
+
+            fs::write(&old, "reused").unwrap();
+            let config = Config {
+                undo: Some(journal_path.clone()),
+                on_conflict: policy,
+                ..Config::default()
+            };
+            run(config).unwrap();
+
+            assert_eq!(fs::read_to_string(&old).unwrap(), "reused");
+            assert_eq!(fs::read_to_string(&new_path).unwrap(), "original");
+            assert!(!tmp.join("a b_1.txt").exists());
+
+            fs::remove_dir_all(tmp).unwrap();
+        }
     }
 
     #[test]
-    fn is_hidden_detects_leading_dot_only() {
-        assert!(is_hidden(".gitignore"));
-        assert!(!is_hidden("file"));
-        assert!(!is_hidden("dir/.git"));
+    fn dry_run_does_not_write_journal() {
+        let tmp = temp_dir();
+        let file = tmp.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+        let journal_path = tmp.join("journal.tsv");
+
+        let config = Config {
+            dry_run: true,
+            targets: vec![file.clone().into()],
+            journal: Some(journal_path.clone()),
+            ..Config::default()
+        };
+        run(config).unwrap();
+
+        assert!(file.exists());
+        assert!(!journal_path.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn is_directory_matches_filesystem() {
-        let base = temp_dir();
-        let dir = base.join("dir");
-        let file = base.join("file.txt");
+    fn parse_args_journal_and_undo() {
+        let args = vec!["--journal=/tmp/j.tsv".to_string(), "f".to_string()];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.journal, Some(PathBuf::from("/tmp/j.tsv")));
 
-        fs::create_dir_all(&dir).unwrap();
+        let cfg = parse_args(&["--journal", "j.tsv", "dir"]).unwrap();
+        assert_eq!(cfg.journal, Some(PathBuf::from("j.tsv")));
+        assert_eq!(cfg.targets, [OsString::from("dir")]);
+        assert!(parse_args(&["--journal"]).is_err());
+
+        let args = vec!["--undo".to_string(), "/tmp/j.tsv".to_string()];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.undo, Some(PathBuf::from("/tmp/j.tsv")));
+        assert!(cfg.targets.is_empty());
+
+        let args = vec!["--undo".to_string()];
+        match parse_args(&args) {
+            Err(CliError::Message(msg)) => {
+                assert!(msg.contains("Option '--undo' requires an argument"))
+            }
+            _ => panic!("expected error for missing --undo argument"),
+        }
+
+        let args = vec!["--undo=".to_string()];
+        match parse_args(&args) {
+            Err(CliError::Message(msg)) => {
+                assert!(msg.contains("Option '--undo=' requires a file name"))
+            }
+            _ => panic!("expected error for empty --undo value"),
+        }
+
+        for extra in [vec!["f"], vec!["--from-file", "list.txt"]] {
+            let mut args = vec!["--undo", "/tmp/j.tsv"];
+            args.extend(extra);
+            match parse_args(&args) {
+                Err(CliError::Message(msg)) => assert!(
+                    msg.contains("'--undo' cannot be combined with targets")
+                ),
+                _ => panic!("expected error for targets with --undo"),
+            }
+        }
+    }
+
+    #[test]
+    fn unwritable_journal_stops_before_renaming() {
+        let tmp = temp_dir();
+        let file = tmp.join("file name.txt");
         fs::write(&file, "test").unwrap();
+        // A regular file where the journal's directory should be.
+        fs::write(tmp.join("state"), "").unwrap();
 
-        assert!(is_directory(dir.to_str().unwrap()));
-        assert!(!is_directory(file.to_str().unwrap()));
+        let config = Config {
+            targets: vec![file.clone().into()],
+            journal: Some(tmp.join("state").join("journal.tsv")),
+            ..Config::default()
+        };
+        let err = run(config).unwrap_err();
+
+        assert!(err.to_string().contains("Cannot open journal"));
+        assert!(file.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn build_plan_lists_children_before_parents_without_renaming() {
+        let tmp = temp_dir();
+        let root = tmp.join("dir one");
+        let sub = root.join("sub dir");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(sub.join("file name.txt"), "test").unwrap();
 
-        fs::remove_dir_all(base).unwrap();
-    }
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        let mut planner = Planner::new(ConflictPolicy::Skip, "_");
+        let plan = build_plan(&[&root], true, &options, &mut planner).unwrap();
 
-    #[test]
-    fn has_extension_ignores_dirs_and_hidden_files() {
-        let base = temp_dir();
-        let dir_with_dot = base.join("dir.with.dot");
-        fs::create_dir_all(&dir_with_dot).unwrap();
+        let renames: Vec<(PathBuf, PathBuf)> = plan
+            .renames()
+            .map(|op| (op.from.clone(), op.to.clone()))
+            .collect();
+        assert_eq!(
+            renames,
+            vec![
+                (sub.join("file name.txt"), sub.join("file_name.txt")),
+                (sub.clone(), root.join("sub_dir")),
+                (root.clone(), tmp.join("dir_one")),
+            ]
+        );
+        assert!(sub.join("file name.txt").exists());
+        assert_eq!(plan.validate(), Ok(()));
 
-        assert!(!has_extension(dir_with_dot.to_str().unwrap()));
-        assert!(has_extension("file.txt"));
-        assert!(has_extension("archive.tar.gz"));
-        assert!(!has_extension(".gitignore"));
+        let mut session = RenameSession::new(false, ConflictPolicy::Skip, "_");
+        apply_plan(&plan, &mut session).unwrap();
+        assert!(tmp.join("dir_one/sub_dir/file_name.txt").exists());
 
-        fs::remove_dir_all(base).unwrap();
+        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn extract_extension_handles_files_and_dirs() {
-        let base = temp_dir();
-        let dir_with_dot = base.join("dir.with.dot");
-        fs::create_dir_all(&dir_with_dot).unwrap();
+    fn apply_plan_rejects_stale_plans() {
+        let tmp = temp_dir();
+        let file = tmp.join("file name.txt");
+        fs::write(&file, "test").unwrap();
+
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        let mut planner = Planner::new(ConflictPolicy::Skip, "_");
+        let plan = build_plan(&[&file], false, &options, &mut planner).unwrap();
+        fs::remove_file(&file).unwrap();
 
-        assert_eq!(extract_extension("file.txt"), "txt");
-        assert_eq!(extract_extension("archive.tar.gz"), "gz");
         assert_eq!(
-            extract_extension(
-                &format!("{}/{}", dir_with_dot.to_string_lossy(), "file.dat")
-            ),
-            "dat"
+            plan.validate(),
+            Err(vec![PlanError::Missing(file.clone())])
         );
-        assert_eq!(extract_extension(dir_with_dot.to_str().unwrap()), "");
-        assert_eq!(extract_extension(".gitignore"), "");
+        let mut session = RenameSession::new(false, ConflictPolicy::Skip, "_");
+        let err = apply_plan(&plan, &mut session).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(!tmp.join("file_name.txt").exists());
 
-        fs::remove_dir_all(base).unwrap();
+        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn sanitize_component_collapses_repeated_replacements() {
-        let result = sanitize_component(
-            "Hello   World",
-            '_',
-            "",
-            SanitizeMode::Legacy,
+    fn build_plan_records_skips_and_unchanged_names() {
+        let tmp = temp_dir();
+        let clean = tmp.join("clean.txt");
+        let dirty = tmp.join("a b.txt");
+        let taken = tmp.join("a_b.txt");
+        for path in [&clean, &dirty, &taken] {
+            fs::write(path, "test").unwrap();
+        }
+        let missing = tmp.join("missing file.txt");
+
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        let mut planner = Planner::new(ConflictPolicy::Skip, "_");
+        let plan = build_plan(
+            &[&clean, &dirty, &missing],
+            false,
+            &options,
+            &mut planner,
+        )
+        .unwrap();
+
+        let kinds: Vec<RenameKind> =
+            plan.ops.iter().map(|op| op.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                RenameKind::Unchanged,
+                RenameKind::Skip(SkipReason::Exists),
+                RenameKind::Skip(SkipReason::Missing),
+            ]
         );
-        assert_eq!(result, "Hello_World");
+        assert_eq!(plan.ops[1].to, taken);
+        assert_eq!(plan.renames().count(), 0);
+
+        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn sanitize_component_maps_special_characters_and_trailing_extension() {
-        let result = sanitize_component(
-            "August Gold Q&A Audio.m4a.wav",
-            '_',
-            "wav",
-            SanitizeMode::Legacy,
+    fn session_reports_events_to_custom_reporter() {
+        use crate::output::Action;
+
+        #[derive(Default)]
+        struct Collect(Vec<(PathBuf, Action)>);
+
+        impl Reporter for Collect {
+            fn report(&mut self, event: &Event<'_>) -> io::Result<()> {
+                self.0.push((event.op.from.clone(), event.action()));
+                Ok(())
+            }
+        }
+
+        let tmp = temp_dir();
+        let root = tmp.join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("file name.txt"), "test").unwrap();
+
+        let mut collect = Collect::default();
+        let mut session = RenameSession::new(false, ConflictPolicy::Skip, "_");
+        session.set_reporter(&mut collect);
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        sanitize_directory_tree_with(&root, &options, &mut session).unwrap();
+        drop(session);
+
+        assert_eq!(
+            collect.0,
+            vec![
+                (root.join("file name.txt"), Action::Renamed),
+                (root.clone(), Action::Unchanged),
+            ]
         );
-        assert_eq!(result, "August_Gold_Q_A_Audio_m4a");
-    }
+        assert!(root.join("file_name.txt").exists());
 
-    #[test]
-    fn sanitize_component_maps_multiplication_sign() {
-        let result =
-            sanitize_component("size 4×4", '_', "", SanitizeMode::Legacy);
-        assert_eq!(result, "size_4x4");
+        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn parse_args_sets_flags_and_targets() {
-        let args = vec![
-            "-r".to_string(),
-            "--dry-run".to_string(),
-            "file1".to_string(),
-            ".".to_string(),
-            "..".to_string(),
-            "dir2".to_string(),
-        ];
-        let cfg = parse_args(&args).expect("parse_args failed");
-        assert!(cfg.recursive);
-        assert!(cfg.dry_run);
-        assert_eq!(cfg.replacement, '_');
-        assert!(!cfg.full_sanitize);
-        assert_eq!(cfg.targets, vec!["file1".to_string(), "dir2".to_string()]);
-    }
+    fn plan_problems_are_reported_as_error_records() {
+        use crate::output::StructuredReporter;
 
-    #[test]
-    fn parse_args_replacement_forms() {
-        let args_short = vec!["-c".to_string(), "+".to_string(), "file".to_string()];
-        let cfg_short = parse_args(&args_short).expect("parse_args failed");
-        assert_eq!(cfg_short.replacement, '+');
-        assert_eq!(cfg_short.targets, vec!["file".to_string()]);
-        assert!(!cfg_short.full_sanitize);
+        let tmp = temp_dir();
+        let a = tmp.join("a");
+        let b = tmp.join("b");
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+        let plan = RenamePlan {
+            ops: vec![
+                RenameOp {
+                    from: a.clone(),
+                    to: b.clone(),
+                    kind: RenameKind::Rename,
+                },
+                RenameOp {
+                    from: b.clone(),
+                    to: tmp.join("c"),
+                    kind: RenameKind::Rename,
+                },
+            ],
+            links: Vec::new(),
+        };
 
-        let args_short_inline = vec!["-c+".to_string(), "file".to_string()];
-        let cfg_short_inline =
-            parse_args(&args_short_inline).expect("parse_args failed");
-        assert_eq!(cfg_short_inline.replacement, '+');
-        assert_eq!(cfg_short_inline.targets, vec!["file".to_string()]);
-        assert!(!cfg_short_inline.full_sanitize);
+        let mut reporter =
+            StructuredReporter::new(Vec::new(), OutputFormat::Ndjson);
+        let mut session = RenameSession::new(false, ConflictPolicy::Skip, "_");
+        session.set_reporter(&mut reporter);
+        assert!(apply_plan(&plan, &mut session).is_err());
+        drop(session);
 
-        let args_long_inline =
-            vec!["--replacement=+".to_string(), "file".to_string()];
-        let cfg_long_inline =
-            parse_args(&args_long_inline).expect("parse_args failed");
-        assert_eq!(cfg_long_inline.replacement, '+');
-        assert_eq!(cfg_long_inline.targets, vec!["file".to_string()]);
-        assert!(!cfg_long_inline.full_sanitize);
+        let out = String::from_utf8(reporter.into_inner()).unwrap();
+        assert_eq!(out.lines().count(), 1, "unexpected output: {out}");
+        assert!(out.contains("\"action\":\"error\""));
+        assert!(out.contains("before it is renamed"));
+        assert!(a.exists() && b.exists());
+
+        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn parse_args_missing_replacement_argument() {
-        let args_short = vec!["-c".to_string()];
-        match parse_args(&args_short) {
-            Err(CliError::Message(msg)) => {
-                assert!(msg.contains("Option '-c' requires an argument"))
-            }
-            _ => panic!("expected error for missing -c argument"),
+    fn parse_args_output_formats() {
+        for (value, expected) in [
+            ("human", OutputFormat::Human),
+            ("json", OutputFormat::Json),
+            ("ndjson", OutputFormat::Ndjson),
+            ("tsv", OutputFormat::Tsv),
+        ] {
+            let args = vec!["--format".to_string(), value.to_string()];
+            let cfg = parse_args(&args).expect("parse_args failed");
+            assert_eq!(cfg.format, expected);
         }
 
-        let args_long = vec!["--replacement".to_string()];
-        match parse_args(&args_long) {
+        let args = vec!["--format=xml".to_string()];
+        match parse_args(&args) {
             Err(CliError::Message(msg)) => {
-                assert!(msg.contains("Option '--replacement' requires an argument"))
+                assert!(msg.contains("Invalid output format 'xml'"))
             }
-            _ => panic!("expected error for missing --replacement argument"),
+            _ => panic!("expected error for unknown format"),
         }
     }
 
     #[test]
-    fn parse_args_unknown_option_yields_error() {
-        let args = vec!["--unknown".to_string()];
-        match parse_args(&args) {
-            Err(CliError::Message(msg)) => {
-                assert!(msg.contains("Unknown option: --unknown"))
-            }
-            _ => panic!("expected error for unknown option"),
-        }
+    fn sanitized_path_applies_target_fs_rules() {
+        let options = SanitizeOptions {
+            target_fs: Some(TargetFs::Windows),
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        let sanitize =
+            |name: &str| sanitized_path(Path::new(name), &options).unwrap();
+
+        assert_eq!(sanitize("a<b>.txt"), PathBuf::from("a_b_.txt"));
+        assert_eq!(sanitize("dir/con.txt"), PathBuf::from("dir/con_.txt"));
+        assert_eq!(
+            sanitize("Report|2024.pdf"),
+            PathBuf::from("Report_2024.pdf")
+        );
+        // "t|t" does not count as an extension, so it joins the stem.
+        assert_eq!(sanitize("notes.t|t"), PathBuf::from("notes_t_t"));
     }
 
     #[test]
-    fn parse_args_allows_single_dash_target() {
-        let args = vec!["-".to_string()];
+    fn parse_args_target_fs() {
+        let args = vec!["--target", "windows", "f"];
         let cfg = parse_args(&args).expect("parse_args failed");
-        assert!(!cfg.full_sanitize);
-        assert_eq!(cfg.targets, vec!["-".to_string()]);
-    }
+        assert_eq!(cfg.target_fs, Some(TargetFs::Windows));
 
-    #[test]
-    fn parse_args_full_sanitize_flags() {
-        let args = vec!["--full-sanitize".to_string(), "file".to_string()];
+        let args = vec!["--target=s3", "f"];
         let cfg = parse_args(&args).expect("parse_args failed");
-        assert!(cfg.full_sanitize);
-        assert_eq!(cfg.targets, vec!["file".to_string()]);
+        assert_eq!(cfg.target_fs, Some(TargetFs::S3));
 
-        let args_short = vec!["-F".to_string(), "other".to_string()];
-        let cfg_short = parse_args(&args_short).expect("parse_args failed");
-        assert!(cfg_short.full_sanitize);
-        assert_eq!(cfg_short.targets, vec!["other".to_string()]);
+        let args = vec!["--target", "ntfs", "f"];
+        assert!(matches!(parse_args(&args), Err(CliError::Message(_))));
+
+        let args = vec!["--target", "windows", "-c", ":", "f"];
+        match parse_args(&args) {
+            Err(CliError::Message(msg)) => {
+                assert!(msg.contains("not allowed on windows"))
+            }
+            _ => panic!("expected error for replacement forbidden by target"),
+        }
     }
 
     #[test]
-    fn sanitized_basic_cases() {
-        assert_eq!(
-            sanitized_filename("×", '_', SanitizeMode::Legacy),
-            "x"
-        );
-        assert_eq!(
-            sanitized_filename("Hello", '_', SanitizeMode::Legacy),
-            "Hello"
-        );
+    fn sanitized_path_truncates_to_max_length() {
+        let options = SanitizeOptions {
+            max_length: Some(LengthLimit::new(17)),
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        let title = "How to: Train Your Dragon (Full Guide).html";
         assert_eq!(
-            sanitized_filename("hello.wav", '_', SanitizeMode::Legacy),
-            "hello.wav"
+            sanitized_path(Path::new(title), &options),
+            Some(PathBuf::from("How_to_Train.html"))
         );
         assert_eq!(
-            sanitized_filename("Hello World", '_', SanitizeMode::Legacy),
-            "Hello_World"
+            sanitized_path(Path::new("dir/short.txt"), &options),
+            Some(PathBuf::from("dir/short.txt"))
         );
+    }
+
+    #[test]
+    fn collision_suffixes_respect_max_length() {
+        let tmp = temp_dir();
+        fs::write(tmp.join("abcdefghij klm.txt"), "a").unwrap();
+        fs::write(tmp.join("abcdefghij,klm.txt"), "b").unwrap();
+        let options = SanitizeOptions {
+            max_length: Some(LengthLimit::new(12)),
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        let mut planner = Planner::new(ConflictPolicy::Suffix, "_");
+        let plan = build_plan(
+            &[tmp.join("abcdefghij klm.txt"), tmp.join("abcdefghij,klm.txt")],
+            false,
+            &options,
+            &mut planner,
+        )
+        .unwrap();
+
+        let names: Vec<_> =
+            plan.ops.iter().map(|op| op.to.file_name().unwrap()).collect();
+        assert_eq!(names, ["abcdefgh.txt", "abcdef_1.txt"]);
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_directories_only_skip_their_own_renames() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = temp_dir();
+        let locked = tmp.join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(locked.join("a b.txt"), "").unwrap();
+        fs::write(tmp.join("c d.txt"), "").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o555))
+            .unwrap();
+
+        let config = Config {
+            targets: vec![
+                locked.join("a b.txt").into(),
+                tmp.join("c d.txt").into(),
+            ],
+            ..Config::default()
+        };
+        let result = run(config);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))
+            .unwrap();
+
+        result.unwrap();
+        assert!(locked.join("a b.txt").exists());
+        assert!(tmp.join("c_d.txt").exists());
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn build_plan_skips_paths_over_max_path_length() {
+        let tmp = temp_dir();
+        let root = tmp.join("a  dir");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("some file.txt"), "test").unwrap();
+
+        let full_len = std::path::absolute(tmp.join("a_dir/some_file.txt"))
+            .unwrap()
+            .as_os_str()
+            .len();
+        let options = SanitizeOptions {
+            max_path_length: Some(full_len),
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        let mut planner = Planner::new(ConflictPolicy::Skip, "_");
+        let plan = build_plan(&[&root], true, &options, &mut planner).unwrap();
+
+        // The length is checked under the renamed parent, which is one
+        // byte shorter than "a  dir".
+        assert_eq!(plan.ops[0].kind, RenameKind::Rename);
+
+        let options = SanitizeOptions {
+            max_path_length: Some(full_len - 1),
+            ..options
+        };
+        let mut planner = Planner::new(ConflictPolicy::Skip, "_");
+        let plan = build_plan(&[&root], true, &options, &mut planner).unwrap();
         assert_eq!(
-            sanitized_filename("Hello.World", '_', SanitizeMode::Legacy),
-            "Hello.World"
+            plan.ops[0].kind,
+            RenameKind::Skip(SkipReason::PathTooLong)
         );
+        assert_eq!(plan.ops[1].kind, RenameKind::Rename);
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn parse_args_length_limits() {
+        let args = vec![
+            "--max-length",
+            "100",
+            "--length-unit=chars",
+            "--truncate-hash",
+            "--max-path-length=4096",
+            "f",
+        ];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.max_length, Some(100));
+        assert_eq!(cfg.length_unit, LengthUnit::Chars);
+        assert!(cfg.truncate_hash);
+        assert_eq!(cfg.max_path_length, Some(4096));
+
+        for bad in ["0", "-5", "lots"] {
+            let args = vec!["--max-length", bad, "f"];
+            match parse_args(&args) {
+                Err(CliError::Message(msg)) => {
+                    assert!(msg.contains("requires a positive number"))
+                }
+                _ => panic!("expected error for --max-length {bad}"),
+            }
+        }
+    }
+
+    #[test]
+    fn sanitized_path_applies_case_to_stem_only() {
+        let sanitize = |name: &str, case: CaseStyle| {
+            let options = SanitizeOptions {
+                case,
+                ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+            };
+            sanitized_path(Path::new(name), &options).unwrap()
+        };
+
         assert_eq!(
-            sanitized_filename("hello world.wav", '_', SanitizeMode::Legacy),
-            "hello_world.wav"
+            sanitize("dir/My HolidayPhotos 2024.JPG", CaseStyle::Kebab),
+            PathBuf::from("dir/my-holiday-photos-2024.JPG")
         );
         assert_eq!(
-            sanitized_filename("Hello.world.wav", '_', SanitizeMode::Legacy),
-            "Hello_world.wav"
+            sanitize("Quarterly Report (final).csv", CaseStyle::Snake),
+            PathBuf::from("quarterly_report_final.csv")
         );
         assert_eq!(
-            sanitized_filename("hello? + world.wav", '_', SanitizeMode::Legacy),
-            "hello_+_world.wav"
+            sanitize("some file.TXT", CaseStyle::Upper),
+            PathBuf::from("SOME_FILE.TXT")
         );
         assert_eq!(
-            sanitized_filename(
-                "Bart_banner_14_5_×_2_5_in.png",
-                '_',
-                SanitizeMode::Legacy
-            ),
-            "Bart_banner_14_5_x_2_5_in.png"
+            sanitize("some file.txt", CaseStyle::Camel),
+            PathBuf::from("someFile.txt")
         );
         assert_eq!(
-            sanitized_filename(
-                "hello? &&*()#@+ world.wav",
-                '_',
-                SanitizeMode::Legacy
-            ),
-            "hello_@+_world.wav"
+            sanitize(".hidden name", CaseStyle::Title),
+            PathBuf::from("Hidden_Name")
         );
+    }
+
+    #[test]
+    fn parse_args_case_styles() {
+        let args = vec!["--case", "kebab", "f"];
+        assert_eq!(parse_args(&args).unwrap().case, CaseStyle::Kebab);
+        let args = vec!["--case=title", "f"];
+        assert_eq!(parse_args(&args).unwrap().case, CaseStyle::Title);
+        let args = vec!["--case", "shouty", "f"];
+        assert!(matches!(parse_args(&args), Err(CliError::Message(_))));
+    }
+
+    #[test]
+    fn case_only_renames_are_not_collisions() {
+        let tmp = temp_dir();
+        let file = tmp.join("Holiday Photo.JPG");
+        fs::write(&file, "test").unwrap();
+
+        let options = SanitizeOptions {
+            case: CaseStyle::Lower,
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        for fold_case in [None, Some(true)] {
+            let mut planner = Planner::new(ConflictPolicy::Skip, "_");
+            planner.fold_case = fold_case;
+            let plan =
+                build_plan(&[&file], false, &options, &mut planner).unwrap();
+            assert_eq!(plan.ops[0].kind, RenameKind::Rename);
+        }
+
+        let mut session = RenameSession::new(false, ConflictPolicy::Skip, "_");
+        session.set_reporter(report::QuietReporter);
+        session.planner.fold_case = Some(true);
+        let file = tmp.join("Photo.JPG");
+        fs::write(&file, "test").unwrap();
+        let renamed = session.rename(&file, &tmp.join("photo.JPG")).unwrap();
+        assert_eq!(renamed, tmp.join("photo.JPG"));
+        let names: Vec<_> = fs::read_dir(&tmp)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .filter(|n| n.eq_ignore_ascii_case("photo.jpg"))
+            .collect();
+        assert_eq!(names, vec![OsString::from("photo.JPG")]);
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn folded_collisions_are_detected_for_case_insensitive_targets() {
+        let tmp = temp_dir();
+        let targets = [
+            tmp.join("my notes.txt"),
+            tmp.join("My notes.txt"),
+            tmp.join("report .txt"),
+        ];
+        for path in &targets {
+            fs::write(path, "test").unwrap();
+        }
+        fs::write(tmp.join("Report.txt"), "test").unwrap();
+
+        let options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        let kinds = |fold_case| {
+            let mut planner = Planner::new(ConflictPolicy::Skip, "_");
+            planner.fold_case = Some(fold_case);
+            let plan =
+                build_plan(&targets, false, &options, &mut planner).unwrap();
+            plan.ops.iter().map(|op| op.kind).collect::<Vec<_>>()
+        };
+
+        assert_eq!(kinds(false), vec![RenameKind::Rename; 3]);
         assert_eq!(
-            sanitized_filename(
-                "August Gold Q&A Audio.m4a.wav",
-                '_',
-                SanitizeMode::Legacy
-            ),
-            "August_Gold_Q_A_Audio_m4a.wav"
+            kinds(true),
+            vec![
+                RenameKind::Rename,
+                RenameKind::Skip(SkipReason::Exists),
+                RenameKind::Skip(SkipReason::Exists),
+            ]
         );
+
+        fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn sanitized_path_keeps_compound_extensions() {
+        let sanitize = |name: &str, options: &SanitizeOptions| {
+            sanitized_path(Path::new(name), options).unwrap()
+        };
+        let mut options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+
         assert_eq!(
-            sanitized_filename(
-                "nested/dir/file name.txt",
-                '_',
-                SanitizeMode::Legacy
-            ),
-            "nested/dir/file_name.txt"
+            sanitize("My Backup.tar.gz", &options),
+            PathBuf::from("My_Backup.tar.gz")
         );
         assert_eq!(
-            sanitized_filename(
-                "/absolute/path/Hello World.txt",
-                '_',
-                SanitizeMode::Legacy
-            ),
-            "/absolute/path/Hello_World.txt"
+            sanitize("jquery 3.7.min.js", &options),
+            PathBuf::from("jquery_3_7.min.js")
         );
         assert_eq!(
-            sanitized_filename(
-            "relative/./path/Hello World.txt",
-            '_',
-            SanitizeMode::Legacy
-        ),
-        "relative/./path/Hello_World.txt"
-    );
-}
-
-    #[test]
-    fn sanitized_trims_edge_replacements() {
-        assert_eq!(
-            sanitized_filename(
-                "🐾_The_Adventures_of_Marshal_Poppy_The_Great_Sarsaparilla_Heist.md",
-                '_',
-                SanitizeMode::Full
-            ),
-            "The_Adventures_of_Marshal_Poppy_The_Great_Sarsaparilla_Heist.md"
+            sanitize("release v1.2", &options),
+            PathBuf::from("release_v1_2")
         );
-
         assert_eq!(
-            sanitized_filename("  spaced  ", '_', SanitizeMode::Legacy),
-            "spaced"
+            sanitize("Chapter 1. The Beginning", &options),
+            PathBuf::from("Chapter_1_The_Beginning")
+        );
+        // A leading `./` does not make the name hidden.
+        assert_eq!(sanitize("./f g.txt", &options), PathBuf::from("f_g.txt"));
+        assert_eq!(sanitize("./.my rc", &options), PathBuf::from(".my_rc"));
+        assert_eq!(
+            sanitize("sub/.hidden", &options),
+            PathBuf::from("sub/.hidden")
+        );
+        assert_eq!(
+            sanitize("./sub/My Backup.tar.gz", &options),
+            PathBuf::from("./sub/My_Backup.tar.gz")
         );
 
+        options.extensions.max_parts = 2;
         assert_eq!(
-            sanitized_filename(
-                "_The_Adventures_of_Marshal_Poppy_The_Great_Sarsaparilla_Heist_.md",
-                '_',
-                SanitizeMode::Legacy
-            ),
-            "The_Adventures_of_Marshal_Poppy_The_Great_Sarsaparilla_Heist.md"
+            sanitize("Q&A Audio.m4a.wav", &options),
+            PathBuf::from("Q_A_Audio.m4a.wav")
         );
 
+        options.extensions.add_compound("blend.bak").unwrap();
         assert_eq!(
-            sanitized_filename(
-                "nested/  spaced  .txt",
-                '_',
-                SanitizeMode::Legacy
-            ),
-            "nested/spaced.txt"
+            sanitize("my scene.blend.bak", &options),
+            PathBuf::from("my_scene.blend.bak")
         );
     }
 
     #[test]
-    fn custom_replacement() {
+    fn parse_args_extension_options() {
+        let args = vec![
+            "--keep-extensions",
+            "2",
+            "--compound-ext",
+            "tar.zst,.d.ts",
+            "--compound-ext=pkg.tar",
+            "f",
+        ];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.extensions.max_parts, 2);
+        assert_eq!(cfg.extensions.compound, ["tar.zst", "d.ts", "pkg.tar"]);
+
+        let args = vec!["--keep-extensions", "many", "f"];
+        assert!(matches!(parse_args(&args), Err(CliError::Message(_))));
+    }
+
+    #[test]
+    fn sanitized_path_normalizes_extensions() {
+        let sanitize = |name: &str, options: &SanitizeOptions| {
+            sanitized_path(Path::new(name), options).unwrap()
+        };
+        let mut options = SanitizeOptions::new("_", SanitizeMode::Full);
+
+        // The extension goes through the same mapping as the stem.
         assert_eq!(
-            sanitized_filename(
-                "Hello World.txt",
-                '-',
-                SanitizeMode::Legacy
-            ),
-            "Hello-World.txt"
+            sanitize("notes.tëxt", &options),
+            PathBuf::from("notes.t_xt")
+        );
+        assert_eq!(sanitize("a.tx t", &options), PathBuf::from("a.tx_t"));
+        assert_eq!(sanitize("x.m p3", &options), PathBuf::from("x.m_p3"));
+        // ... and is folded into the stem if nothing of it survives.
+        assert_eq!(
+            sanitize("report.日本", &options),
+            PathBuf::from("report")
+        );
+        assert_eq!(
+            sanitize("Holiday Photo.JPEG", &options),
+            PathBuf::from("Holiday_Photo.JPEG")
+        );
+
+        options.extensions.case = ExtensionCase::Lower;
+        options.extensions.add_aliases("common").unwrap();
+        assert_eq!(
+            sanitize("Holiday Photo.JPEG", &options),
+            PathBuf::from("Holiday_Photo.jpg")
+        );
+        assert_eq!(
+            sanitize("Scan 01.TIFF", &options),
+            PathBuf::from("Scan_01.tif")
+        );
+        assert_eq!(
+            sanitize("backup.TAR.GZ", &options),
+            PathBuf::from("backup.tar.gz")
         );
     }
 
     #[test]
-    fn full_sanitize_outputs_only_whitelisted_chars() {
-        let input = "Hello World! @#[](){}=+,.×é";
-        let output =
-            sanitized_filename(input, '_', SanitizeMode::Full);
-        let path = Path::new(&output);
-        let fname = path.file_name().unwrap().to_string_lossy();
-        let base = fname.split('.').next().unwrap();
-        for ch in base.chars() {
-            assert!(
-                ch.is_ascii_alphanumeric() || ch == '_' || ch == '-',
-                "found disallowed character {:?} in output {:?}",
-                ch,
-                output
-            );
+    fn parse_args_extension_normalization() {
+        let args =
+            vec!["--ext-case", "lower", "--ext-alias=common,mov=mp4", "f"];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.extensions.case, ExtensionCase::Lower);
+        assert_eq!(cfg.extensions.normalize("MOV"), "mp4");
+        assert_eq!(cfg.extensions.normalize("jpeg"), "jpg");
+
+        for bad in [["--ext-case", "title"], ["--ext-alias", "jpeg"]] {
+            assert!(matches!(parse_args(&bad), Err(CliError::Message(_))));
         }
     }
 
     #[test]
-    fn cli_replacement_option() {
-        let args = vec!["--replacement".to_string(), "-".to_string()];
-        let cfg = parse_args(&args).expect("parse_args failed");
-        assert_eq!(cfg.replacement, '-');
-        assert!(cfg.targets.is_empty());
-        assert!(!cfg.full_sanitize);
+    fn sanitized_path_fixes_extensions_from_content() {
+        let dir = temp_dir();
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let files: [(&str, &[u8]); 6] = [
+            ("download", png),
+            ("picture.jpg", png),
+            ("SCAN.JPG", png),
+            ("My Backup.tar.gz", b"\x1f\x8b\x08\0"),
+            ("notes", b"just text"),
+            (".hidden", png),
+        ];
+        for (name, content) in files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+
+        let mut options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        let sanitize = |name: &str, options: &SanitizeOptions| {
+            sanitized_path(&dir.join(name), options).unwrap()
+        };
+        assert_eq!(sanitize("download", &options), dir.join("download"));
+
+        options.fix_extensions = true;
+        assert_eq!(sanitize("download", &options), dir.join("download.png"));
+        assert_eq!(sanitize("picture.jpg", &options), dir.join("picture.png"));
+        assert_eq!(sanitize("SCAN.JPG", &options), dir.join("SCAN.PNG"));
+        assert_eq!(
+            sanitize("My Backup.tar.gz", &options),
+            dir.join("My_Backup.tar.gz")
+        );
+        assert_eq!(sanitize("notes", &options), dir.join("notes"));
+        assert_eq!(sanitize(".hidden", &options), dir.join(".hidden"));
+
+        let cfg = parse_args(&["--fix-extensions", "f"]).unwrap();
+        assert!(cfg.fix_extensions);
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn invalid_replacement_rejected() {
-        let err = validate_replacement("/").unwrap_err();
-        assert!(
-            err.contains("Replacement character '/' is not allowed"),
-            "unexpected error message: {err}"
-        );
+    fn parse_args_records_command_line_settings() {
+        let cfg = parse_args(&["f"]).unwrap();
+        assert_eq!(cfg.settings, Settings::default());
+        assert_eq!(cfg.replacement, "_");
+
+        let args = [
+            "-c",
+            "-",
+            "-F",
+            "--case=lower",
+            "--profile",
+            "music",
+            "--no-config",
+            "f",
+        ];
+        let cfg = parse_args(&args).unwrap();
+        assert_eq!(cfg.settings.replacement.as_deref(), Some("-"));
+        assert_eq!(cfg.settings.mode, Some(SanitizeMode::Full));
+        assert_eq!(cfg.settings.case, Some(CaseStyle::Lower));
+        assert_eq!(cfg.profile.as_deref(), Some("music"));
+        assert_eq!(cfg.user_config, None);
+        assert!(!cfg.project_config);
+
+        assert!(parse_args(&["f"]).unwrap().project_config);
+        assert!(!Config::default().project_config);
     }
 
     #[test]
-    fn recursive_directory() {
+    fn run_applies_project_config_files() {
         let tmp = temp_dir();
-        let root = tmp.join("dir one");
-        let sub = root.join("sub dir");
-        fs::create_dir_all(&sub).unwrap();
-        let file = sub.join("file name.txt");
-        fs::write(&file, "test").unwrap();
+        fs::write(
+            tmp.join(config_file::PROJECT_FILE),
+            "replacement = \"-\"\n[profile.loud]\ncase = \"upper\"\n",
+        )
+        .unwrap();
+        let tree = tmp.join("tree");
+        fs::create_dir_all(tree.join("keep dir")).unwrap();
+        fs::write(
+            tree.join(config_file::PROJECT_FILE),
+            "exclude = ['keep*']\n",
+        )
+        .unwrap();
+        for name in ["My File.txt", "x y.txt"] {
+            fs::write(tmp.join(name), "").unwrap();
+        }
+        for name in ["a b.txt", "keep me.txt", "keep dir/c d.txt"] {
+            fs::write(tree.join(name), "").unwrap();
+        }
 
-        let sanitized_root =
-            sanitize_directory_tree(&root, false, '_', SanitizeMode::Legacy)
-                .unwrap();
+        let arg = |name: &str| tmp.join(name).into_os_string();
+        let run_args = |args: Vec<OsString>| {
+            let mut cfg = parse_args(&args).unwrap();
+            // Whatever the user running the tests has configured.
+            cfg.user_config = None;
+            run(cfg)
+        };
 
-        let expected_root = tmp.join("dir_one");
-        let expected_sub = expected_root.join("sub_dir");
-        let expected_file = expected_sub.join("file_name.txt");
+        run_args(vec!["--profile".into(), "loud".into(), arg("My File.txt")])
+            .unwrap();
+        assert!(tmp.join("MY-FILE.txt").exists());
 
-        assert_eq!(sanitized_root, expected_root);
-        assert!(expected_sub.is_dir());
-        assert!(expected_file.is_file());
+        // The command line wins over the file.
+        run_args(vec!["-c".into(), "+".into(), arg("x y.txt")]).unwrap();
+        assert!(tmp.join("x+y.txt").exists());
 
-        fs::remove_dir_all(tmp).unwrap();
+        // The nearest file applies; excluded names and the config file
+        // itself are left alone.
+        run_args(vec!["-r".into(), arg("tree")]).unwrap();
+        assert!(tree.join("a_b.txt").exists());
+        assert!(tree.join("keep me.txt").exists());
+        assert!(tree.join("keep dir/c d.txt").exists());
+        assert!(tree.join(config_file::PROJECT_FILE).exists());
+
+        let err =
+            run_args(vec!["--profile".into(), "quiet".into(), arg("tree")])
+                .unwrap_err();
+        assert!(err.to_string().contains("Profile 'quiet'"));
+
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
     #[test]
-    fn recursive_custom_replacement() {
-        let tmp = temp_dir();
-        let root = tmp.join("dir one");
-        let sub = root.join("sub dir");
-        fs::create_dir_all(&sub).unwrap();
-        let file = sub.join("file name.txt");
-        fs::write(&file, "test").unwrap();
+    fn sanitized_filename_applies_custom_rules() {
+        let sanitize = |name: &str, options: &SanitizeOptions| {
+            sanitized_path(Path::new(name), options).unwrap()
+        };
+        let mut options = SanitizeOptions::new("_", SanitizeMode::Full);
+        options.rules.add_map("&=and").unwrap();
+        options.rules.add_map("@=at").unwrap();
+        options.rules.add_keep("+").unwrap();
+        options.rules.add_strip("!'");
 
-        let sanitized_root =
-            sanitize_directory_tree(&root, false, '-', SanitizeMode::Legacy)
-                .unwrap();
+        assert_eq!(
+            sanitize("Rock & Roll!.mp3", &options),
+            PathBuf::from("Rock_and_Roll.mp3")
+        );
+        assert_eq!(
+            sanitize("Tom's C++ notes.txt", &options),
+            PathBuf::from("Toms_C++_notes.txt")
+        );
+        assert_eq!(
+            sanitize("me@home.txt", &options),
+            PathBuf::from("meathome.txt")
+        );
+    }
 
-        let expected_root = tmp.join("dir-one");
-        let expected_sub = expected_root.join("sub-dir");
-        let expected_file = expected_sub.join("file-name.txt");
+    #[test]
+    fn parse_args_collects_rules() {
+        let tmp = temp_dir();
+        let rules_file = tmp.join("rules.txt");
+        fs::write(&rules_file, "# ours\n+=plus\nstrip ?\n").unwrap();
+        let args = vec![
+            "--map".to_string(),
+            "&=and".to_string(),
+            "--map===eq".to_string(),
+            "--keep=~".to_string(),
+            "--strip".to_string(),
+            "!".to_string(),
+            format!("--rules={}", rules_file.display()),
+            "f".to_string(),
+        ];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        let rules = &cfg.settings.rules;
+        assert_eq!(rules.apply_map("a&b=c+d"), "aandbeqcplusd");
+        assert_eq!(rules.keep, ['~']);
+        assert_eq!(rules.strip, ['!', '?']);
 
-        assert_eq!(sanitized_root, expected_root);
-        assert!(expected_sub.is_dir());
-        assert!(expected_file.is_file());
+        let missing = vec!["--rules", "/nonexistent/rules.txt", "f"];
+        assert!(matches!(parse_args(&missing), Err(CliError::Message(_))));
 
-        fs::remove_dir_all(tmp).unwrap();
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
     #[test]
-    fn recursive_directory_with_nested_content() {
-        let tmp = temp_dir();
-        let root = tmp.join("Root Dir");
-        let child_one = root.join("Child One");
-        let child_two = root.join("Second & Child");
-        let grand_one = child_one.join("Grand Child(1)");
-        let grand_two = child_two.join("Grand (Final)");
+    fn sanitized_path_applies_regex_rules() {
+        let sanitize = |name: &str, options: &SanitizeOptions| {
+            sanitized_path(Path::new(name), options).unwrap()
+        };
+        let mut options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        for spec in [r"\s*\(\d+\)$=", r"\s*\[[^\]]*\]=", r"(?i) - copy$="] {
+            options.regex.pre.push(RegexRule::parse(spec).unwrap());
+        }
+        options.regex.post.push(RegexRule::parse("_+-_+=-").unwrap());
 
-        fs::create_dir_all(&grand_one).unwrap();
-        fs::create_dir_all(&grand_two).unwrap();
+        assert_eq!(
+            sanitize("photo (1).jpg", &options),
+            PathBuf::from("photo.jpg")
+        );
+        assert_eq!(
+            sanitize("dir/Movie [YTS.MX] - 1080p.mkv", &options),
+            PathBuf::from("dir/Movie-1080p.mkv")
+        );
+        assert_eq!(
+            sanitize("report - Copy.docx", &options),
+            PathBuf::from("report.docx")
+        );
+        // A rule that would remove the whole stem is ignored.
+        assert_eq!(sanitize("(2).txt", &options), PathBuf::from("2.txt"));
+    }
 
-        let files = [
-            root.join("Root File?.txt"),
-            child_one.join("Clip (A).mov"),
-            child_one.join("Clip (B).mov"),
-            grand_one.join("Take #1.wav"),
-            child_two.join("Audio (Draft).wav"),
-            grand_two.join("Mix #2?.wav"),
+    #[test]
+    fn parse_args_collects_regex_rules() {
+        let args = vec![
+            "--regex",
+            r"^(\d{4})(\d{2})(\d{2})=$1-$2-$3",
+            "--regex-post=__=_",
+            "f",
         ];
-        for path in &files {
-            fs::write(path, "test").unwrap();
+        let cfg = parse_args(&args).expect("parse_args failed");
+        let regex = &cfg.settings.regex;
+        assert_eq!(regex.pre.len(), 1);
+        assert_eq!(regex.pre[0].apply("20240131"), "2024-01-31");
+        assert_eq!(regex.post[0].apply("a____b"), "a__b");
+
+        for bad in [["--regex", "(a=b"], ["--regex", "a=$1"]] {
+            let args = [bad[0], bad[1], "f"];
+            assert!(matches!(parse_args(&args), Err(CliError::Message(_))));
         }
+    }
 
-        let sanitized_root =
-            sanitize_directory_tree(&root, false, '_', SanitizeMode::Legacy)
-                .unwrap();
+    #[test]
+    fn sanitized_filename_with_multi_character_replacement() {
+        let legacy = |name, replacement| {
+            sanitized_filename(name, replacement, SanitizeMode::Legacy)
+        };
+        assert_eq!(legacy("hello? + world.wav", "__"), "hello__+__world.wav");
+        assert_eq!(legacy("  spaced  ", "__"), "spaced");
+        assert_eq!(legacy("My File (1).txt", " - "), "My - File - 1.txt");
+        // The separator itself survives a second run.
+        assert_eq!(legacy("My - File - 1.txt", " - "), "My - File - 1.txt");
+        assert_eq!(legacy("???", "__"), "__");
+    }
 
-        let expected_root = PathBuf::from(sanitized_filename(
-            root.to_str().unwrap(),
-            '_',
-            SanitizeMode::Legacy,
-        ));
-        let expected_child_one = PathBuf::from(sanitized_filename(
-            expected_root.join("Child One").to_str().unwrap(),
-            '_',
-            SanitizeMode::Legacy,
-        ));
-        let expected_child_two = PathBuf::from(sanitized_filename(
-            expected_root.join("Second & Child").to_str().unwrap(),
-            '_',
-            SanitizeMode::Legacy,
-        ));
-        let expected_grand_one = PathBuf::from(sanitized_filename(
-            expected_child_one.join("Grand Child(1)").to_str().unwrap(),
-            '_',
-            SanitizeMode::Legacy,
-        ));
-        let expected_grand_two = PathBuf::from(sanitized_filename(
-            expected_child_two.join("Grand (Final)").to_str().unwrap(),
-            '_',
-            SanitizeMode::Legacy,
+    #[test]
+    fn sanitized_filename_with_empty_replacement() {
+        let legacy = |name| sanitized_filename(name, "", SanitizeMode::Legacy);
+        assert_eq!(legacy("hello? + world.wav"), "hello+world.wav");
+        assert_eq!(legacy("Q&A (final).txt"), "QAfinal.txt");
+        // A name with nothing left falls back to "_".
+        assert_eq!(legacy("???.txt"), "_.txt");
+
+        let options = SanitizeOptions {
+            target_fs: Some(TargetFs::Windows),
+            ..SanitizeOptions::new("", SanitizeMode::Full)
+        };
+        let path = sanitized_path(Path::new("con.txt"), &options).unwrap();
+        assert_eq!(path, PathBuf::from("con_.txt"));
+    }
+
+    #[test]
+    fn parse_args_accepts_any_replacement_string() {
+        let cfg = parse_args(&["-c", " - ", "f"]).expect("parse_args failed");
+        assert_eq!(cfg.replacement, " - ");
+        let cfg =
+            parse_args(&["--replacement=", "f"]).expect("parse_args failed");
+        assert_eq!(cfg.replacement, "");
+        assert_eq!(cfg.settings.replacement.as_deref(), Some(""));
+        assert!(matches!(
+            parse_args(&["-c", "a/b", "f"]),
+            Err(CliError::Message(_))
         ));
+    }
 
-        let expected_files = [
-            PathBuf::from(sanitized_filename(
-                expected_root.join("Root File?.txt").to_str().unwrap(),
-                '_',
-                SanitizeMode::Legacy,
-            )),
-            PathBuf::from(sanitized_filename(
-                expected_child_one.join("Clip (A).mov").to_str().unwrap(),
-                '_',
-                SanitizeMode::Legacy,
-            )),
-            PathBuf::from(sanitized_filename(
-                expected_child_one.join("Clip (B).mov").to_str().unwrap(),
-                '_',
-                SanitizeMode::Legacy,
-            )),
-            PathBuf::from(sanitized_filename(
-                expected_grand_one.join("Take #1.wav").to_str().unwrap(),
-                '_',
-                SanitizeMode::Legacy,
-            )),
-            PathBuf::from(sanitized_filename(
-                expected_child_two.join("Audio (Draft).wav").to_str().unwrap(),
-                '_',
-                SanitizeMode::Legacy,
-            )),
-            PathBuf::from(sanitized_filename(
-                expected_grand_two.join("Mix #2?.wav").to_str().unwrap(),
-                '_',
+    #[test]
+    fn dot_replacements_never_name_a_directory_reference() {
+        for replacement in [".", ".."] {
+            let cfg = parse_args(&["-c", replacement, "d/???"]).unwrap();
+            let options = SanitizeOptions::new(
+                &cfg.replacement,
                 SanitizeMode::Legacy,
-            )),
-        ];
-
-        assert_eq!(sanitized_root, expected_root);
-        for dir in &[
-            &expected_child_one,
-            &expected_child_two,
-            &expected_grand_one,
-            &expected_grand_two,
-        ] {
-            assert!(dir.is_dir(), "expected directory {:?} to exist", dir);
-        }
-        for file in &expected_files {
-            assert!(file.is_file(), "expected file {:?} to exist", file);
-        }
-        for dir in &[&root, &child_one, &child_two, &grand_one, &grand_two] {
-            assert!(
-                !dir.exists(),
-                "expected original path {:?} to be gone",
-                dir
+            );
+            assert_eq!(
+                sanitized_path(Path::new("d/???"), &options),
+                Some(PathBuf::from("d/_"))
             );
         }
-
-        fs::remove_dir_all(tmp).unwrap();
     }
 
     #[test]
-    fn sanitize_directory_tree_handles_nonexistent_root() {
-        let tmp = temp_dir();
-        let missing = tmp.join("does_not_exist");
+    fn sanitized_filename_with_class_replacements() {
+        let mut options = SanitizeOptions::new("_", SanitizeMode::Full);
+        options.classes.space = Some("-".to_string());
+        options.classes.punct = Some(String::new());
+        let sanitize = |name: &str, options: &SanitizeOptions| {
+            sanitized_path(Path::new(name), options).unwrap()
+        };
+        assert_eq!(
+            sanitize("Rock & Roll: Live.mp3", &options),
+            PathBuf::from("Rock-Roll-Live.mp3")
+        );
+        // Brackets fall back to the main replacement, and each class
+        // collapses on its own.
+        assert_eq!(
+            sanitize("Live (2019)  Remaster!.flac", &options),
+            PathBuf::from("Live-_2019_-Remaster.flac")
+        );
 
-        let result =
-            sanitize_directory_tree(&missing, false, '_', SanitizeMode::Legacy)
-                .unwrap();
-        assert_eq!(result, missing);
-        assert!(!missing.exists());
+        options.classes.bracket = Some(String::new());
+        options.classes.quote = Some(String::new());
+        assert_eq!(
+            sanitize("Don't Stop [Remix] .mp3", &options),
+            PathBuf::from("Dont-Stop-Remix.mp3")
+        );
+        assert_eq!(
+            sanitize("Dont-Stop-Remix.mp3", &options),
+            PathBuf::from("Dont-Stop-Remix.mp3")
+        );
+    }
 
-        fs::remove_dir_all(tmp).unwrap();
+    #[test]
+    fn parse_args_class_replacements() {
+        let args = [
+            "--space-replacement",
+            "-",
+            "--punct-replacement=",
+            "--bracket-replacement=__",
+            "f",
+        ];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        let classes = &cfg.settings.classes;
+        assert_eq!(classes.space.as_deref(), Some("-"));
+        assert_eq!(classes.punct.as_deref(), Some(""));
+        assert_eq!(classes.bracket.as_deref(), Some("__"));
+        assert_eq!(classes.quote, None);
+
+        let args = ["--target", "windows", "--quote-replacement", "\"", "f"];
+        assert!(matches!(parse_args(&args), Err(CliError::Message(_))));
     }
 
     #[test]
-    fn sanitize_directory_tree_sanitizes_single_file() {
+    fn recursion_honours_excludes_and_ignore_files() {
         let tmp = temp_dir();
-        let file = tmp.join("file name.txt");
-        fs::write(&file, "test").unwrap();
-
-        let result =
-            sanitize_directory_tree(&file, false, '_', SanitizeMode::Legacy)
-                .unwrap();
-        let expected = tmp.join("file_name.txt");
-
-        assert_eq!(result, expected);
-        assert!(!file.exists());
-        assert!(expected.exists());
-
-        fs::remove_dir_all(tmp).unwrap();
+        for dir in [".git/objects", "node_modules/a b", "src/gen", "docs"] {
+            fs::create_dir_all(tmp.join(dir)).unwrap();
+        }
+        for file in [
+            ".git/objects/pack 1",
+            "node_modules/a b/c d.js",
+            "src/gen/x y.rs",
+            "src/main file.rs",
+            "src/temp file.tmp",
+            "docs/read me.md",
+            "docs/keep me.txt",
+        ] {
+            fs::write(tmp.join(file), "").unwrap();
+        }
+        fs::write(tmp.join("src").join(ignore::IGNORE_FILE), "*.tmp\ngen/\n")
+            .unwrap();
+        fs::write(tmp.join(".gitignore"), "node_modules/\n").unwrap();
+
+        let run_args = |args: &[&str]| {
+            let mut args: Vec<OsString> =
+                args.iter().map(OsString::from).collect();
+            args.push("--no-config".into());
+            args.push(tmp.clone().into_os_string());
+            run(parse_args(&args).unwrap()).unwrap();
+        };
+        run_args(&["-r", "--exclude", "docs/keep*", "--gitignore"]);
+
+        assert!(tmp.join(".git/objects/pack 1").exists());
+        assert!(tmp.join("node_modules/a b/c d.js").exists());
+        assert!(tmp.join("src/gen/x y.rs").exists());
+        assert!(tmp.join("src/temp file.tmp").exists());
+        assert!(tmp.join("src/main_file.rs").exists());
+        assert!(tmp.join("docs/read_me.md").exists());
+        assert!(tmp.join("docs/keep me.txt").exists());
+
+        // Without --gitignore, only .sanitizeignore files count, and
+        // --include limits what is renamed.
+        run_args(&["-r", "--include", "*.js"]);
+        assert!(tmp.join("node_modules/a b/c_d.js").exists());
+        assert!(tmp.join("docs/keep me.txt").exists());
+
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
     #[test]
-    fn dry_run_does_not_rename() {
+    fn ignore_files_above_the_root_need_a_repository() {
         let tmp = temp_dir();
-        let file = tmp.join("file name.txt");
-        fs::write(&file, "test").unwrap();
-
-        let desired = PathBuf::from(sanitized_filename(
-            file.to_str().unwrap(),
-            '_',
-            SanitizeMode::Legacy,
-        ));
-        let result = rename_path(&file, &desired, true).unwrap();
+        let root = tmp.join("outer").join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(tmp.join("outer").join(ignore::IGNORE_FILE), "*.txt\n")
+            .unwrap();
+        let is_ignored = || {
+            ignore::Ignores::new(&root, &[], false)
+                .unwrap()
+                .is_ignored(&root.join("a.txt"), false)
+        };
 
-        assert_eq!(result, desired);
-        assert!(file.exists());
-        assert!(!desired.exists());
+        assert!(!is_ignored());
+        fs::create_dir(tmp.join("outer").join(".git")).unwrap();
+        assert!(is_ignored());
 
-        fs::remove_dir_all(tmp).unwrap();
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
     #[test]
-    fn rename_path_noop_when_old_equals_new() {
-        let tmp = temp_dir();
-        let path = tmp.join("same.txt");
-        fs::write(&path, "test").unwrap();
-
-        let result = rename_path(&path, &path, false).unwrap();
-
-        assert_eq!(result, path);
-        assert!(path.exists());
+    fn parse_args_collects_walk_filters() {
+        let args =
+            ["--exclude", ".venv", "--exclude=*.bak", "--include=*.mp3", "f"];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.settings.exclude, [".venv", "*.bak"]);
+        assert_eq!(cfg.settings.include, ["*.mp3"]);
+        assert_eq!(cfg.settings.gitignore, None);
 
-        fs::remove_dir_all(tmp).unwrap();
+        let cfg = parse_args(&["--gitignore", "f"]).expect("parse_args failed");
+        assert_eq!(cfg.settings.gitignore, Some(true));
     }
 
     #[test]
-    fn rename_path_skips_when_old_missing() {
+    fn recursion_honours_depth_and_entry_filters() {
         let tmp = temp_dir();
-        let old = tmp.join("missing.txt");
-        let new_path = tmp.join("new.txt");
-
-        let result = rename_path(&old, &new_path, false).unwrap();
-
-        assert_eq!(result, old);
-        assert!(!old.exists());
-        assert!(!new_path.exists());
+        fs::create_dir_all(tmp.join("d 1/d 2/d 3")).unwrap();
+        for file in ["f 0", "d 1/f 1", "d 1/d 2/f 2", "d 1/d 2/d 3/f 3"] {
+            fs::write(tmp.join(file), "").unwrap();
+        }
 
-        fs::remove_dir_all(tmp).unwrap();
+        let mut options = SanitizeOptions::new("_", SanitizeMode::Legacy);
+        options.min_depth = 1;
+        options.max_depth = Some(2);
+        options.entries = EntryFilter::FilesOnly;
+        let mut session = RenameSession::new(false, ConflictPolicy::Skip, "_");
+        let result =
+            sanitize_directory_tree_with(&tmp, &options, &mut session).unwrap();
+        assert_eq!(result, tmp);
+        assert!(tmp.join("f_0").exists());
+        assert!(tmp.join("d 1/f_1").exists());
+        assert!(tmp.join("d 1/d 2/f 2").exists());
+
+        options.max_depth = None;
+        options.entries = EntryFilter::DirsOnly;
+        sanitize_directory_tree_with(&tmp, &options, &mut session).unwrap();
+        assert!(tmp.join("d_1/d_2/d_3/f 3").exists());
+        assert!(tmp.join("d_1/d_2/f 2").exists());
+
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
     #[test]
-    fn rename_path_skips_when_new_exists() {
+    fn recursion_sorts_entries_by_name() {
         let tmp = temp_dir();
-        let old = tmp.join("old.txt");
-        let new_path = tmp.join("new.txt");
-
-        fs::write(&old, "test").unwrap();
-        fs::write(&new_path, "other").unwrap();
-
-        let result = rename_path(&old, &new_path, false).unwrap();
+        for file in ["b?c.txt", "b c.txt", "a b.txt"] {
+            fs::write(tmp.join(file), "").unwrap();
+        }
+        let options = SanitizeOptions {
+            min_depth: 1,
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        let mut planner = Planner::new(ConflictPolicy::Suffix, "_");
+        let plan = build_plan(&[&tmp], true, &options, &mut planner).unwrap();
+        let names: Vec<_> = plan
+            .ops
+            .iter()
+            .map(|op| {
+                let from = op.from.file_name().unwrap().to_string_lossy();
+                let to = op.final_path().file_name().unwrap().to_string_lossy();
+                format!("{from} -> {to}")
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "a b.txt -> a_b.txt",
+                "b c.txt -> b_c.txt",
+                "b?c.txt -> b_c_1.txt"
+            ]
+        );
 
-        assert_eq!(result, old);
-        assert!(old.exists());
-        assert!(new_path.exists());
+        fs::remove_dir_all(&tmp).unwrap();
+    }
 
-        fs::remove_dir_all(tmp).unwrap();
+    #[test]
+    fn parse_args_reads_depth_and_order_options() {
+        let args = [
+            "--min-depth", "1", "--max-depth=3", "--sort=none", "--dirs-only",
+            "f",
+        ];
+        let cfg = parse_args(&args).expect("parse_args failed");
+        assert_eq!(cfg.min_depth, 1);
+        assert_eq!(cfg.max_depth, Some(3));
+        assert_eq!(cfg.sort, SortOrder::None);
+        assert_eq!(cfg.entries, EntryFilter::DirsOnly);
+
+        for args in [
+            &["--min-depth", "2", "--max-depth", "1", "f"][..],
+            &["--dirs-only", "--files-only", "f"],
+            &["--sort", "size", "f"],
+            &["--max-depth", "-1", "f"],
+        ] {
+            assert!(matches!(parse_args(args), Err(CliError::Message(_))));
+        }
     }
 
+    #[cfg(unix)]
     #[test]
-    fn rename_path_renames_when_possible() {
+    fn symlink_policies_and_fixes() {
+        use std::os::unix::fs::symlink;
+
         let tmp = temp_dir();
-        let old = tmp.join("old name.txt");
-        let new_path = tmp.join("new_name.txt");
+        fs::create_dir_all(tmp.join("Album One/Disc 1")).unwrap();
+        fs::write(tmp.join("Album One/Disc 1/track 1.mp3"), "").unwrap();
+        fs::create_dir(tmp.join("links")).unwrap();
+        symlink(
+            "../Album One/Disc 1/track 1.mp3",
+            tmp.join("links/best of.mp3"),
+        )
+        .unwrap();
+        symlink("../Album One", tmp.join("links/album link")).unwrap();
+        // A loop back to the top.
+        symlink("..", tmp.join("links/up")).unwrap();
+
+        let options = SanitizeOptions {
+            symlinks: SymlinkPolicy::Skip,
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        let mut planner = Planner::new(ConflictPolicy::Skip, "_");
+        let plan = build_plan(&[&tmp], true, &options, &mut planner).unwrap();
+        assert!(plan.links.is_empty());
+        assert!(!plan.ops.iter().any(|op| op.from.ends_with("best of.mp3")));
+
+        let options = SanitizeOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+        };
+        let mut planner = Planner::new(ConflictPolicy::Skip, "_");
+        let plan = build_plan(&[&tmp], true, &options, &mut planner).unwrap();
+        let entered = |dir: &str| {
+            plan.ops
+                .iter()
+                .filter(|op| op.from.parent() == Some(&tmp.join(dir)))
+                .count()
+        };
+        // "Album One" sorts first, so it is entered directly and not
+        // again through the link; "up" leads back to the start.
+        assert_eq!(entered("Album One"), 1);
+        assert_eq!(entered("links/album link"), 0);
+        assert_eq!(entered("links/up"), 0);
+        assert_eq!(plan.links.len(), 3);
 
-        fs::write(&old, "test").unwrap();
+        let config = Config {
+            recursive: true,
+            fix_symlinks: true,
+            targets: vec![tmp.clone().into_os_string()],
+            ..Config::default()
+        };
+        run(config).unwrap();
+        let track = tmp.join("links/best_of.mp3");
+        assert_eq!(
+            fs::read_link(&track).unwrap(),
+            Path::new("../Album_One/Disc_1/track_1.mp3")
+        );
+        assert!(track.exists());
+        assert!(tmp.join("links/album_link/Disc_1").exists());
+        assert_eq!(
+            fs::read_link(tmp.join("links/up")).unwrap(),
+            Path::new("..")
+        );
 
-        let result = rename_path(&old, &new_path, false).unwrap();
+        fs::remove_dir_all(&tmp).unwrap();
+    }
 
-        assert_eq!(result, new_path);
-        assert!(!old.exists());
-        assert!(new_path.exists());
+    #[test]
+    fn parse_args_reads_symlink_options() {
+        let cfg = parse_args(&["--symlinks=follow", "--fix-symlinks", "f"])
+            .expect("parse_args failed");
+        assert_eq!(cfg.symlinks, SymlinkPolicy::Follow);
+        assert!(cfg.fix_symlinks);
+        assert!(parse_args(&["--symlinks", "chase", "f"]).is_err());
+    }
 
-        fs::remove_dir_all(tmp).unwrap();
+    #[test]
+    fn refuses_protected_roots_without_force() {
+        let config = Config {
+            dry_run: true,
+            recursive: true,
+            targets: vec![OsString::from("/")],
+            ..Config::default()
+        };
+        let err = run(config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("--force"));
+
+        let cfg =
+            parse_args(&["-x", "--force", "f"]).expect("parse_args failed");
+        assert!(cfg.one_file_system);
+        assert!(cfg.force);
     }
 
+    #[cfg(unix)]
     #[test]
-    fn run_non_recursive_renames_target_files() {
+    fn followed_links_do_not_escape_into_protected_roots() {
+        use std::os::unix::fs::symlink;
+
         let tmp = temp_dir();
-        let file = tmp.join("file name.txt");
-        fs::write(&file, "test").unwrap();
+        symlink("/etc", tmp.join("system config")).unwrap();
 
-        let original = file.to_str().unwrap().to_string();
         let config = Config {
-            recursive: false,
-            dry_run: false,
-            replacement: '_',
-            targets: vec![original.clone()],
-            full_sanitize: false,
+            dry_run: true,
+            recursive: true,
+            symlinks: SymlinkPolicy::Follow,
+            targets: vec![tmp.clone().into_os_string()],
+            ..Config::default()
         };
+        let err = run(config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("--force"));
 
-        run(config).unwrap();
-
-        let expected_path = PathBuf::from(sanitized_filename(
-            &original,
-            '_',
-            SanitizeMode::Legacy,
-        ));
-        assert!(!file.exists());
-        assert!(expected_path.exists());
-
-        fs::remove_dir_all(tmp).unwrap();
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
+    #[cfg(unix)]
     #[test]
-    fn run_non_recursive_does_not_recurse_into_directories() {
+    fn dangling_symlinks_are_renamed() {
+        use std::os::unix::fs::symlink;
+
         let tmp = temp_dir();
-        let root = tmp.join("dir one");
-        let sub = root.join("sub dir");
-        fs::create_dir_all(&sub).unwrap();
-        let file = sub.join("file name.txt");
-        fs::write(&file, "test").unwrap();
+        symlink("nowhere", tmp.join("bad link")).unwrap();
 
-        let root_str = root.to_str().unwrap().to_string();
         let config = Config {
-            recursive: false,
-            dry_run: false,
-            replacement: '_',
-            targets: vec![root_str.clone()],
-            full_sanitize: false,
+            targets: vec![tmp.join("bad link").into_os_string()],
+            ..Config::default()
         };
-
         run(config).unwrap();
-
-        let expected_root = PathBuf::from(sanitized_filename(
-            &root_str,
-            '_',
-            SanitizeMode::Legacy,
-        ));
-        assert!(!root.exists());
-        assert!(expected_root.exists());
-
-        let expected_sub = expected_root.join("sub dir");
-        let expected_file = expected_sub.join("file name.txt");
-        assert!(
-            expected_sub.is_dir(),
-            "expected non-recursive run to leave subdirectory name unchanged"
-        );
-        assert!(
-            expected_file.is_file(),
-            "expected non-recursive run to leave file name unchanged"
+        assert_eq!(
+            fs::read_link(tmp.join("bad_link")).unwrap(),
+            Path::new("nowhere")
         );
 
-        fs::remove_dir_all(tmp).unwrap();
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
     #[test]
-    fn run_recursive_respects_dry_run() {
+    fn parallel_walk_plans_like_a_single_thread() {
         let tmp = temp_dir();
-        let root = tmp.join("dir one");
-        let sub = root.join("sub dir");
-        fs::create_dir_all(&sub).unwrap();
-        let file = sub.join("file name.txt");
-        fs::write(&file, "test").unwrap();
+        for a in 0..6 {
+            for b in 0..4 {
+                let dir = tmp.join(format!("dir {a}/sub {b}"));
+                fs::create_dir_all(&dir).unwrap();
+                for name in ["x y.txt", "x?y.txt", "x&y.txt", "plain.txt"] {
+                    fs::write(dir.join(name), "").unwrap();
+                }
+            }
+        }
 
-        let root_str = root.to_str().unwrap().to_string();
-        let config = Config {
-            recursive: true,
-            dry_run: true,
-            replacement: '_',
-            targets: vec![root_str.clone()],
-            full_sanitize: false,
+        let plan_with = |jobs| {
+            let options = SanitizeOptions {
+                jobs,
+                ..SanitizeOptions::new("_", SanitizeMode::Legacy)
+            };
+            let mut planner = Planner::new(ConflictPolicy::Suffix, "_");
+            build_plan(&[&tmp], true, &options, &mut planner).unwrap()
         };
+        let single = plan_with(1);
+        assert_eq!(single.renames().count(), 6 * 4 * 3 + 6 * 5);
+        assert!(single.validate().is_ok());
+        for jobs in [0, 2, 8] {
+            assert_eq!(plan_with(jobs), single);
+        }
 
-        run(config).unwrap();
-
-        assert!(root.exists());
-        assert!(sub.exists());
-        assert!(file.exists());
-
-        let expected_root =
-            PathBuf::from(sanitized_filename(&root_str, '_', SanitizeMode::Legacy));
-        assert!(!expected_root.exists());
-
-        fs::remove_dir_all(tmp).unwrap();
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
     #[test]
-    fn run_with_args_help_returns_zero() {
-        let args = vec!["--help".to_string()];
-        let code = run_with_args(&args);
-        assert_eq!(code, 0);
+    fn parse_args_reads_jobs() {
+        let cfg = parse_args(&["--jobs", "4", "f"]).expect("parse_args failed");
+        assert_eq!(cfg.jobs, 4);
+        let cfg = parse_args(&["--jobs=0", "f"]).expect("parse_args failed");
+        assert_eq!(cfg.jobs, 0);
+        assert!(parse_args(&["--jobs", "many", "f"]).is_err());
     }
 
     #[test]
-    fn run_with_args_reports_missing_targets() {
-        let args: Vec<String> = Vec::new();
-        let code = run_with_args(&args);
-        assert_eq!(code, 1);
+    fn reads_target_lists() {
+        let list = read_target_list(&b"a b.txt\n\n.\ndir/c d\n"[..], false)
+            .unwrap();
+        assert_eq!(list, [OsString::from("a b.txt"), "dir/c d".into()]);
+
+        let list =
+            read_target_list(&b"line\nbreak.txt\0..\0x\0"[..], true).unwrap();
+        assert_eq!(list, [OsString::from("line\nbreak.txt"), "x".into()]);
     }
 
+    #[cfg(unix)]
     #[test]
-    fn run_with_args_propagates_parse_error() {
-        let args = vec!["--unknown".to_string()];
-        let code = run_with_args(&args);
-        assert_eq!(code, 1);
-    }
+    fn from_file_targets_keep_raw_bytes() {
+        use std::os::unix::ffi::OsStrExt;
 
-    #[test]
-    fn run_with_args_successfully_sanitizes_file() {
         let tmp = temp_dir();
-        let file = tmp.join("file name.txt");
-        fs::write(&file, "test").unwrap();
-        let file_str = file.to_str().unwrap().to_string();
-        let args = vec![file_str.clone()];
-
-        let code = run_with_args(&args);
-        assert_eq!(code, 0);
+        let odd = tmp.join(OsStr::from_bytes(b"caf\xe9 \nmenu.txt"));
+        fs::write(&odd, "").unwrap();
+        fs::write(tmp.join("plain name.txt"), "").unwrap();
+        let mut list = odd.as_os_str().as_bytes().to_vec();
+        list.push(0);
+        let plain = tmp.join("plain name.txt");
+        list.extend_from_slice(plain.as_os_str().as_bytes());
+        let list_path = tmp.join("list");
+        fs::write(&list_path, list).unwrap();
+
+        let args = [
+            OsString::from("--from-file"),
+            list_path.clone().into_os_string(),
+            OsString::from("-0"),
+            OsString::from("--non-utf8=latin1"),
+            OsString::from("--no-config"),
+        ];
+        let config = parse_args(&args).expect("parse_args failed");
+        assert_eq!(config.from_file.as_deref(), Some(list_path.as_path()));
+        assert!(config.null);
+        run(config).unwrap();
 
-        let expected = PathBuf::from(sanitized_filename(
-            &file_str,
-            '_',
-            SanitizeMode::Legacy,
-        ));
-        assert!(!file.exists());
-        assert!(expected.exists());
+        assert!(tmp.join("café_menu.txt").exists());
+        assert!(tmp.join("plain_name.txt").exists());
 
-        fs::remove_dir_all(tmp).unwrap();
+        assert!(parse_args(&["--null", "f"]).is_err());
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
     #[test]
-    fn recursive_dry_run_does_not_rename() {
+    fn from_file_accepts_directories_before_their_contents() {
         let tmp = temp_dir();
-        let root = tmp.join("dir one");
-        let sub = root.join("sub dir");
-        fs::create_dir_all(&sub).unwrap();
-        let file = sub.join("file name.txt");
-        fs::write(&file, "test").unwrap();
+        fs::create_dir_all(tmp.join("a dir/sub d")).unwrap();
+        fs::write(tmp.join("a dir/x y"), "").unwrap();
+        fs::write(tmp.join("a dir/sub d/f g.txt"), "").unwrap();
+        // What `find DIR -mindepth 1 -print0` lists, without `-depth`.
+        let mut list = String::new();
+        for name in ["a dir", "a dir/x y", "a dir/sub d", "a dir/sub d/f g.txt"]
+        {
+            list.push_str(&tmp.join(name).to_string_lossy());
+            list.push('\0');
+        }
+        let list_path = tmp.join("list");
+        fs::write(&list_path, list).unwrap();
 
-        let sanitized_root =
-            sanitize_directory_tree(&root, true, '_', SanitizeMode::Legacy)
-                .unwrap();
-        let expected_root = PathBuf::from(sanitized_filename(
-            root.to_str().unwrap(),
-            '_',
-            SanitizeMode::Legacy,
-        ));
+        let config = Config {
+            from_file: Some(list_path),
+            null: true,
+            ..Config::default()
+        };
+        run(config).unwrap();
 
-        assert_eq!(sanitized_root, expected_root);
-        assert!(root.exists());
-        assert!(!expected_root.exists());
+        assert!(tmp.join("a_dir/x_y").exists());
+        assert!(tmp.join("a_dir/sub_d/f_g.txt").exists());
+        assert!(!tmp.join("a dir").exists());
 
-        fs::remove_dir_all(tmp).unwrap();
+        fs::remove_dir_all(&tmp).unwrap();
     }
 
     #[test]
-    fn option_terminator_handles_dash_prefixed_filename() {
+    fn unrelated_targets_keep_their_order() {
         let tmp = temp_dir();
-        let file = tmp.join("-file name.txt");
-        fs::write(&file, "test").unwrap();
-
-        let file_str = file.to_str().unwrap().to_string();
-        let args = vec![
-            "--dry-run".to_string(),
-            "--".to_string(),
-            file_str.clone(),
-        ];
-        let cfg = parse_args(&args).expect("parse_args failed");
-
-        assert!(cfg.dry_run);
-        assert_eq!(cfg.replacement, '_');
-        assert!(!cfg.full_sanitize);
-        assert_eq!(cfg.targets, vec![file_str.clone()]);
+        fs::create_dir(tmp.join("sub")).unwrap();
+        fs::write(tmp.join("a b"), "first").unwrap();
+        fs::write(tmp.join("a?b"), "second").unwrap();
 
-        let desired = PathBuf::from(sanitized_filename(
-            &file_str,
-            cfg.replacement,
-            SanitizeMode::Legacy,
-        ));
-        rename_path(Path::new(&file_str), &desired, cfg.dry_run).unwrap();
+        // The second target is deeper, but only its position decides
+        // which of the two gets the shared name.
+        let config = Config {
+            targets: vec![
+                tmp.join("a b").into_os_string(),
+                tmp.join("sub/../a?b").into_os_string(),
+            ],
+            ..Config::default()
+        };
+        run(config).unwrap();
 
-        assert!(Path::new(&file_str).exists());
-        assert!(!desired.exists());
+        assert_eq!(fs::read_to_string(tmp.join("a_b")).unwrap(), "first");
+        assert!(tmp.join("a?b").exists());
 
-        fs::remove_dir_all(tmp).unwrap();
+        fs::remove_dir_all(&tmp).unwrap();
     }
 }
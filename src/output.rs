@@ -0,0 +1,296 @@
+//! Machine-readable output for `--format json|ndjson|tsv`.
+//!
+//! Every planned path produces one record with the old and new path, an
+//! action and an optional reason. The wording of the human output can
+//! change freely; these field names and action values cannot.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::journal;
+use crate::plan::PlanError;
+use crate::report::{Event, HumanReporter, Reporter};
+use crate::symlinks::LinkFix;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Coloured, human-oriented messages.
+    #[default]
+    Human,
+    /// A single JSON array, printed once the run is finished.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    /// Tab-separated values with a header line.
+    Tsv,
+}
+
+impl OutputFormat {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            "tsv" => Ok(Self::Tsv),
+            _ => Err(format!(
+                "Invalid output format '{s}' (expected human, json, ndjson or tsv)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Renamed,
+    Unchanged,
+    Skipped,
+    Collision,
+    /// A symlink now points at the new name of its target.
+    Relinked,
+    Error,
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Renamed => "renamed",
+            Self::Unchanged => "unchanged",
+            Self::Skipped => "skipped",
+            Self::Collision => "collision",
+            Self::Relinked => "relinked",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// The outcome for a single path.
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    pub old: &'a Path,
+    /// `None` when no new name could be worked out.
+    pub new: Option<&'a Path>,
+    pub action: Action,
+    pub reason: Option<&'a str>,
+    pub dry_run: bool,
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                out.push_str(&format!("\\u{:04x}", u32::from(c)));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_path(path: Option<&Path>) -> String {
+    match path {
+        Some(path) => json_string(&path.to_string_lossy()),
+        None => "null".to_string(),
+    }
+}
+
+/// Render `record` as a single-line JSON object. Paths that are not
+/// valid UTF-8 are converted lossily; use TSV to get them byte-exact.
+pub fn to_json(record: &Record<'_>) -> String {
+    format!(
+        "{{\"old\":{},\"new\":{},\"action\":{},\"reason\":{},\"dry_run\":{}}}",
+        json_path(Some(record.old)),
+        json_path(record.new),
+        json_string(record.action.as_str()),
+        record
+            .reason
+            .map_or_else(|| "null".to_string(), json_string),
+        record.dry_run
+    )
+}
+
+pub const TSV_HEADER: &str = "old\tnew\taction\treason\tdry_run";
+
+/// Render `record` as a TSV line. Paths use the journal escaping
+/// (`\\`, `\t`, `\n`, `\r`, `\xHH`), so they round-trip exactly.
+pub fn to_tsv(record: &Record<'_>) -> String {
+    let reason = record.reason.unwrap_or("").replace(['\t', '\n'], " ");
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        journal::escape(record.old),
+        record.new.map(journal::escape).unwrap_or_default(),
+        record.action.as_str(),
+        reason,
+        record.dry_run
+    )
+}
+
+/// A `Reporter` that writes one record per event in a structured format.
+#[derive(Debug)]
+pub struct StructuredReporter<W> {
+    out: W,
+    format: OutputFormat,
+    pending: Vec<String>,
+    started: bool,
+}
+
+impl<W: Write> StructuredReporter<W> {
+    /// `OutputFormat::Human` produces no output here; use
+    /// `report::HumanReporter` for that.
+    pub fn new(out: W, format: OutputFormat) -> Self {
+        Self {
+            out,
+            format,
+            pending: Vec::new(),
+            started: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    pub fn write(&mut self, record: &Record<'_>) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Human => Ok(()),
+            OutputFormat::Json => {
+                self.pending.push(to_json(record));
+                Ok(())
+            }
+            OutputFormat::Ndjson => writeln!(self.out, "{}", to_json(record)),
+            OutputFormat::Tsv => {
+                if !self.started {
+                    writeln!(self.out, "{TSV_HEADER}")?;
+                    self.started = true;
+                }
+                writeln!(self.out, "{}", to_tsv(record))
+            }
+        }
+    }
+}
+
+impl<W: Write> Reporter for StructuredReporter<W> {
+    fn report(&mut self, event: &Event<'_>) -> io::Result<()> {
+        let reason = event.reason();
+        self.write(&Record {
+            old: &event.op.from,
+            new: event.new_path(),
+            action: event.action(),
+            reason: reason.as_deref(),
+            dry_run: event.dry_run,
+        })
+    }
+
+    /// The link is both the old and the new path; the reason gives the
+    /// new target.
+    fn relink(&mut self, fix: &LinkFix, dry_run: bool) -> io::Result<()> {
+        let reason = format!(
+            "target '{}' is now '{}'",
+            fix.old_target.display(),
+            fix.new_target.display()
+        );
+        self.write(&Record {
+            old: &fix.link,
+            new: Some(&fix.link),
+            action: Action::Relinked,
+            reason: Some(&reason),
+            dry_run,
+        })
+    }
+
+    fn problem(
+        &mut self,
+        problem: &PlanError,
+        dry_run: bool,
+    ) -> io::Result<()> {
+        let reason = problem.to_string();
+        self.write(&Record {
+            old: problem.path(),
+            new: problem.new_path(),
+            action: Action::Error,
+            reason: Some(&reason),
+            dry_run,
+        })
+    }
+
+    /// Print anything that has to wait for the end of the run, i.e. the
+    /// JSON array.
+    fn finish(&mut self) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            writeln!(self.out, "[{}]", self.pending.join(","))?;
+            self.pending.clear();
+        }
+        self.out.flush()
+    }
+}
+
+/// The reporter the CLI uses for `format`, printing to standard output.
+pub fn stdout_reporter(format: OutputFormat) -> Box<dyn Reporter> {
+    match format {
+        OutputFormat::Human => Box::new(HumanReporter::stdout()),
+        _ => Box::new(StructuredReporter::new(io::stdout(), format)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_escapes_strings() {
+        let record = Record {
+            old: Path::new("dir/a \"b\"\t.txt"),
+            new: Some(Path::new("dir/a_b.txt")),
+            action: Action::Renamed,
+            reason: None,
+            dry_run: true,
+        };
+        assert_eq!(
+            to_json(&record),
+            r#"{"old":"dir/a \"b\"\t.txt","new":"dir/a_b.txt","action":"renamed","reason":null,"dry_run":true}"#
+        );
+    }
+
+    #[test]
+    fn tsv_escapes_paths() {
+        let record = Record {
+            old: Path::new("tab\there"),
+            new: None,
+            action: Action::Skipped,
+            reason: Some("does not exist"),
+            dry_run: false,
+        };
+        assert_eq!(
+            to_tsv(&record),
+            "tab\\there\t\tskipped\tdoes not exist\tfalse"
+        );
+    }
+
+    #[test]
+    fn json_array_is_written_on_finish() {
+        let mut writer =
+            StructuredReporter::new(Vec::new(), OutputFormat::Json);
+        let record = Record {
+            old: Path::new("a"),
+            new: Some(Path::new("a")),
+            action: Action::Unchanged,
+            reason: None,
+            dry_run: false,
+        };
+        writer.write(&record).unwrap();
+        writer.write(&record).unwrap();
+        assert!(writer.out.is_empty());
+        writer.finish().unwrap();
+        let out = String::from_utf8(writer.into_inner()).unwrap();
+        assert!(out.starts_with("[{\"old\":\"a\""));
+        assert!(out.ends_with("}]\n"));
+        assert_eq!(out.matches("\"action\":\"unchanged\"").count(), 2);
+    }
+}
@@ -0,0 +1,809 @@
+//! Rename plans: the full list of renames for a run, worked out and
+//! checked before anything on disk is touched.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ConflictPolicy;
+use crate::extension::ExtensionRules;
+use crate::length::LengthLimit;
+
+/// Why an entry is left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The source path does not exist.
+    Missing,
+    /// The name is not valid UTF-8 and `NonUtf8Policy::Skip` is in effect.
+    NotUtf8,
+    /// The new name is taken and `ConflictPolicy::Skip` is in effect,
+    /// or another rename of the same run has already claimed it.
+    Exists,
+    /// The new path would be longer than `--max-path-length` allows.
+    PathTooLong,
+    /// The directory of the source or the new name looks read-only.
+    ReadOnly,
+}
+
+/// What applying a `RenameOp` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameKind {
+    /// Rename `from` to the free name `to`.
+    Rename,
+    /// Rename `from` to `to`, replacing what is there
+    /// (`ConflictPolicy::Overwrite`).
+    Overwrite,
+    /// The name is already clean; `to` equals `from`.
+    Unchanged,
+    /// Nothing happens. For `SkipReason::Exists`, `to` is the name that
+    /// was taken; otherwise it equals `from`.
+    Skip(SkipReason),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameOp {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub kind: RenameKind,
+}
+
+impl RenameOp {
+    /// Whether applying this operation touches the filesystem.
+    pub fn is_rename(&self) -> bool {
+        matches!(self.kind, RenameKind::Rename | RenameKind::Overwrite)
+    }
+
+    /// Where the entry lives once the operation has been applied.
+    pub fn final_path(&self) -> &Path {
+        match self.kind {
+            RenameKind::Skip(_) => &self.from,
+            _ => &self.to,
+        }
+    }
+}
+
+/// A problem that makes a plan unsafe to apply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanError {
+    /// Two operations rename to the same path.
+    Collision {
+        to: PathBuf,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    /// `from` would be renamed onto `to` before `to` itself is moved away.
+    Chain { from: PathBuf, to: PathBuf },
+    /// The operations starting at these sources rename in a circle.
+    Cycle(Vec<PathBuf>),
+    /// `from` is inside a directory that an earlier operation renames.
+    ParentRenamed { from: PathBuf, parent: PathBuf },
+    /// The source path no longer exists.
+    Missing(PathBuf),
+    /// The new name `to` is taken and `ConflictPolicy::Fail` is in effect.
+    Exists { from: PathBuf, to: PathBuf },
+}
+
+impl PlanError {
+    /// The path the problem is about: the source of the offending
+    /// rename, or the unwritable directory.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Collision { second: path, .. }
+            | Self::Chain { from: path, .. }
+            | Self::ParentRenamed { from: path, .. }
+            | Self::Missing(path)
+            | Self::Exists { from: path, .. } => path,
+            Self::Cycle(paths) => paths.first().map_or(Path::new(""), |p| p),
+        }
+    }
+
+    /// The new name involved, if the problem is about one.
+    pub fn new_path(&self) -> Option<&Path> {
+        match self {
+            Self::Collision { to, .. }
+            | Self::Chain { to, .. }
+            | Self::Exists { to, .. } => Some(to),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Collision { to, first, second } => write!(
+                f,
+                "both '{}' and '{}' would be renamed to '{}'",
+                first.display(),
+                second.display(),
+                to.display()
+            ),
+            Self::Chain { from, to } => write!(
+                f,
+                "renaming '{}' would replace '{}' before it is renamed",
+                from.display(),
+                to.display()
+            ),
+            Self::Cycle(paths) => {
+                write!(f, "renames form a cycle:")?;
+                for path in paths {
+                    write!(f, " '{}'", path.display())?;
+                }
+                Ok(())
+            }
+            Self::ParentRenamed { from, parent } => write!(
+                f,
+                "'{}' is inside '{}', which is renamed first",
+                from.display(),
+                parent.display()
+            ),
+            Self::Missing(path) => {
+                write!(f, "'{}' does not exist", path.display())
+            }
+            Self::Exists { from, to } => write!(
+                f,
+                "new file name '{}' for '{}' already exists",
+                to.display(),
+                from.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+/// An ordered list of rename operations. Children always come before
+/// the directory that contains them, so applying the operations in
+/// order never invalidates a later `from` path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenamePlan {
+    pub ops: Vec<RenameOp>,
+    /// Symlinks found while planning, as they were named then, for
+    /// `symlinks::plan_fixes`.
+    pub links: Vec<PathBuf>,
+}
+
+impl RenamePlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Operations that touch the filesystem.
+    pub fn renames(&self) -> impl Iterator<Item = &RenameOp> {
+        self.ops.iter().filter(|op| op.is_rename())
+    }
+
+    /// Skip every rename whose new absolute path, once the directories
+    /// above it have been renamed too, is longer than `max_bytes`.
+    pub fn skip_long_paths(&mut self, max_bytes: usize) {
+        let renamed: HashMap<PathBuf, PathBuf> = self
+            .renames()
+            .map(|op| (op.from.clone(), op.to.clone()))
+            .collect();
+        for op in &mut self.ops {
+            if !op.is_rename() {
+                continue;
+            }
+            let full = std::path::absolute(final_location(&op.to, &renamed))
+                .unwrap_or_else(|_| op.to.clone());
+            if full.as_os_str().len() > max_bytes {
+                op.kind = RenameKind::Skip(SkipReason::PathTooLong);
+            }
+        }
+    }
+
+    /// Skip every rename out of or into a directory whose permissions
+    /// say it is read-only. Other renames still go ahead.
+    pub fn skip_read_only(&mut self) {
+        let mut read_only: HashMap<PathBuf, bool> = HashMap::new();
+        for op in &mut self.ops {
+            if !op.is_rename() {
+                continue;
+            }
+            let blocked = [dir_of(&op.from), dir_of(&op.to)].iter().any(|dir| {
+                *read_only.entry(dir.to_path_buf()).or_insert_with(|| {
+                    fs::metadata(dir)
+                        .is_ok_and(|m| m.permissions().readonly())
+                })
+            });
+            if blocked {
+                op.kind = RenameKind::Skip(SkipReason::ReadOnly);
+            }
+        }
+    }
+
+    /// Check the plan against the filesystem as it is now. Returns every
+    /// problem found, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<PlanError>> {
+        let mut problems = Vec::new();
+        let active: Vec<&RenameOp> = self.renames().collect();
+
+        let mut by_from: HashMap<&Path, usize> = HashMap::new();
+        for (idx, op) in active.iter().enumerate() {
+            by_from.insert(op.from.as_path(), idx);
+        }
+
+        let mut targets: HashMap<&Path, &Path> = HashMap::new();
+        let mut moved: HashSet<&Path> = HashSet::new();
+        let mut in_cycle: HashSet<usize> = HashSet::new();
+
+        for (idx, op) in active.iter().enumerate() {
+            if let Some(first) = targets.insert(&op.to, &op.from) {
+                problems.push(PlanError::Collision {
+                    to: op.to.clone(),
+                    first: first.to_path_buf(),
+                    second: op.from.clone(),
+                });
+            }
+
+            if let Some(parent) =
+                op.from.ancestors().skip(1).find(|a| moved.contains(a))
+            {
+                problems.push(PlanError::ParentRenamed {
+                    from: op.from.clone(),
+                    parent: parent.to_path_buf(),
+                });
+            }
+            moved.insert(&op.from);
+
+            if let Some(&next) = by_from.get(op.to.as_path())
+                && next > idx
+                && !in_cycle.contains(&idx)
+            {
+                match cycle_from(&active, &by_from, idx) {
+                    Some(cycle) => {
+                        problems.push(PlanError::Cycle(
+                            cycle
+                                .iter()
+                                .map(|&i| active[i].from.clone())
+                                .collect(),
+                        ));
+                        in_cycle.extend(cycle);
+                    }
+                    None => problems.push(PlanError::Chain {
+                        from: op.from.clone(),
+                        to: op.to.clone(),
+                    }),
+                }
+            }
+
+            if fs::symlink_metadata(&op.from).is_err() {
+                problems.push(PlanError::Missing(op.from.clone()));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Where `path` ends up once every renamed directory above it has moved.
+pub(crate) fn final_location(
+    path: &Path,
+    renamed: &HashMap<PathBuf, PathBuf>,
+) -> PathBuf {
+    for ancestor in path.ancestors().skip(1) {
+        if let Some(new) = renamed.get(ancestor) {
+            let rest = path.strip_prefix(ancestor).unwrap_or(path);
+            return final_location(new, renamed).join(rest);
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Follow `to -> from` links starting at `start`; returns the indices
+/// visited if they lead back to `start`.
+fn cycle_from(
+    ops: &[&RenameOp],
+    by_from: &HashMap<&Path, usize>,
+    start: usize,
+) -> Option<Vec<usize>> {
+    let mut seen = vec![start];
+    let mut cur = start;
+    while let Some(&next) = by_from.get(ops[cur].to.as_path()) {
+        if next == start {
+            return Some(seen);
+        }
+        if seen.contains(&next) {
+            return None;
+        }
+        seen.push(next);
+        cur = next;
+    }
+    None
+}
+
+/// Combine validation problems into a single error for callers that
+/// only deal in `io::Error`.
+pub fn problems_to_io_error(problems: &[PlanError]) -> io::Error {
+    let msg = problems
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ");
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Whether anything, including a dangling symlink, exists at `path`.
+fn path_taken(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+/// The directory `path` lives in, with "" spelled as ".".
+fn dir_of(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+fn fold_name(name: &OsStr) -> String {
+    name.to_string_lossy().to_lowercase()
+}
+
+/// Whether `from` and `to` are the same name in the same directory
+/// apart from letter case, e.g. `Photo.JPG` and `photo.JPG`.
+pub fn is_case_only(from: &Path, to: &Path) -> bool {
+    match (from.file_name(), to.file_name()) {
+        (Some(a), Some(b)) => {
+            a != b && dir_of(from) == dir_of(to) && fold_name(a) == fold_name(b)
+        }
+        _ => false,
+    }
+}
+
+fn swap_case(name: &str) -> String {
+    name.chars()
+        .flat_map(|c| {
+            if c.is_uppercase() {
+                c.to_lowercase().collect::<Vec<_>>()
+            } else {
+                c.to_uppercase().collect()
+            }
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    a.len() == b.len() && a.modified().ok() == b.modified().ok()
+}
+
+/// Detect whether name lookups in `dir` ignore case (vfat, exFAT, CIFS,
+/// ext4 with casefold, default APFS, ...). An existing entry is looked up
+/// with its case swapped; only if there is none with letters in its name,
+/// and `may_write` allows it, is a probe file created. Otherwise, and on
+/// errors, the directory counts as case-sensitive.
+pub fn detect_case_insensitive(dir: &Path, may_write: bool) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        let swapped = swap_case(name);
+        if swapped == name {
+            continue;
+        }
+        return match (
+            fs::symlink_metadata(entry.path()),
+            fs::symlink_metadata(dir.join(swapped)),
+        ) {
+            (Ok(a), Ok(b)) => same_file(&a, &b),
+            _ => false,
+        };
+    }
+    if !may_write {
+        return false;
+    }
+
+    let probe =
+        dir.join(format!(".sanitize_filenames-probe-{}", std::process::id()));
+    if fs::File::create_new(&probe).is_err() {
+        return false;
+    }
+    let insensitive = path_taken(&dir.join(swap_case(
+        &probe.file_name().unwrap_or_default().to_string_lossy(),
+    )));
+    let _ = fs::remove_file(&probe);
+    insensitive
+}
+
+/// Turns desired renames into `RenameOp`s, resolving name conflicts
+/// against the filesystem and against every rename planned so far.
+#[derive(Debug, Clone)]
+pub struct Planner {
+    pub on_conflict: ConflictPolicy,
+    /// Separator used by `ConflictPolicy::Suffix`.
+    pub separator: String,
+    /// Compare names case-insensitively: `Some(true)` everywhere (e.g.
+    /// for a case-insensitive `--target`), `Some(false)` nowhere, `None`
+    /// in directories detected as case-insensitive.
+    pub fold_case: Option<bool>,
+    /// Where the extension starts, for `ConflictPolicy::Suffix`.
+    pub extensions: ExtensionRules,
+    /// Name length limit that names with a `ConflictPolicy::Suffix`
+    /// suffix must still fit.
+    pub max_length: Option<LengthLimit>,
+    /// Whether case detection may create a probe file in directories
+    /// without a name to look up. Off by default, and in dry runs.
+    pub probe: bool,
+    claimed: HashSet<PathBuf>,
+    /// Directories resolved for claim keys, so that different spellings
+    /// of one directory (`a/../b`, links) claim the same names.
+    resolved: HashMap<PathBuf, PathBuf>,
+    detected: HashMap<PathBuf, bool>,
+    /// Folded names of the existing entries of case-insensitive
+    /// directories, listed once per directory.
+    listings: HashMap<PathBuf, HashMap<String, Vec<PathBuf>>>,
+}
+
+impl Planner {
+    pub fn new(on_conflict: ConflictPolicy, separator: &str) -> Self {
+        Self {
+            on_conflict,
+            separator: crate::filler(separator).to_string(),
+            fold_case: None,
+            extensions: ExtensionRules::default(),
+            max_length: None,
+            probe: false,
+            claimed: HashSet::new(),
+            resolved: HashMap::new(),
+            detected: HashMap::new(),
+            listings: HashMap::new(),
+        }
+    }
+
+    /// Insert `_N` (with `separator` in place of `_`) before the
+    /// extension of `path`, as `extensions` defines it, so `a.tar.gz`
+    /// becomes `a_1.tar.gz`. Directories have no extension, so the
+    /// suffix goes last. With `max_length`, the stem is shortened to
+    /// make room for the suffix.
+    fn with_numeric_suffix(
+        &self,
+        path: &Path,
+        is_dir: bool,
+        n: usize,
+    ) -> PathBuf {
+        let name = path.file_name().unwrap_or_default();
+        let suffix = format!("{}{n}", self.separator);
+        let fit = |stem, rest: &str| match &self.max_length {
+            Some(limit) => limit.fit_stem(stem, rest, &self.separator),
+            None => stem,
+        };
+        let new_name = match name.to_str() {
+            Some(name) if is_dir => {
+                format!("{}{suffix}", fit(name, &suffix)).into()
+            }
+            _ if is_dir => {
+                let mut new_name = name.to_os_string();
+                new_name.push(&suffix);
+                new_name
+            }
+            Some(name) => {
+                let ext = self.extensions.extension_of(name);
+                let stem = &name[..name.len() - ext.len()];
+                match stem.strip_suffix('.') {
+                    // A dotfile such as `.bashrc` is all stem.
+                    Some(stem) if !stem.is_empty() => {
+                        let rest = format!("{suffix}.{ext}");
+                        format!("{}{rest}", fit(stem, &rest)).into()
+                    }
+                    _ => format!("{}{suffix}", fit(name, &suffix)).into(),
+                }
+            }
+            // Names that are not UTF-8 only get a plain extension.
+            None => {
+                let stem = path.file_stem().unwrap_or(name);
+                let mut new_name = stem.to_os_string();
+                new_name.push(&suffix);
+                if let Some(ext) = path.extension() {
+                    new_name.push(".");
+                    new_name.push(ext);
+                }
+                new_name
+            }
+        };
+        path.with_file_name(new_name)
+    }
+
+    fn folds_case(&mut self, dir: &Path) -> bool {
+        if let Some(fold) = self.fold_case {
+            return fold;
+        }
+        *self
+            .detected
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| detect_case_insensitive(dir, self.probe))
+    }
+
+    /// The key `path` is claimed under: its name in the resolved
+    /// directory, folded if the directory is case-insensitive.
+    fn claim_key(&mut self, path: &Path) -> PathBuf {
+        let dir = dir_of(path);
+        let Some(name) = path.file_name() else {
+            return path.to_path_buf();
+        };
+        let folds = self.folds_case(dir);
+        let resolved = self
+            .resolved
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+            });
+        if folds {
+            resolved.join(fold_name(name))
+        } else {
+            resolved.join(name)
+        }
+    }
+
+    /// Whether another rename planned so far ends up at `path`.
+    fn is_claimed(&mut self, path: &Path) -> bool {
+        let key = self.claim_key(path);
+        self.claimed.contains(&key)
+    }
+
+    /// Whether `path` is taken by something other than `from`.
+    fn is_taken(&mut self, from: &Path, path: &Path) -> bool {
+        if self.is_claimed(path) {
+            return true;
+        }
+        let dir = dir_of(path);
+        let Some(name) = path.file_name() else {
+            return path_taken(path);
+        };
+        if !self.folds_case(dir) {
+            return path_taken(path);
+        }
+        let listing =
+            self.listings.entry(dir.to_path_buf()).or_insert_with(|| {
+                let mut listing: HashMap<String, Vec<PathBuf>> = HashMap::new();
+                for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+                    listing
+                        .entry(fold_name(&entry.file_name()))
+                        .or_default()
+                        .push(dir.join(entry.file_name()));
+                }
+                listing
+            });
+        let from_here = dir_of(from) == dir;
+        listing.get(&fold_name(name)).is_some_and(|paths| {
+            paths
+                .iter()
+                .any(|p| !(from_here && p.file_name() == from.file_name()))
+        })
+    }
+
+    /// Decide what renaming `from` to `to` should do. Fails only for
+    /// `ConflictPolicy::Fail`.
+    pub fn plan_rename(
+        &mut self,
+        from: &Path,
+        to: &Path,
+    ) -> io::Result<RenameOp> {
+        let op = |to: &Path, kind| RenameOp {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            kind,
+        };
+
+        if from == to {
+            return Ok(op(to, RenameKind::Unchanged));
+        } else if !path_taken(from) {
+            return Ok(op(from, RenameKind::Skip(SkipReason::Missing)));
+        }
+
+        let mut to = to.to_path_buf();
+        let mut kind = RenameKind::Rename;
+        if self.is_taken(from, &to) {
+            let claimed = self.is_claimed(&to);
+            match self.on_conflict {
+                ConflictPolicy::Skip => {
+                    return Ok(op(&to, RenameKind::Skip(SkipReason::Exists)));
+                }
+                ConflictPolicy::Fail => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        PlanError::Exists {
+                            from: from.to_path_buf(),
+                            to,
+                        },
+                    ));
+                }
+                // Overwriting is for files that were there before the
+                // run, not for the result of an earlier rename: the
+                // first source to claim a name keeps it.
+                ConflictPolicy::Overwrite if claimed => {
+                    return Ok(op(&to, RenameKind::Skip(SkipReason::Exists)));
+                }
+                ConflictPolicy::Overwrite => kind = RenameKind::Overwrite,
+                ConflictPolicy::Suffix => {
+                    let is_dir = from.is_dir();
+                    let mut n = 1;
+                    let mut candidate =
+                        self.with_numeric_suffix(&to, is_dir, n);
+                    while candidate != from && self.is_taken(from, &candidate) {
+                        n += 1;
+                        candidate = self.with_numeric_suffix(&to, is_dir, n);
+                    }
+                    to = candidate;
+                }
+            }
+        }
+
+        if to == from {
+            // Only possible when `Suffix` lands back on the current name.
+            return Ok(op(&to, RenameKind::Unchanged));
+        }
+
+        let key = self.claim_key(&to);
+        self.claimed.insert(key);
+        Ok(op(&to, kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename(from: &str, to: &str) -> RenameOp {
+        RenameOp {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+            kind: RenameKind::Rename,
+        }
+    }
+
+    fn problems(ops: Vec<RenameOp>) -> Vec<PlanError> {
+        let plan = RenamePlan {
+            ops,
+            ..RenamePlan::default()
+        };
+        plan.validate()
+            .unwrap_err()
+            .into_iter()
+            .filter(|p| !matches!(p, PlanError::Missing(_)))
+            .collect()
+    }
+
+    #[test]
+    fn validate_reports_collisions() {
+        let found = problems(vec![
+            rename("/x/a b", "/x/a_b"),
+            rename("/x/a,b", "/x/a_b"),
+        ]);
+        assert_eq!(
+            found,
+            vec![PlanError::Collision {
+                to: PathBuf::from("/x/a_b"),
+                first: PathBuf::from("/x/a b"),
+                second: PathBuf::from("/x/a,b"),
+            }]
+        );
+    }
+
+    #[test]
+    fn validate_reports_chains_and_cycles() {
+        let found =
+            problems(vec![rename("/x/a", "/x/b"), rename("/x/b", "/x/c")]);
+        assert_eq!(
+            found,
+            vec![PlanError::Chain {
+                from: PathBuf::from("/x/a"),
+                to: PathBuf::from("/x/b"),
+            }]
+        );
+
+        let found =
+            problems(vec![rename("/x/a", "/x/b"), rename("/x/b", "/x/a")]);
+        assert_eq!(
+            found,
+            vec![PlanError::Cycle(vec![
+                PathBuf::from("/x/a"),
+                PathBuf::from("/x/b"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn validate_reports_parent_renamed_first() {
+        let found =
+            problems(vec![rename("/x/d", "/x/e"), rename("/x/d/f", "/x/d/g")]);
+        assert_eq!(
+            found,
+            vec![PlanError::ParentRenamed {
+                from: PathBuf::from("/x/d/f"),
+                parent: PathBuf::from("/x/d"),
+            }]
+        );
+    }
+
+    #[test]
+    fn validate_ignores_operations_that_do_nothing() {
+        let plan = RenamePlan {
+            ops: vec![RenameOp {
+                from: PathBuf::from("/does/not/exist"),
+                to: PathBuf::from("/does/not/exist"),
+                kind: RenameKind::Unchanged,
+            }],
+            ..RenamePlan::default()
+        };
+        assert_eq!(plan.validate(), Ok(()));
+    }
+
+    #[test]
+    fn case_only_renames_are_recognised() {
+        assert!(is_case_only(
+            Path::new("d/Photo.JPG"),
+            Path::new("d/photo.JPG")
+        ));
+        assert!(!is_case_only(
+            Path::new("d/photo.JPG"),
+            Path::new("d/photo.JPG")
+        ));
+        assert!(!is_case_only(Path::new("a/Photo"), Path::new("b/photo")));
+        assert!(!is_case_only(Path::new("Photo"), Path::new("Photo_1")));
+    }
+
+    #[test]
+    fn suffixes_go_before_compound_extensions() {
+        let mut planner = Planner::new(ConflictPolicy::Suffix, "-");
+        let suffixed = |planner: &Planner, name: &str, is_dir: bool| {
+            planner.with_numeric_suffix(&Path::new("d").join(name), is_dir, 1)
+        };
+        assert_eq!(
+            suffixed(&planner, "a.tar.gz", false),
+            Path::new("d/a-1.tar.gz")
+        );
+        assert_eq!(suffixed(&planner, "v1.2", false), Path::new("d/v1.2-1"));
+        assert_eq!(
+            suffixed(&planner, ".bashrc", false),
+            Path::new("d/.bashrc-1")
+        );
+        assert_eq!(
+            suffixed(&planner, "a.tar.gz", true),
+            Path::new("d/a.tar.gz-1")
+        );
+
+        planner.extensions.max_parts = 0;
+        assert_eq!(
+            suffixed(&planner, "a.tar.gz", false),
+            Path::new("d/a.tar.gz-1")
+        );
+    }
+
+    #[test]
+    fn detects_case_sensitive_directories() {
+        let dir = std::env::temp_dir().join(format!(
+            "sanitize_filenames_case_probe_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Mixed"), "test").unwrap();
+        let with_entry = detect_case_insensitive(&dir, false);
+        fs::remove_file(dir.join("Mixed")).unwrap();
+        // Without an entry to look up, a dry run assumes case-sensitive
+        // rather than write anything.
+        assert!(!detect_case_insensitive(&dir, false));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        let with_probe = detect_case_insensitive(&dir, true);
+        // The probe must clean up after itself.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+
+        assert_eq!(with_entry, with_probe);
+    }
+}
@@ -0,0 +1,197 @@
+//! Regular expression rules for `--regex` and `--regex-post`.
+//!
+//! Patterns use the syntax of the `regex` crate, which matches in linear
+//! time whatever the pattern, so a rule cannot hang on a long name.
+//!
+//! In a replacement, `$1` to `$9` and `${N}` insert capture groups, `$0`
+//! the whole match and `$$` a dollar sign. Unlike the crate's own
+//! syntax, `$1a` is group 1 followed by `a`.
+
+use ::regex::Regex;
+
+/// One `PATTERN=REPLACEMENT` rule.
+#[derive(Debug, Clone)]
+pub struct RegexRule {
+    pub regex: Regex,
+    /// The replacement in the syntax `Regex::replace_all` expects.
+    replacement: String,
+}
+
+impl PartialEq for RegexRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str()
+            && self.replacement == other.replacement
+    }
+}
+
+impl Eq for RegexRule {}
+
+impl RegexRule {
+    /// Parse `PATTERN=REPLACEMENT`. The first `=` not preceded by a
+    /// backslash separates the two; `\=` in the pattern matches `=`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut escaped = false;
+        let split = spec.char_indices().find_map(|(i, c)| {
+            let found = c == '=' && !escaped;
+            escaped = c == '\\' && !escaped;
+            found.then_some(i)
+        });
+        let Some(split) = split else {
+            return Err(format!(
+                "Invalid regex rule '{spec}' (expected PATTERN=REPLACEMENT)"
+            ));
+        };
+        let (pattern, replacement) = (&spec[..split], &spec[split + 1..]);
+        if replacement.contains('/') {
+            return Err(format!("Regex rule '{spec}' may not produce '/'"));
+        }
+        let regex = Regex::new(pattern)
+            .map_err(|err| format!("Invalid regex in rule '{spec}': {err}"))?;
+        let (replacement, max_group) = expand_syntax(replacement)?;
+        let groups = regex.captures_len() - 1;
+        if max_group > groups {
+            return Err(format!(
+                "Regex rule '{spec}' refers to group {max_group} but has \
+                 only {groups}"
+            ));
+        }
+        Ok(Self { regex, replacement })
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        self.regex
+            .replace_all(text, self.replacement.as_str())
+            .into_owned()
+    }
+}
+
+/// Translate `replacement` into the crate's syntax, with every group
+/// reference spelled `${N}`. Also returns the highest group referred to.
+fn expand_syntax(replacement: &str) -> Result<(String, usize), String> {
+    let mut out = String::new();
+    let mut max_group = 0;
+    let mut chars = replacement.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '$' {
+            out.push(ch);
+            continue;
+        }
+        let group = match chars.peek() {
+            Some('$') => {
+                chars.next();
+                out.push_str("$$");
+                continue;
+            }
+            Some(&d) if d.is_ascii_digit() => {
+                chars.next();
+                d.to_digit(10).map(|d| d as usize)
+            }
+            Some('{') => {
+                chars.next();
+                let digits: String =
+                    chars.by_ref().take_while(|&c| c != '}').collect();
+                digits.parse().ok()
+            }
+            _ => None,
+        };
+        let Some(group) = group else {
+            return Err(format!("Invalid group reference in '{replacement}'"));
+        };
+        max_group = max_group.max(group);
+        out.push_str(&format!("${{{group}}}"));
+    }
+    Ok((out, max_group))
+}
+
+/// Rules applied to the stem before and after the character mapping.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegexRules {
+    pub pre: Vec<RegexRule>,
+    pub post: Vec<RegexRule>,
+}
+
+impl RegexRules {
+    /// Add the rules of `other` after these.
+    pub fn merge(&mut self, other: &RegexRules) {
+        self.pre.extend(other.pre.iter().cloned());
+        self.post.extend(other.post.iter().cloned());
+    }
+
+    /// Apply `rules` in order.
+    pub fn apply(rules: &[RegexRule], text: &str) -> String {
+        rules
+            .iter()
+            .fold(text.to_string(), |text, rule| rule.apply(&text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(spec: &str, text: &str) -> String {
+        RegexRule::parse(spec).unwrap().apply(text)
+    }
+
+    #[test]
+    fn removes_common_clutter() {
+        assert_eq!(replace(r"\s*\(\d+\)$=", "photo (1)"), "photo");
+        assert_eq!(
+            replace(r"\s*\[[^\]]*\]=", "Movie [YTS.MX] 1080p"),
+            "Movie 1080p"
+        );
+        assert_eq!(
+            replace(r"(?i) - copy( \(\d+\))?$=", "report - Copy (2)"),
+            "report"
+        );
+        assert_eq!(
+            replace("^(\\d{4})(\\d{2})(\\d{2})=$1-$2-$3", "20240131_scan"),
+            "2024-01-31_scan"
+        );
+    }
+
+    #[test]
+    fn supports_alternation_and_quantifiers() {
+        assert_eq!(replace("cat|dog=pet", "cat and dog"), "pet and pet");
+        assert_eq!(replace("a{2,3}=x", "aaaa a aa"), "xa a x");
+        assert_eq!(replace("<.+?>=", "<b>bold</b>"), "bold");
+        assert_eq!(
+            replace(r"\bthe\b=THE", "the theme, the end"),
+            "THE theme, THE end"
+        );
+        assert_eq!(replace("x*=-", "ab"), "-a-b-");
+        assert_eq!(replace("(?:ab)+=${0}!", "ababc"), "abab!c");
+        assert_eq!(replace(r"\$=USD", "5$"), "5USD");
+        assert_eq!(replace(r"a\==b", "a=1"), "b1");
+    }
+
+    #[test]
+    fn group_references_end_after_one_digit() {
+        assert_eq!(replace("(a)(b)=$2a$1", "ab"), "baa");
+        assert_eq!(replace("(a)=${1}0", "a"), "a0");
+        assert_eq!(replace("a=$$1", "a"), "$1");
+    }
+
+    #[test]
+    fn pathological_patterns_finish_quickly() {
+        let name = format!("{}c", "a".repeat(5000));
+        let start = std::time::Instant::now();
+        for spec in ["(a*)*b=x", "(a|aa)+b=x", "(a+)+$=x", "(.*a){20}b=x"] {
+            assert_eq!(replace(spec, &name), name);
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        assert!(RegexRule::parse("no separator").is_err());
+        assert!(RegexRule::parse("(open=x").is_err());
+        assert!(RegexRule::parse("close)=x").is_err());
+        assert!(RegexRule::parse("[a=x").is_err());
+        assert!(RegexRule::parse("*=x").is_err());
+        assert!(RegexRule::parse("(a)=$2").is_err());
+        assert!(RegexRule::parse(r"\q=x").is_err());
+        assert!(RegexRule::parse("-=/").is_err());
+        assert!(RegexRule::parse("a=$x").is_err());
+    }
+}
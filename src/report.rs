@@ -0,0 +1,346 @@
+//! Progress reporting for `RenameSession`.
+//!
+//! The session never prints anything itself. Every applied operation is
+//! handed to a `Reporter`, which decides whether and where to show it.
+//! `HumanReporter` prints the classic coloured messages, `QuietReporter`
+//! discards everything, and `output::StructuredReporter` writes JSON,
+//! NDJSON or TSV records.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::color::{self, Palette};
+use crate::output::Action;
+use crate::plan::{PlanError, RenameKind, RenameOp, SkipReason};
+use crate::symlinks::LinkFix;
+
+/// The outcome of applying one planned operation.
+#[derive(Debug, Clone, Copy)]
+pub struct Event<'a> {
+    pub op: &'a RenameOp,
+    pub dry_run: bool,
+    /// Set when the rename was attempted and failed. The error is also
+    /// returned to the caller, which stops the run.
+    pub error: Option<&'a io::Error>,
+}
+
+impl Event<'_> {
+    pub fn action(&self) -> Action {
+        if self.error.is_some() {
+            return Action::Error;
+        }
+        match self.op.kind {
+            RenameKind::Rename | RenameKind::Overwrite => Action::Renamed,
+            RenameKind::Unchanged => Action::Unchanged,
+            RenameKind::Skip(SkipReason::Exists) => Action::Collision,
+            RenameKind::Skip(_) => Action::Skipped,
+        }
+    }
+
+    /// The new path, or `None` when no new name could be worked out.
+    pub fn new_path(&self) -> Option<&Path> {
+        match self.op.kind {
+            RenameKind::Skip(SkipReason::Missing | SkipReason::NotUtf8) => None,
+            _ => Some(&self.op.to),
+        }
+    }
+
+    /// A short explanation for anything other than a plain rename.
+    pub fn reason(&self) -> Option<String> {
+        if let Some(err) = self.error {
+            return Some(err.to_string());
+        }
+        let reason = match self.op.kind {
+            RenameKind::Rename | RenameKind::Unchanged => return None,
+            RenameKind::Overwrite => "replaced an existing file",
+            RenameKind::Skip(SkipReason::Missing) => "does not exist",
+            RenameKind::Skip(SkipReason::NotUtf8) => "name is not valid UTF-8",
+            RenameKind::Skip(SkipReason::Exists) => "new name already exists",
+            RenameKind::Skip(SkipReason::PathTooLong) => "new path is too long",
+            RenameKind::Skip(SkipReason::ReadOnly) => {
+                "directory is not writable"
+            }
+        };
+        Some(reason.to_string())
+    }
+}
+
+/// Receives an `Event` for every operation a session applies.
+pub trait Reporter {
+    fn report(&mut self, event: &Event<'_>) -> io::Result<()>;
+
+    /// Called for every symlink whose target `--fix-symlinks` rewrites,
+    /// once it has been rewritten.
+    fn relink(&mut self, _fix: &LinkFix, _dry_run: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called for every problem that stops the run before anything is
+    /// renamed. The problem is also returned to the caller as an error,
+    /// which is how the CLI prints it in human output.
+    fn problem(
+        &mut self,
+        _problem: &PlanError,
+        _dry_run: bool,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once when the run is over, for output that can only be
+    /// written at the end.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<R: Reporter + ?Sized> Reporter for &mut R {
+    fn report(&mut self, event: &Event<'_>) -> io::Result<()> {
+        (**self).report(event)
+    }
+
+    fn relink(&mut self, fix: &LinkFix, dry_run: bool) -> io::Result<()> {
+        (**self).relink(fix, dry_run)
+    }
+
+    fn problem(
+        &mut self,
+        problem: &PlanError,
+        dry_run: bool,
+    ) -> io::Result<()> {
+        (**self).problem(problem, dry_run)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+impl<R: Reporter + ?Sized> Reporter for Box<R> {
+    fn report(&mut self, event: &Event<'_>) -> io::Result<()> {
+        (**self).report(event)
+    }
+
+    fn relink(&mut self, fix: &LinkFix, dry_run: bool) -> io::Result<()> {
+        (**self).relink(fix, dry_run)
+    }
+
+    fn problem(
+        &mut self,
+        problem: &PlanError,
+        dry_run: bool,
+    ) -> io::Result<()> {
+        (**self).problem(problem, dry_run)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+/// Discards every event.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuietReporter;
+
+impl Reporter for QuietReporter {
+    fn report(&mut self, _event: &Event<'_>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Prints one human-readable line per event.
+#[derive(Debug)]
+pub struct HumanReporter<W> {
+    out: W,
+    palette: Palette,
+}
+
+impl HumanReporter<io::Stdout> {
+    /// Print to standard output, coloured if it is a terminal.
+    pub fn stdout() -> Self {
+        Self::new(io::stdout(), color::for_stdout())
+    }
+}
+
+impl<W: Write> HumanReporter<W> {
+    pub fn new(out: W, palette: Palette) -> Self {
+        Self { out, palette }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Reporter for HumanReporter<W> {
+    fn report(&mut self, event: &Event<'_>) -> io::Result<()> {
+        let p = self.palette;
+        let (old, new) = (event.op.from.display(), event.op.to.display());
+        if let Some(err) = event.error {
+            return writeln!(
+                self.out,
+                "{}Could not change '{old}' to '{new}': {err}{}",
+                p.red, p.reset
+            );
+        }
+        match event.op.kind {
+            RenameKind::Unchanged => writeln!(
+                self.out,
+                "{}Old name and new name are the same for '{old}'.  Not changing{}",
+                p.dim, p.reset
+            ),
+            RenameKind::Skip(SkipReason::Missing) => writeln!(
+                self.out,
+                "{}Old file name '{old}' does not exist.  Skipping{}",
+                p.yellow, p.reset
+            ),
+            RenameKind::Skip(SkipReason::NotUtf8) => writeln!(
+                self.out,
+                "{}File name '{old}' is not valid UTF-8.  Skipping (see --non-utf8){}",
+                p.yellow, p.reset
+            ),
+            RenameKind::Skip(SkipReason::Exists) => writeln!(
+                self.out,
+                "{}New file name '{new}' already exists!  Skipping{}",
+                p.yellow, p.reset
+            ),
+            RenameKind::Skip(SkipReason::PathTooLong) => writeln!(
+                self.out,
+                "{}New path '{new}' is too long (see --max-path-length).  Skipping{}",
+                p.yellow, p.reset
+            ),
+            RenameKind::Skip(SkipReason::ReadOnly) => writeln!(
+                self.out,
+                "{}Directory of '{old}' or '{new}' is not writable.  Skipping{}",
+                p.yellow, p.reset
+            ),
+            RenameKind::Rename | RenameKind::Overwrite => {
+                let (action, action_color) = if event.dry_run {
+                    ("Would change", p.cyan)
+                } else {
+                    ("Changing", p.green)
+                };
+                let note = if event.op.kind == RenameKind::Overwrite {
+                    format!(" {}(overwriting existing){}", p.yellow, p.reset)
+                } else {
+                    String::new()
+                };
+                writeln!(
+                    self.out,
+                    "{action_color}{action}{reset} '{old}' to '{bold}{new}{reset}'{note}",
+                    reset = p.reset,
+                    bold = p.bold,
+                )
+            }
+        }
+    }
+
+    fn relink(&mut self, fix: &LinkFix, dry_run: bool) -> io::Result<()> {
+        let p = self.palette;
+        let (action, action_color) = if dry_run {
+            ("Would point", p.cyan)
+        } else {
+            ("Pointing", p.green)
+        };
+        writeln!(
+            self.out,
+            "{action_color}{action}{reset} link '{link}' at '{bold}{new}{reset}' instead of '{old}'",
+            link = fix.link.display(),
+            new = fix.new_target.display(),
+            old = fix.old_target.display(),
+            reset = p.reset,
+            bold = p.bold,
+        )
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn op(kind: RenameKind) -> RenameOp {
+        RenameOp {
+            from: PathBuf::from("a b.txt"),
+            to: PathBuf::from("a_b.txt"),
+            kind,
+        }
+    }
+
+    fn human(op: &RenameOp, dry_run: bool) -> String {
+        let mut reporter = HumanReporter::new(Vec::new(), Palette::plain());
+        let event = Event {
+            op,
+            dry_run,
+            error: None,
+        };
+        reporter.report(&event).unwrap();
+        String::from_utf8(reporter.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn human_reporter_keeps_classic_messages() {
+        assert_eq!(
+            human(&op(RenameKind::Rename), false),
+            "Changing 'a b.txt' to 'a_b.txt'\n"
+        );
+        assert_eq!(
+            human(&op(RenameKind::Rename), true),
+            "Would change 'a b.txt' to 'a_b.txt'\n"
+        );
+        assert_eq!(
+            human(&op(RenameKind::Skip(SkipReason::Exists)), false),
+            "New file name 'a_b.txt' already exists!  Skipping\n"
+        );
+    }
+
+    #[test]
+    fn human_reporter_prints_failed_renames() {
+        let mut reporter = HumanReporter::new(Vec::new(), Palette::plain());
+        let err = io::Error::other("disk on fire");
+        let event = Event {
+            op: &op(RenameKind::Rename),
+            dry_run: false,
+            error: Some(&err),
+        };
+        reporter.report(&event).unwrap();
+        assert_eq!(
+            String::from_utf8(reporter.into_inner()).unwrap(),
+            "Could not change 'a b.txt' to 'a_b.txt': disk on fire\n"
+        );
+    }
+
+    #[test]
+    fn events_map_to_actions_and_reasons() {
+        let renamed = op(RenameKind::Rename);
+        let event = Event {
+            op: &renamed,
+            dry_run: false,
+            error: None,
+        };
+        assert_eq!(event.action(), Action::Renamed);
+        assert_eq!(event.reason(), None);
+
+        let missing = op(RenameKind::Skip(SkipReason::Missing));
+        let event = Event {
+            op: &missing,
+            dry_run: false,
+            error: None,
+        };
+        assert_eq!(event.action(), Action::Skipped);
+        assert_eq!(event.new_path(), None);
+        assert_eq!(event.reason().as_deref(), Some("does not exist"));
+
+        let err = io::Error::other("disk on fire");
+        let event = Event {
+            op: &renamed,
+            dry_run: false,
+            error: Some(&err),
+        };
+        assert_eq!(event.action(), Action::Error);
+        assert_eq!(event.reason().as_deref(), Some("disk on fire"));
+    }
+}
@@ -0,0 +1,188 @@
+//! User-defined character rules for `--map`, `--keep`, `--strip` and
+//! `--rules`.
+//!
+//! String replacements run first, on the original name, so their output
+//! still goes through the mode's character mapping: with `&=and`,
+//! "Rock & Roll" becomes "Rock_and_Roll". Stripped characters are then
+//! deleted, and kept characters are passed through the mapping untouched.
+//!
+//! A rules file has one rule per line: `FROM=TO` (an empty `TO` deletes
+//! `FROM`), `keep CHARS` or `strip CHARS`. Blank lines and lines starting
+//! with `#` are ignored, except that `#=...` maps `#`.
+
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CharRules {
+    /// `(from, to)` replacements. At each position the longest matching
+    /// `from` wins, and for equal ones the rule added last.
+    pub map: Vec<(String, String)>,
+    /// Characters the mode never replaces.
+    pub keep: Vec<char>,
+    /// Characters that are deleted.
+    pub strip: Vec<char>,
+}
+
+impl CharRules {
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty() && self.keep.is_empty() && self.strip.is_empty()
+    }
+
+    /// Add the rules of `other` after these, so that its mappings win.
+    pub fn merge(&mut self, other: &CharRules) {
+        self.map.extend(other.map.iter().cloned());
+        self.keep.extend(&other.keep);
+        self.strip.extend(&other.strip);
+    }
+
+    /// Add a `FROM=TO` mapping. The first `=` after the first character
+    /// separates the two, so `==eq` maps `=`.
+    pub(crate) fn add_map(&mut self, spec: &str) -> Result<(), String> {
+        let split = spec
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| c == '=')
+            .map(|(i, _)| i);
+        let Some(split) = split else {
+            return Err(format!("Invalid mapping '{spec}' (expected FROM=TO)"));
+        };
+        let (from, to) = (&spec[..split], &spec[split + 1..]);
+        if to.contains('/') {
+            return Err(format!("Mapping '{spec}' may not produce '/'"));
+        }
+        self.map.push((from.to_string(), to.to_string()));
+        Ok(())
+    }
+
+    pub(crate) fn add_keep(&mut self, chars: &str) -> Result<(), String> {
+        if chars.contains('/') {
+            return Err("'/' cannot be kept".to_string());
+        }
+        self.keep.extend(chars.chars());
+        Ok(())
+    }
+
+    pub(crate) fn add_strip(&mut self, chars: &str) {
+        self.strip.extend(chars.chars());
+    }
+
+    /// Add every rule in the rules file `text`.
+    pub(crate) fn add_file(&mut self, text: &str) -> Result<(), String> {
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.trim().is_empty()
+                || (line.starts_with('#') && !line.starts_with("#="))
+            {
+                continue;
+            }
+            let result = if let Some(chars) = line.strip_prefix("keep ") {
+                self.add_keep(chars.trim())
+            } else if let Some(chars) = line.strip_prefix("strip ") {
+                self.add_strip(chars.trim());
+                Ok(())
+            } else {
+                self.add_map(line)
+            };
+            result.map_err(|msg| format!("line {}: {msg}", idx + 1))?;
+        }
+        Ok(())
+    }
+
+    /// Read the rules file at `path`.
+    pub(crate) fn add_path(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|err| {
+            format!("Cannot read rules file '{}': {err}", path.display())
+        })?;
+        self.add_file(&text)
+            .map_err(|msg| format!("{}: {msg}", path.display()))
+    }
+
+    /// Apply the string replacements to `name`.
+    pub fn apply_map(&self, name: &str) -> String {
+        if self.map.is_empty() {
+            return name.to_string();
+        }
+        let mut out = String::with_capacity(name.len());
+        let mut rest = name;
+        while let Some(ch) = rest.chars().next() {
+            let best = self
+                .map
+                .iter()
+                .filter(|(from, _)| {
+                    !from.is_empty() && rest.starts_with(from.as_str())
+                })
+                .max_by_key(|(from, _)| from.len());
+            match best {
+                Some((from, to)) => {
+                    out.push_str(to);
+                    rest = &rest[from.len()..];
+                }
+                None => {
+                    out.push(ch);
+                    rest = &rest[ch.len_utf8()..];
+                }
+            }
+        }
+        out
+    }
+
+    pub fn keeps(&self, ch: char) -> bool {
+        self.keep.contains(&ch)
+    }
+
+    pub fn strips(&self, ch: char) -> bool {
+        self.strip.contains(&ch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_longest_match_first() {
+        let mut rules = CharRules::default();
+        rules.add_map("&=and").unwrap();
+        rules.add_map("@=at").unwrap();
+        rules.add_map("&&=both").unwrap();
+        assert_eq!(
+            rules.apply_map("R&D @ home && away"),
+            "RandD at home both away"
+        );
+
+        // A later rule for the same string wins.
+        rules.add_map("&=+").unwrap();
+        assert_eq!(rules.apply_map("a&b"), "a+b");
+    }
+
+    #[test]
+    fn parses_mapping_specs() {
+        let mut rules = CharRules::default();
+        rules.add_map("==eq").unwrap();
+        rules.add_map("!=").unwrap();
+        assert_eq!(rules.apply_map("a=b!"), "aeqb");
+        assert!(rules.add_map("&").is_err());
+        assert!(rules.add_map("=").is_err());
+        assert!(rules.add_map("+=a/b").is_err());
+    }
+
+    #[test]
+    fn reads_rules_files() {
+        let mut rules = CharRules::default();
+        rules
+            .add_file("# team rules\n&=and\n#=sharp\n\nkeep +\nstrip !?\n")
+            .unwrap();
+        assert_eq!(
+            rules.map,
+            [("&".into(), "and".into()), ("#".into(), "sharp".into())]
+        );
+        assert_eq!(rules.keep, ['+']);
+        assert_eq!(rules.strip, ['!', '?']);
+
+        let err = CharRules::default()
+            .add_file("&=and\nnonsense\n")
+            .unwrap_err();
+        assert!(err.starts_with("line 2:"));
+    }
+}
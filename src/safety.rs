@@ -0,0 +1,135 @@
+//! Guards against renaming where it does damage: protected roots such as
+//! `/` and `$HOME`, which are refused unless `--force` is given, and
+//! mount points, which recursion does not cross with `--one-file-system`.
+//!
+//! Pseudo-filesystems (`/proc`, `/sys`, `/dev/pts`, ...) are never
+//! entered, whatever the options. They are found in
+//! `/proc/self/mountinfo`, so this only works on Linux.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// System directories directly below `/` that are never renamed or
+/// walked without `--force`.
+const PROTECTED: [&str; 19] = [
+    "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/lib32", "/lib64",
+    "/opt", "/proc", "/root", "/run", "/sbin", "/srv", "/sys", "/usr", "/var",
+    "/System", "/Users",
+];
+
+/// Filesystem types that are not backed by files anyone wants renamed.
+const PSEUDO_FS: [&str; 17] = [
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "proc",
+    "pstore",
+    "securityfs",
+    "sysfs",
+];
+
+/// Refuse `target` if it is a filesystem root, `$HOME` or one of the
+/// system directories, however it is spelled.
+pub fn check_target(target: &Path) -> io::Result<()> {
+    // Targets that do not exist are reported by the planner.
+    let Ok(canonical) = fs::canonicalize(target) else {
+        return Ok(());
+    };
+    let home = env::var_os("HOME").and_then(|home| fs::canonicalize(home).ok());
+    let protected = canonical.parent().is_none()
+        || home.as_deref() == Some(canonical.as_path())
+        || PROTECTED.iter().any(|dir| canonical == Path::new(dir));
+    if protected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Refusing to operate on '{}' (use --force to override)",
+                canonical.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The device `meta` lives on, where the platform says.
+#[cfg(unix)]
+pub fn device(meta: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device(_meta: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// The devices of the mounted pseudo-filesystems.
+pub fn pseudo_devices() -> HashSet<u64> {
+    fs::read_to_string("/proc/self/mountinfo")
+        .map(|text| {
+            text.lines()
+                .filter_map(parse_mountinfo)
+                .filter(|(_, fs_type)| PSEUDO_FS.contains(fs_type))
+                .map(|(dev, _)| dev)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The device and filesystem type of a `/proc/self/mountinfo` line.
+fn parse_mountinfo(line: &str) -> Option<(u64, &str)> {
+    let mut fields = line.split(' ');
+    let (major, minor) = fields.nth(2)?.split_once(':')?;
+    let fs_type = fields.skip_while(|&field| field != "-").nth(1)?;
+    Some((makedev(major.parse().ok()?, minor.parse().ok()?), fs_type))
+}
+
+/// The `st_dev` value for a device number, as glibc encodes it.
+fn makedev(major: u64, minor: u64) -> u64 {
+    ((major & 0xffff_f000) << 32)
+        | ((major & 0x0000_0fff) << 8)
+        | ((minor & 0xffff_ff00) << 12)
+        | (minor & 0x0000_00ff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_protected_roots() {
+        assert!(check_target(Path::new("/")).is_err());
+        assert!(check_target(Path::new("/usr/..")).is_err());
+        assert!(check_target(Path::new("/etc")).is_err());
+        assert!(check_target(&env::temp_dir()).is_ok());
+        assert!(check_target(Path::new("/does/not/exist")).is_ok());
+    }
+
+    #[test]
+    fn parses_mountinfo_lines() {
+        assert_eq!(
+            parse_mountinfo("23 28 0:22 / /proc rw,relatime - proc proc rw"),
+            Some((22, "proc"))
+        );
+        assert_eq!(
+            parse_mountinfo(
+                "30 28 8:1 / /mnt/my\\040disk rw shared:1 - ext4 /dev/sda1 rw"
+            ),
+            Some((0x801, "ext4"))
+        );
+        assert_eq!(parse_mountinfo("garbage"), None);
+    }
+}
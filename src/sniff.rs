@@ -0,0 +1,266 @@
+//! Content sniffing for `--fix-extensions`.
+//!
+//! A file's type is recognized from its first few bytes. Each type has a
+//! canonical extension, which is added to names without one, and a list
+//! of other extensions that are accepted as they are, so that files in
+//! formats built on another (Office documents in ZIP, camera raws in
+//! TIFF) are never "corrected" to the container's extension.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// How much of a file is read to recognize it. Enough for the tar header
+/// and the first entries of a ZIP archive.
+const HEAD_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileType {
+    /// Canonical extension, without a dot.
+    pub extension: &'static str,
+    /// Other extensions used for the same content.
+    pub also: &'static [&'static str],
+}
+
+impl FileType {
+    const fn new(
+        extension: &'static str,
+        also: &'static [&'static str],
+    ) -> Self {
+        Self { extension, also }
+    }
+
+    /// Whether `extension` (without its dot, possibly compound) fits this
+    /// type. Only the last segment is compared, ignoring case.
+    pub fn accepts(&self, extension: &str) -> bool {
+        let last = extension.rsplit('.').next().unwrap_or(extension);
+        last.eq_ignore_ascii_case(self.extension)
+            || self.also.iter().any(|ext| last.eq_ignore_ascii_case(ext))
+    }
+}
+
+const ZIP_FAMILY: &[&str] = &[
+    "jar", "war", "apk", "aab", "ipa", "xpi", "crx", "whl", "nupkg", "vsix",
+    "kmz", "cbz", "3mf", "docx", "docm", "dotx", "xlsx", "xlsm", "xltx",
+    "pptx", "pptm", "potx", "odt", "ods", "odp", "odg", "epub", "sketch",
+];
+const OOXML_WORD: FileType = FileType::new("docx", &["docm", "dotx", "dotm"]);
+const OOXML_EXCEL: FileType =
+    FileType::new("xlsx", &["xlsm", "xltx", "xltm", "xlsb"]);
+const OOXML_POWERPOINT: FileType =
+    FileType::new("pptx", &["pptm", "potx", "potm", "ppsx"]);
+const TIFF_FAMILY: &[&str] = &[
+    "tiff", "dng", "nef", "nrw", "cr2", "arw", "sr2", "srf", "orf", "rw2",
+    "pef", "srw", "3fr", "erf", "kdc", "mos", "iiq",
+];
+const MP4_FAMILY: &[&str] =
+    &["m4v", "m4a", "m4b", "m4p", "mov", "3gp", "3g2", "f4v"];
+
+/// Signatures that are a fixed byte string at a fixed offset.
+const MAGIC: &[(usize, &[u8], FileType)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", FileType::new("png", &["apng"])),
+    (
+        0,
+        b"\xff\xd8\xff",
+        FileType::new("jpg", &["jpeg", "jpe", "jfif"]),
+    ),
+    (0, b"GIF87a", FileType::new("gif", &[])),
+    (0, b"GIF89a", FileType::new("gif", &[])),
+    (0, b"II*\0", FileType::new("tif", TIFF_FAMILY)),
+    (0, b"MM\0*", FileType::new("tif", TIFF_FAMILY)),
+    (0, b"8BPS", FileType::new("psd", &["psb"])),
+    (0, b"%PDF-", FileType::new("pdf", &["ai"])),
+    (0, b"{\\rtf", FileType::new("rtf", &[])),
+    (0, b"ID3", FileType::new("mp3", &[])),
+    (0, b"fLaC", FileType::new("flac", &[])),
+    (
+        0,
+        b"OggS",
+        FileType::new("ogg", &["oga", "ogv", "opus", "spx"]),
+    ),
+    (0, b"MThd", FileType::new("mid", &["midi"])),
+    (
+        0,
+        b"\x1f\x8b",
+        FileType::new("gz", &["tgz", "gzip", "svgz"]),
+    ),
+    (0, b"BZh", FileType::new("bz2", &["tbz2", "tbz"])),
+    (0, b"\xfd7zXZ\0", FileType::new("xz", &["txz"])),
+    (0, b"\x28\xb5\x2f\xfd", FileType::new("zst", &["tzst"])),
+    (0, b"7z\xbc\xaf\x27\x1c", FileType::new("7z", &[])),
+    (0, b"Rar!\x1a\x07", FileType::new("rar", &["cbr"])),
+    (257, b"ustar", FileType::new("tar", &[])),
+];
+
+/// Recognize the type of a file from its first bytes.
+pub fn detect(head: &[u8]) -> Option<FileType> {
+    let at = |offset: usize, magic: &[u8]| {
+        head.get(offset..offset + magic.len()) == Some(magic)
+    };
+
+    if at(0, b"PK\x03\x04") || at(0, b"PK\x05\x06") {
+        return Some(detect_zip(head));
+    }
+    if at(0, b"RIFF") {
+        return match head.get(8..12) {
+            Some(b"WEBP") => Some(FileType::new("webp", &[])),
+            Some(b"WAVE") => Some(FileType::new("wav", &["wave"])),
+            Some(b"AVI ") => Some(FileType::new("avi", &[])),
+            _ => None,
+        };
+    }
+    if at(4, b"ftyp") {
+        return head.get(8..12).map(|brand| match brand {
+            b"heic" | b"heix" | b"heim" | b"heis" | b"mif1" | b"msf1" => {
+                FileType::new("heic", &["heif", "hif"])
+            }
+            b"avif" | b"avis" => FileType::new("avif", &[]),
+            b"qt  " => FileType::new("mov", &["qt", "mp4"]),
+            b"M4A " | b"M4B " => FileType::new("m4a", &["m4b", "mp4"]),
+            _ => FileType::new("mp4", MP4_FAMILY),
+        });
+    }
+    if at(0, b"\x1a\x45\xdf\xa3") {
+        let head = &head[..head.len().min(64)];
+        return Some(if contains(head, b"webm") {
+            FileType::new("webm", &["mkv"])
+        } else {
+            FileType::new("mkv", &["mka", "mk3d", "webm"])
+        });
+    }
+    // The reserved header fields of a BMP are zero; "BM" alone is too
+    // common a start for a text file.
+    if at(0, b"BM") && at(6, b"\0\0\0\0") {
+        return Some(FileType::new("bmp", &["dib"]));
+    }
+    if at(0, b"\xff\xfb") || at(0, b"\xff\xf3") || at(0, b"\xff\xf2") {
+        return Some(FileType::new("mp3", &[]));
+    }
+
+    MAGIC
+        .iter()
+        .find(|(offset, magic, _)| at(*offset, magic))
+        .map(|&(_, _, kind)| kind)
+}
+
+/// ZIP archives are also the container for Office and OpenDocument
+/// files; tell those apart by the first entries.
+fn detect_zip(head: &[u8]) -> FileType {
+    // OpenDocument and EPUB store an uncompressed `mimetype` entry first.
+    if head.get(30..38) == Some(b"mimetype") {
+        let mime = &head[38..head.len().min(100)];
+        for (prefix, kind) in [
+            (&b"application/epub+zip"[..], FileType::new("epub", &[])),
+            (
+                b"application/vnd.oasis.opendocument.text",
+                FileType::new("odt", &["ott"]),
+            ),
+            (
+                b"application/vnd.oasis.opendocument.spreadsheet",
+                FileType::new("ods", &["ots"]),
+            ),
+            (
+                b"application/vnd.oasis.opendocument.presentation",
+                FileType::new("odp", &["otp"]),
+            ),
+            (
+                b"application/vnd.oasis.opendocument.graphics",
+                FileType::new("odg", &["otg"]),
+            ),
+        ] {
+            if mime.starts_with(prefix) {
+                return kind;
+            }
+        }
+    }
+    if contains(head, b"[Content_Types].xml") {
+        if contains(head, b"word/") {
+            return OOXML_WORD;
+        }
+        if contains(head, b"xl/") {
+            return OOXML_EXCEL;
+        }
+        if contains(head, b"ppt/") {
+            return OOXML_POWERPOINT;
+        }
+    }
+    FileType::new("zip", ZIP_FAMILY)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Read the start of the file at `path` and recognize its type. Files
+/// that cannot be read are not recognized.
+pub fn sniff(path: &Path) -> Option<FileType> {
+    read_head(path).ok().and_then(|head| detect(&head))
+}
+
+fn read_head(path: &Path) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(HEAD_LEN);
+    File::open(path)?
+        .take(HEAD_LEN as u64)
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_common_signatures() {
+        let ext = |head: &[u8]| detect(head).map(|kind| kind.extension);
+        assert_eq!(ext(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some("png"));
+        assert_eq!(ext(b"\xff\xd8\xff\xe0\0\x10JFIF"), Some("jpg"));
+        assert_eq!(ext(b"%PDF-1.7\n"), Some("pdf"));
+        assert_eq!(ext(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(ext(b"\0\0\0\x20ftypisom\0\0\x02\0"), Some("mp4"));
+        assert_eq!(ext(b"\0\0\0\x18ftypheic"), Some("heic"));
+        assert_eq!(ext(b"ID3\x04\0"), Some("mp3"));
+        assert_eq!(ext(b"\x1f\x8b\x08\0"), Some("gz"));
+        assert_eq!(ext(b"BMW service notes"), None);
+        assert_eq!(ext(b"plain text"), None);
+        assert_eq!(ext(b""), None);
+
+        let mut tar = vec![0; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(ext(&tar), Some("tar"));
+    }
+
+    #[test]
+    fn tells_zip_based_formats_apart() {
+        let entry = |name: &[u8]| {
+            let mut head = b"PK\x03\x04".to_vec();
+            head.resize(30, 0);
+            head.extend_from_slice(name);
+            head
+        };
+        assert_eq!(
+            detect(&entry(b"[Content_Types].xml....word/document.xml")),
+            Some(OOXML_WORD)
+        );
+        assert_eq!(
+            detect(&entry(b"mimetypeapplication/vnd.oasis.opendocument.text"))
+                .map(|kind| kind.extension),
+            Some("odt")
+        );
+        let zip = detect(&entry(b"photos/a.jpg")).unwrap();
+        assert_eq!(zip.extension, "zip");
+        assert!(zip.accepts("jar") && zip.accepts("DOCX"));
+    }
+
+    #[test]
+    fn accepts_related_extensions() {
+        let tiff = detect(b"II*\0\x08\0\0\0").unwrap();
+        assert!(
+            tiff.accepts("tif") && tiff.accepts("TIFF") && tiff.accepts("cr2")
+        );
+        assert!(!tiff.accepts("jpg"));
+
+        let gz = detect(b"\x1f\x8b\x08\0").unwrap();
+        assert!(gz.accepts("tar.gz") && gz.accepts("tgz"));
+        assert!(!gz.accepts("tar"));
+    }
+}
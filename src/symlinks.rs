@@ -0,0 +1,227 @@
+//! Symlink handling for `--symlinks` and `--fix-symlinks`.
+//!
+//! A symlink is renamed like any other entry, but renaming what it points
+//! at leaves it dangling. After the renames, `plan_fixes` works out which
+//! relative symlinks found during the walk pointed at a path that has
+//! moved, and what they should point at instead. Targets are resolved
+//! lexically, without following symlinks along the way, and absolute
+//! targets are left alone.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::plan::{self, RenamePlan};
+
+/// What recursion does with symlinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Leave them alone.
+    Skip,
+    /// Rename the link itself, never what it points at.
+    #[default]
+    Rename,
+    /// Rename the link, and enter it if it points at a directory. Each
+    /// directory is entered only once, however many links lead to it.
+    Follow,
+}
+
+impl SymlinkPolicy {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "rename" => Ok(Self::Rename),
+            "follow" => Ok(Self::Follow),
+            _ => Err(format!(
+                "Invalid symlink policy '{s}' (expected skip, rename or follow)"
+            )),
+        }
+    }
+}
+
+/// A symlink whose target has to change after the renames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkFix {
+    /// Where the link is once the renames are done.
+    pub link: PathBuf,
+    pub old_target: PathBuf,
+    pub new_target: PathBuf,
+}
+
+impl LinkFix {
+    /// Point the link at `new_target`. The new link is made under a
+    /// temporary name and renamed over the old one, so the link never
+    /// goes missing, even if this fails halfway.
+    pub fn apply(&self) -> io::Result<()> {
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(self.link.file_name().unwrap_or_default());
+        tmp_name.push(format!(".sanitize_filenames-{}", std::process::id()));
+        let tmp = self.link.with_file_name(tmp_name);
+        make_symlink(&self.new_target, &tmp)?;
+        fs::rename(&tmp, &self.link).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+    }
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn make_symlink(_target: &Path, link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("cannot recreate symlink '{}'", link.display()),
+    ))
+}
+
+/// The fixes for the links in `plan.links` once the renames of `plan`
+/// are done.
+pub fn plan_fixes(plan: &RenamePlan) -> Vec<LinkFix> {
+    // Renames as the walk spelled them, for reporting, and normalized,
+    // for comparing with link targets.
+    let spelled: HashMap<PathBuf, PathBuf> = plan
+        .renames()
+        .map(|op| (op.from.clone(), op.to.clone()))
+        .collect();
+    let renamed: HashMap<PathBuf, PathBuf> = spelled
+        .iter()
+        .map(|(from, to)| (normalize(from), normalize(to)))
+        .collect();
+    plan.links
+        .iter()
+        .filter_map(|link| {
+            let (old_target, new_target) = retarget(link, &renamed)?;
+            Some(LinkFix {
+                link: new_location(link, &spelled),
+                old_target,
+                new_target,
+            })
+        })
+        .collect()
+}
+
+/// The old and new target of `link`, if it has to change.
+fn retarget(
+    link: &Path,
+    renamed: &HashMap<PathBuf, PathBuf>,
+) -> Option<(PathBuf, PathBuf)> {
+    let old_target = fs::read_link(link).ok()?;
+    if old_target.is_absolute() {
+        return None;
+    }
+    let link = normalize(link);
+    let pointee = normalize(&link.parent()?.join(&old_target));
+    let new_pointee = new_location(&pointee, renamed);
+    if new_pointee == pointee {
+        return None;
+    }
+    let new_link = new_location(&link, renamed);
+    let new_target = relative_path(new_link.parent()?, &new_pointee);
+    Some((old_target, new_target))
+}
+
+/// Where `path` is once it and the directories above it have moved.
+fn new_location(path: &Path, renamed: &HashMap<PathBuf, PathBuf>) -> PathBuf {
+    match renamed.get(path) {
+        Some(to) => plan::final_location(to, renamed),
+        None => plan::final_location(path, renamed),
+    }
+}
+
+/// `path` made absolute, with `.` and `..` resolved lexically.
+fn normalize(path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// `to` relative to the directory `from`; both are normalized.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut out = PathBuf::new();
+    for _ in common..from.len() {
+        out.push("..");
+    }
+    out.extend(&to[common..]);
+    if out.as_os_str().is_empty() {
+        out.push(".");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_relative_paths() {
+        let rel = |from: &str, to: &str| {
+            relative_path(Path::new(from), Path::new(to))
+        };
+        assert_eq!(rel("/a/b", "/a/b/c"), Path::new("c"));
+        assert_eq!(rel("/a/b", "/a/c/d"), Path::new("../c/d"));
+        assert_eq!(rel("/a/b", "/x"), Path::new("../../x"));
+        assert_eq!(rel("/a/b", "/a/b"), Path::new("."));
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+    }
+
+    #[test]
+    fn follows_renamed_directories() {
+        let renamed = HashMap::from([
+            (PathBuf::from("/m/Old Dir"), PathBuf::from("/m/Old_Dir")),
+            (
+                PathBuf::from("/m/Old Dir/a b.mp3"),
+                PathBuf::from("/m/Old Dir/a_b.mp3"),
+            ),
+        ]);
+        assert_eq!(
+            new_location(Path::new("/m/Old Dir/a b.mp3"), &renamed),
+            Path::new("/m/Old_Dir/a_b.mp3")
+        );
+        assert_eq!(
+            new_location(Path::new("/m/Old Dir/c.mp3"), &renamed),
+            Path::new("/m/Old_Dir/c.mp3")
+        );
+        assert_eq!(
+            new_location(Path::new("/m/other"), &renamed),
+            Path::new("/m/other")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaces_links_in_place() {
+        let dir = std::env::temp_dir().join(format!(
+            "sanitize_filenames_link_fix_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let link = dir.join("link");
+        make_symlink(Path::new("old target"), &link).unwrap();
+        let fix = LinkFix {
+            link: link.clone(),
+            old_target: PathBuf::from("old target"),
+            new_target: PathBuf::from("new_target"),
+        };
+        fix.apply().unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("new_target"));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
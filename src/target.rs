@@ -0,0 +1,294 @@
+//! Filesystem target profiles used by `--target`.
+//!
+//! A profile is applied to the finished name (extension included), after
+//! the sanitize mode has run, and only ever makes names stricter.
+
+/// Pairs of base letter and combining mark that compose to a single
+/// precomposed letter, grouped by mark: the n-th base in the first string
+/// composes to the n-th letter in the second. Covers Latin-1 through
+/// Latin Extended-B and Latin Extended Additional.
+const COMPOSED: &[(char, &str, &str)] = &[
+    // Combining Grave Accent
+    (
+        '\u{0300}',
+        "AEIOUaeiouÜüNnĒēŌōWwÂâĂăÊêÔôƠơƯưYy",
+        "ÀÈÌÒÙàèìòùǛǜǸǹḔḕṐṑẀẁẦầẰằỀềỒồỜờỪừỲỳ",
+    ),
+    // Combining Acute Accent
+    (
+        '\u{0301}',
+        "AEIOUYaeiouyCcLlNnRrSsZzÜüGgÅåÆæØøÇçĒēÏïKkMmÕõŌōPpŨũWwÂâĂăÊêÔôƠơƯư",
+        "ÁÉÍÓÚÝáéíóúýĆćĹĺŃńŔŕŚśŹźǗǘǴǵǺǻǼǽǾǿḈḉḖḗḮḯḰḱḾḿṌṍṒṓṔṕṸṹẂẃẤấẮắẾếỐốỚớỨứ",
+    ),
+    // Combining Circumflex Accent
+    (
+        '\u{0302}',
+        "AEIOUaeiouCcGgHhJjSsWwYyZzẠạẸẹỌọ",
+        "ÂÊÎÔÛâêîôûĈĉĜĝĤĥĴĵŜŝŴŵŶŷẐẑẬậỆệỘộ",
+    ),
+    // Combining Tilde
+    (
+        '\u{0303}',
+        "ANOanoIiUuVvÂâĂăEeÊêÔôƠơƯưYy",
+        "ÃÑÕãñõĨĩŨũṼṽẪẫẴẵẼẽỄễỖỗỠỡỮữỸỹ",
+    ),
+    // Combining Macron
+    (
+        '\u{0304}',
+        "AaEeIiOoUuÜüÄäȦȧÆæǪǫÖöÕõȮȯYyGgḶḷṚṛ",
+        "ĀāĒēĪīŌōŪūǕǖǞǟǠǡǢǣǬǭȪȫȬȭȰȱȲȳḠḡḸḹṜṝ",
+    ),
+    // Combining Breve
+    ('\u{0306}', "AaEeGgIiOoUuȨȩẠạ", "ĂăĔĕĞğĬĭŎŏŬŭḜḝẶặ"),
+    // Combining Dot Above
+    (
+        '\u{0307}',
+        "CcEeGgIZzAaOoBbDdFfHhMmNnPpRrSsŚśŠšṢṣTtWwXxYyſ",
+        "ĊċĖėĠġİŻżȦȧȮȯḂḃḊḋḞḟḢḣṀṁṄṅṖṗṘṙṠṡṤṥṦṧṨṩṪṫẆẇẊẋẎẏẛ",
+    ),
+    // Combining Diaeresis
+    (
+        '\u{0308}',
+        "AEIOUaeiouyYHhÕõŪūWwXxt",
+        "ÄËÏÖÜäëïöüÿŸḦḧṎṏṺṻẄẅẌẍẗ",
+    ),
+    // Combining Hook Above
+    (
+        '\u{0309}',
+        "AaÂâĂăEeÊêIiOoÔôƠơUuƯưYy",
+        "ẢảẨẩẲẳẺẻỂểỈỉỎỏỔổỞởỦủỬửỶỷ",
+    ),
+    // Combining Ring Above
+    ('\u{030A}', "AaUuwy", "ÅåŮůẘẙ"),
+    // Combining Double Acute Accent
+    ('\u{030B}', "OoUu", "ŐőŰű"),
+    // Combining Caron
+    (
+        '\u{030C}',
+        "CcDdEeLlNnRrSsTtZzAaIiOoUuÜüGgKkƷʒjHh",
+        "ČčĎďĚěĽľŇňŘřŠšŤťŽžǍǎǏǐǑǒǓǔǙǚǦǧǨǩǮǯǰȞȟ",
+    ),
+    // Combining Double Grave Accent
+    ('\u{030F}', "AaEeIiOoRrUu", "ȀȁȄȅȈȉȌȍȐȑȔȕ"),
+    // Combining Inverted Breve
+    ('\u{0311}', "AaEeIiOoRrUu", "ȂȃȆȇȊȋȎȏȒȓȖȗ"),
+    // Combining Horn
+    ('\u{031B}', "OoUu", "ƠơƯư"),
+    // Combining Dot Below
+    (
+        '\u{0323}',
+        "BbDdHhKkLlMmNnRrSsTtVvWwZzAaEeIiOoƠơUuƯưYy",
+        "ḄḅḌḍḤḥḲḳḶḷṂṃṆṇṚṛṢṣṬṭṾṿẈẉẒẓẠạẸẹỊịỌọỢợỤụỰựỴỵ",
+    ),
+    // Combining Diaeresis Below
+    ('\u{0324}', "Uu", "Ṳṳ"),
+    // Combining Ring Below
+    ('\u{0325}', "Aa", "Ḁḁ"),
+    // Combining Comma Below
+    ('\u{0326}', "SsTt", "ȘșȚț"),
+    // Combining Cedilla
+    (
+        '\u{0327}',
+        "CcGgKkLlNnRrSsTtEeDdHh",
+        "ÇçĢģĶķĻļŅņŖŗŞşŢţȨȩḐḑḨḩ",
+    ),
+    // Combining Ogonek
+    ('\u{0328}', "AaEeIiUuOo", "ĄąĘęĮįŲųǪǫ"),
+    // Combining Circumflex Accent Below
+    ('\u{032D}', "DdEeLlNnTtUu", "ḒḓḘḙḼḽṊṋṰṱṶṷ"),
+    // Combining Breve Below
+    ('\u{032E}', "Hh", "Ḫḫ"),
+    // Combining Tilde Below
+    ('\u{0330}', "EeIiUu", "ḚḛḬḭṴṵ"),
+    // Combining Macron Below
+    ('\u{0331}', "BbDdKkLlNnRrTtZzh", "ḆḇḎḏḴḵḺḻṈṉṞṟṮṯẔẕẖ"),
+];
+
+/// Device names that Windows reserves regardless of extension.
+const WINDOWS_RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6",
+    "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6",
+    "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetFs {
+    /// The POSIX portable filename character set: `A-Z a-z 0-9 . _ -`.
+    Posix,
+    /// NTFS, SMB shares and FAT32/exFAT long names (`fat32` is an
+    /// alias): no `<>:"/\|?*` or control characters, no reserved device
+    /// names, no trailing dots or spaces.
+    Windows,
+    /// HFS+ and APFS: no `:`, and names are composed to NFC so that a
+    /// decomposed and a precomposed copy of a name cannot both exist.
+    Macos,
+    /// Amazon S3 object keys, limited to the characters AWS lists as
+    /// safe: `A-Z a-z 0-9 ! - _ . * ' ( )`.
+    S3,
+}
+
+impl TargetFs {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "posix" => Ok(Self::Posix),
+            "macos" => Ok(Self::Macos),
+            "windows" | "fat32" => Ok(Self::Windows),
+            "s3" => Ok(Self::S3),
+            _ => Err(format!(
+                "Invalid target '{s}' (expected windows, macos, fat32, posix or s3)"
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Posix => "posix",
+            Self::Windows => "windows",
+            Self::Macos => "macos",
+            Self::S3 => "s3",
+        }
+    }
+
+    /// Whether the filesystem treats names that differ only in case as
+    /// the same name.
+    pub fn case_insensitive(self) -> bool {
+        matches!(self, Self::Windows | Self::Macos)
+    }
+
+    /// Whether `ch` may appear in a name on this target.
+    pub fn allows(self, ch: char) -> bool {
+        match self {
+            Self::Posix => {
+                ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '-')
+            }
+            Self::Windows => {
+                !ch.is_control()
+                    && !matches!(
+                        ch,
+                        '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*'
+                    )
+            }
+            Self::Macos => ch != ':' && ch != '/' && ch != '\0',
+            Self::S3 => {
+                ch.is_ascii_alphanumeric()
+                    || matches!(
+                        ch,
+                        '!' | '-' | '_' | '.' | '*' | '\'' | '(' | ')'
+                    )
+            }
+        }
+    }
+
+    /// Make `name` valid on this target. Forbidden characters become
+    /// `replacement` (without doubling it up), and a name that would
+    /// still be rejected is adjusted as little as possible.
+    pub fn apply(self, name: &str, replacement: &str) -> String {
+        let composed;
+        let name = if self == Self::Macos {
+            composed = compose(name);
+            composed.as_str()
+        } else {
+            name
+        };
+
+        let mut out = String::with_capacity(name.len());
+        for ch in name.chars() {
+            if self.allows(ch) {
+                out.push(ch);
+            } else if !out.ends_with(replacement) {
+                out.push_str(replacement);
+            }
+        }
+
+        if self == Self::Windows {
+            let trimmed = out.trim_end_matches(['.', ' ']).len();
+            if trimmed == 0 && !out.is_empty() {
+                // Nothing but dots and spaces; keep "." and "..".
+                if out != "." && out != ".." {
+                    out = crate::filler(replacement).to_string();
+                }
+            } else {
+                out.truncate(trimmed);
+            }
+
+            let stem = out.split('.').next().unwrap_or_default();
+            if WINDOWS_RESERVED
+                .iter()
+                .any(|reserved| reserved.eq_ignore_ascii_case(stem.trim_end()))
+            {
+                out.insert_str(stem.len(), crate::filler(replacement));
+            }
+        }
+
+        if out.is_empty() && !name.is_empty() {
+            out.push_str(crate::filler(replacement));
+        }
+        out
+    }
+}
+
+/// Compose base letters followed by combining marks into precomposed
+/// letters where `COMPOSED` knows how, approximating NFC.
+fn compose(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for ch in name.chars() {
+        let composed = out.chars().next_back().and_then(|base| {
+            let &(_, bases, letters) =
+                COMPOSED.iter().find(|&&(mark, _, _)| mark == ch)?;
+            let idx = bases.chars().position(|b| b == base)?;
+            letters.chars().nth(idx)
+        });
+        match composed {
+            Some(letter) => {
+                out.pop();
+                out.push(letter);
+            }
+            None => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_replaces_forbidden_characters() {
+        assert_eq!(
+            TargetFs::Windows.apply("a<b>c|d?.txt", "_"),
+            "a_b_c_d_.txt"
+        );
+        assert_eq!(TargetFs::Windows.apply("tab\there", "_"), "tab_here");
+    }
+
+    #[test]
+    fn windows_avoids_reserved_names_and_trailing_dots() {
+        assert_eq!(TargetFs::Windows.apply("CON", "_"), "CON_");
+        assert_eq!(TargetFs::Windows.apply("con.txt", "_"), "con_.txt");
+        assert_eq!(TargetFs::Windows.apply("lpt1.tar.gz", "-"), "lpt1-.tar.gz");
+        assert_eq!(TargetFs::Windows.apply("CONSOLE.txt", "_"), "CONSOLE.txt");
+        assert_eq!(TargetFs::Windows.apply("notes. . ", "_"), "notes");
+        assert_eq!(TargetFs::Windows.apply("...", "_"), "_");
+    }
+
+    #[test]
+    fn fat32_is_an_alias_of_windows() {
+        assert_eq!(TargetFs::parse("fat32"), Ok(TargetFs::Windows));
+        assert_eq!(TargetFs::Windows.apply("a\u{7f}b", "_"), "a_b");
+    }
+
+    #[test]
+    fn macos_composes_and_replaces_colons() {
+        assert_eq!(TargetFs::Macos.apply("Cafe\u{301}.txt", "_"), "Café.txt");
+        assert_eq!(TargetFs::Macos.apply("e\u{323}\u{302}", "_"), "ệ");
+        assert_eq!(TargetFs::Macos.apply("10:30 notes", "_"), "10_30 notes");
+    }
+
+    #[test]
+    fn posix_and_s3_use_their_character_sets() {
+        assert_eq!(TargetFs::Posix.apply("naïve (1).txt", "_"), "na_ve_1_.txt");
+        assert_eq!(TargetFs::S3.apply("naïve (1).txt", "_"), "na_ve_(1).txt");
+        assert_eq!(TargetFs::S3.apply("a+b=c", "-"), "a-b-c");
+    }
+}
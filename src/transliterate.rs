@@ -0,0 +1,298 @@
+//! ASCII transliteration used by `SanitizeMode::Transliterate`.
+//!
+//! Accented letters are reduced to their base letter, and letters that
+//! have no ASCII base (ß, Ж, θ, ...) are spelled out using a fixed table.
+//! Anything not covered is passed through unchanged so the whitelist
+//! that runs afterwards can replace it.
+
+/// Precomposed letters, grouped by the ASCII letter they decompose to.
+const DECOMPOSED: &[(char, &str)] = &[
+    ('A', "ÀÁÂÃÄÅĀĂĄǍǞǠǺȀȂȦȺḀẠẢẤẦẨẪẬẮẰẲẴẶ"),
+    ('a', "àáâãäåāăąǎǟǡǻȁȃȧḁạảấầẩẫậắằẳẵặ"),
+    ('C', "ÇĆĈĊČƇȻḈ"),
+    ('c', "çćĉċčƈȼḉ"),
+    ('D', "ĎḊḌḎḐḒ"),
+    ('d', "ďḋḍḏḑḓ"),
+    ('E', "ÈÉÊËĒĔĖĘĚȄȆȨḔḖḘḚḜẸẺẼẾỀỂỄỆ"),
+    ('e', "èéêëēĕėęěȅȇȩḕḗḙḛḝẹẻẽếềểễệ"),
+    ('G', "ĜĞĠĢǤǦǴḠ"),
+    ('g', "ĝğġģǥǧǵḡ"),
+    ('H', "ĤȞḢḤḦḨḪ"),
+    ('h', "ĥȟḣḥḧḩḫẖ"),
+    ('I', "ÌÍÎÏĨĪĬĮİǏȈȊḬḮỈỊ"),
+    ('i', "ìíîïĩīĭįǐȉȋḭḯỉị"),
+    ('J', "Ĵ"),
+    ('j', "ĵǰ"),
+    ('K', "ĶǨḰḲḴ"),
+    ('k', "ķǩḱḳḵ"),
+    ('L', "ĹĻĽĿḶḸḺḼ"),
+    ('l', "ĺļľŀḷḹḻḽ"),
+    ('M', "ḾṀṂ"),
+    ('m', "ḿṁṃ"),
+    ('N', "ÑŃŅŇǸṄṆṈṊ"),
+    ('n', "ñńņňǹṅṇṉṋ"),
+    ('O', "ÒÓÔÕÖŌŎŐƠǑǪǬȌȎȪȬȮȰṌṎṐṒỌỎỐỒỔỖỘỚỜỞỠỢ"),
+    ('o', "òóôõöōŏőơǒǫǭȍȏȫȭȯȱṍṏṑṓọỏốồổỗộớờởỡợ"),
+    ('P', "ṔṖ"),
+    ('p', "ṕṗ"),
+    ('R', "ŔŖŘȐȒṘṚṜṞ"),
+    ('r', "ŕŗřȑȓṙṛṝṟ"),
+    ('S', "ŚŜŞŠȘṠṢṤṦṨ"),
+    ('s', "śŝşšșṡṣṥṧṩ"),
+    ('T', "ŢŤȚṪṬṮṰ"),
+    ('t', "ţťțṫṭṯṱẗ"),
+    ('U', "ÙÚÛÜŨŪŬŮŰŲƯǓǕǗǙǛȔȖṲṴṶṸṺỤỦỨỪỬỮỰ"),
+    ('u', "ùúûüũūŭůűųưǔǖǘǚǜȕȗṳṵṷṹṻụủứừửữự"),
+    ('V', "ṼṾ"),
+    ('v', "ṽṿ"),
+    ('W', "ŴẀẂẄẆẈ"),
+    ('w', "ŵẁẃẅẇẉẘ"),
+    ('X', "ẊẌ"),
+    ('x', "ẋẍ"),
+    ('Y', "ÝŶŸȲẎỲỴỶỸ"),
+    ('y', "ýÿŷȳẏẙỳỵỷỹ"),
+    ('Z', "ŹŻŽẐẒẔ"),
+    ('z', "źżžẑẓẕ"),
+    // Greek letters with tonos or dialytika reduce to the plain letter,
+    // which is then spelled out by `SPELLED`.
+    ('Α', "Ά"),
+    ('α', "ά"),
+    ('Ε', "Έ"),
+    ('ε', "έ"),
+    ('Η', "Ή"),
+    ('η', "ή"),
+    ('Ι', "ΊΪ"),
+    ('ι', "ίϊΐ"),
+    ('Ο', "Ό"),
+    ('ο', "ό"),
+    ('Υ', "ΎΫ"),
+    ('υ', "ύϋΰ"),
+    ('Ω', "Ώ"),
+    ('ω', "ώ"),
+    // Cyrillic letters with diacritics.
+    ('Е', "Ѐ"),
+    ('е', "ѐ"),
+    ('И', "Ѝ"),
+    ('и', "ѝ"),
+];
+
+/// Letters and symbols that are spelled out in ASCII. Lowercase entries
+/// also cover their uppercase form, which is capitalized on output
+/// (Ж -> Zh) unless an explicit uppercase entry exists.
+const SPELLED: &[(char, &str)] = &[
+    // Latin letters without a decomposition.
+    ('ß', "ss"),
+    ('ẞ', "SS"),
+    ('æ', "ae"),
+    ('Æ', "AE"),
+    ('œ', "oe"),
+    ('Œ', "OE"),
+    ('ĳ', "ij"),
+    ('Ĳ', "IJ"),
+    ('ø', "o"),
+    ('ð', "d"),
+    ('þ', "th"),
+    ('đ', "d"),
+    ('ħ', "h"),
+    ('ł', "l"),
+    ('ŋ', "ng"),
+    ('ı', "i"),
+    ('ſ', "s"),
+    // Cyrillic (Russian, Ukrainian, Belarusian, Serbian, Macedonian).
+    ('а', "a"),
+    ('б', "b"),
+    ('в', "v"),
+    ('г', "g"),
+    ('ґ', "g"),
+    ('д', "d"),
+    ('ђ', "dj"),
+    ('ѓ', "gj"),
+    ('е', "e"),
+    ('ё', "yo"),
+    ('є', "ye"),
+    ('ж', "zh"),
+    ('з', "z"),
+    ('ѕ', "dz"),
+    ('и', "i"),
+    ('і', "i"),
+    ('ї', "yi"),
+    ('й', "y"),
+    ('ј', "j"),
+    ('к', "k"),
+    ('ќ', "kj"),
+    ('л', "l"),
+    ('љ', "lj"),
+    ('м', "m"),
+    ('н', "n"),
+    ('њ', "nj"),
+    ('о', "o"),
+    ('п', "p"),
+    ('р', "r"),
+    ('с', "s"),
+    ('т', "t"),
+    ('ћ', "c"),
+    ('у', "u"),
+    ('ў', "u"),
+    ('ф', "f"),
+    ('х', "kh"),
+    ('ц', "ts"),
+    ('ч', "ch"),
+    ('џ', "dz"),
+    ('ш', "sh"),
+    ('щ', "shch"),
+    ('ъ', ""),
+    ('ы', "y"),
+    ('ь', ""),
+    ('э', "e"),
+    ('ю', "yu"),
+    ('я', "ya"),
+    // Greek.
+    ('α', "a"),
+    ('β', "v"),
+    ('γ', "g"),
+    ('δ', "d"),
+    ('ε', "e"),
+    ('ζ', "z"),
+    ('η', "i"),
+    ('θ', "th"),
+    ('ι', "i"),
+    ('κ', "k"),
+    ('λ', "l"),
+    ('μ', "m"),
+    ('ν', "n"),
+    ('ξ', "x"),
+    ('ο', "o"),
+    ('π', "p"),
+    ('ρ', "r"),
+    ('σ', "s"),
+    ('ς', "s"),
+    ('τ', "t"),
+    ('υ', "y"),
+    ('φ', "f"),
+    ('χ', "ch"),
+    ('ψ', "ps"),
+    ('ω', "o"),
+    // Common symbols.
+    ('×', "x"),
+    ('€', "EUR"),
+    ('£', "GBP"),
+    ('¥', "JPY"),
+    ('¢', "c"),
+    ('©', "c"),
+    ('®', "r"),
+    ('™', "tm"),
+    ('°', "deg"),
+    ('µ', "u"),
+    ('¹', "1"),
+    ('²', "2"),
+    ('³', "3"),
+    ('‘', "'"),
+    ('’', "'"),
+    ('‚', "'"),
+    ('“', "\""),
+    ('”', "\""),
+    ('„', "\""),
+    ('«', "\""),
+    ('»', "\""),
+    ('–', "-"),
+    ('—', "-"),
+    ('…', "..."),
+];
+
+fn is_combining_mark(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+fn decompose(ch: char) -> char {
+    DECOMPOSED
+        .iter()
+        .find(|(_, variants)| variants.contains(ch))
+        .map_or(ch, |&(base, _)| base)
+}
+
+fn spelled(ch: char) -> Option<String> {
+    if let Some(&(_, s)) = SPELLED.iter().find(|&&(c, _)| c == ch) {
+        return Some(s.to_string());
+    }
+
+    // Fall back to the lowercase entry and capitalize it.
+    let mut lower = ch.to_lowercase();
+    let lc = lower.next().filter(|_| lower.next().is_none())?;
+    if lc == ch {
+        return None;
+    }
+    let &(_, s) = SPELLED.iter().find(|&&(c, _)| c == lc)?;
+    let mut chars = s.chars();
+    Some(match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    })
+}
+
+/// Transliterate `name` to ASCII where a mapping is known. Combining
+/// marks (as found in NFD names written by macOS) are dropped.
+pub fn transliterate(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for ch in name.chars() {
+        if ch.is_ascii() {
+            out.push(ch);
+            continue;
+        }
+        if is_combining_mark(ch) {
+            continue;
+        }
+        let base = decompose(ch);
+        if base.is_ascii() {
+            out.push(base);
+        } else if let Some(s) = spelled(base) {
+            out.push_str(&s);
+        } else {
+            out.push(base);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_accents_from_precomposed_letters() {
+        assert_eq!(transliterate("Café Müller"), "Cafe Muller");
+        assert_eq!(transliterate("Ångström Łódź"), "Angstrom Lodz");
+        assert_eq!(transliterate("Tiếng Việt"), "Tieng Viet");
+    }
+
+    #[test]
+    fn drops_combining_marks_from_decomposed_input() {
+        assert_eq!(transliterate("Cafe\u{301} Mu\u{308}ller"), "Cafe Muller");
+    }
+
+    #[test]
+    fn spells_out_letters_without_ascii_base() {
+        assert_eq!(transliterate("Straße"), "Strasse");
+        assert_eq!(transliterate("Æsir Œuvre"), "AEsir OEuvre");
+        assert_eq!(transliterate("Жизнь Щука"), "Zhizn Shchuka");
+        assert_eq!(transliterate("Їжак Ёлка"), "Yizhak Yolka");
+        assert_eq!(transliterate("Θάλασσα ψυχή"), "Thalassa psychi");
+    }
+
+    #[test]
+    fn maps_common_symbols() {
+        assert_eq!(transliterate("4×4 – 5€"), "4x4 - 5EUR");
+        assert_eq!(transliterate("“quoted”"), "\"quoted\"");
+    }
+
+    #[test]
+    fn leaves_unknown_characters_alone() {
+        assert_eq!(transliterate("日本 🐾"), "日本 🐾");
+    }
+}
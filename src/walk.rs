@@ -0,0 +1,257 @@
+//! The recursive walk behind `--recursive`.
+//!
+//! Walking a tree has two halves. The scan reads directories, applies
+//! the filters and works out every sanitized name; with `--jobs`, idle
+//! threads take over whole subtrees as the scan comes across them. The
+//! planning half hands the scanned entries to the `Planner` one at a
+//! time, in the order a single thread would have found them: contents
+//! before their directory, and siblings in `--sort` order. Renames,
+//! output and collision suffixes are therefore the same for any number
+//! of threads.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, Scope, ScopedJoinHandle};
+
+use crate::ignore::Ignores;
+use crate::plan::{Planner, RenameKind, RenameOp, RenamePlan, SkipReason};
+use crate::{SanitizeOptions, SortOrder, SymlinkPolicy, safety};
+
+/// One entry found by the scan.
+enum Step {
+    /// Rename `path` to `new`; `None` if no new name could be worked
+    /// out.
+    Rename {
+        path: PathBuf,
+        new: Option<PathBuf>,
+    },
+    Missing(PathBuf),
+    /// A symlink, for `--fix-symlinks`.
+    Link(PathBuf),
+}
+
+/// Scanned entries, in planning order, or a thread still scanning the
+/// entries that go in its place.
+enum Chunk<'scope> {
+    Step(Step),
+    Thread(ScopedJoinHandle<'scope, io::Result<Vec<Chunk<'scope>>>>),
+}
+
+/// Plan the sanitization of `root` and, for directories, everything
+/// below it, adding the operations to `plan`.
+pub(crate) fn plan_tree(
+    root: &Path,
+    options: &SanitizeOptions,
+    planner: &mut Planner,
+    plan: &mut RenamePlan,
+) -> io::Result<()> {
+    let scan = Scan::new(root, options)?;
+    let mut ignores = Ignores::new(root, &options.exclude, options.gitignore)?;
+    thread::scope(|scope| {
+        let mut chunks = Vec::new();
+        scan.scan(scope, root, 0, &mut ignores, &mut chunks)?;
+        feed(chunks, planner, plan)
+    })
+}
+
+/// Plan `chunks` in order, waiting for the threads among them.
+fn feed(
+    chunks: Vec<Chunk<'_>>,
+    planner: &mut Planner,
+    plan: &mut RenamePlan,
+) -> io::Result<()> {
+    for chunk in chunks {
+        match chunk {
+            Chunk::Step(Step::Rename { path, new }) => {
+                let op = crate::plan_op(&path, new, planner)?;
+                plan.ops.push(op);
+            }
+            Chunk::Step(Step::Missing(path)) => plan.ops.push(RenameOp {
+                from: path.clone(),
+                to: path,
+                kind: RenameKind::Skip(SkipReason::Missing),
+            }),
+            Chunk::Step(Step::Link(path)) => plan.links.push(path),
+            Chunk::Thread(handle) => {
+                let chunks = handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+                feed(chunks, planner, plan)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What the threads of a walk from one target share.
+struct Scan<'a> {
+    options: &'a SanitizeOptions,
+    /// Threads that may still be started.
+    idle: AtomicUsize,
+    /// Canonical paths of the directories entered so far, with
+    /// `SymlinkPolicy::Follow`.
+    visited: Mutex<HashSet<PathBuf>>,
+    /// The device of the root, with `--one-file-system`.
+    root_device: Option<u64>,
+    /// Devices of pseudo-filesystems, which are never entered.
+    pseudo_devices: HashSet<u64>,
+}
+
+impl<'a> Scan<'a> {
+    fn new(root: &Path, options: &'a SanitizeOptions) -> io::Result<Self> {
+        // Which link enters a directory first must not depend on the
+        // threads, so links are followed with one.
+        let jobs = match options.jobs {
+            _ if options.symlinks == SymlinkPolicy::Follow => 1,
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        Ok(Self {
+            options,
+            idle: AtomicUsize::new(jobs - 1),
+            visited: Mutex::new(HashSet::new()),
+            root_device: fs::metadata(root)
+                .ok()
+                .filter(|_| options.one_file_system)
+                .and_then(|meta| safety::device(&meta)),
+            pseudo_devices: safety::pseudo_devices(),
+        })
+    }
+
+    /// Scan `path` and, for directories, everything below it. `depth`
+    /// is 0 for the target; entries below it are subject to `ignores`
+    /// and `options.include`.
+    fn scan<'scope, 'env>(
+        &'env self,
+        scope: &'scope Scope<'scope, 'env>,
+        path: &Path,
+        depth: usize,
+        ignores: &mut Ignores,
+        out: &mut Vec<Chunk<'scope>>,
+    ) -> io::Result<()> {
+        let options = self.options;
+        let file_type = match fs::symlink_metadata(path) {
+            Ok(meta) => meta.file_type(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                out.push(Chunk::Step(Step::Missing(path.to_path_buf())));
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+
+        let is_link = file_type.is_symlink();
+        if is_link {
+            if options.symlinks == SymlinkPolicy::Skip {
+                return Ok(());
+            }
+            out.push(Chunk::Step(Step::Link(path.to_path_buf())));
+        }
+        let enters = if is_link {
+            options.symlinks == SymlinkPolicy::Follow && path.is_dir()
+        } else {
+            file_type.is_dir()
+        };
+        // Mount points are left alone entirely, as they cannot be renamed
+        // anyway; links to them are renamed, but not followed.
+        let mount = enters && depth > 0 && self.is_other_mount(path)?;
+        if mount && !is_link {
+            return Ok(());
+        }
+        let enters = enters
+            && !mount
+            && options.max_depth.is_none_or(|max| depth < max);
+        // A followed link may lead anywhere, so where it leads gets the
+        // same protection as a target.
+        if enters && is_link && !options.force {
+            safety::check_target(path)?;
+        }
+        if enters && self.first_visit(path)? {
+            let entered = ignores.enter(path)?;
+            for child_path in read_entries(path, options.sort)? {
+                let child_type = fs::symlink_metadata(&child_path)?.file_type();
+                if ignores.is_ignored(&child_path, child_type.is_dir()) {
+                    continue;
+                }
+
+                if child_type.is_dir() && self.claim_thread() {
+                    let mut ignores = ignores.clone();
+                    out.push(Chunk::Thread(scope.spawn(move || {
+                        let mut chunks = Vec::new();
+                        let result = self.scan(
+                            scope,
+                            &child_path,
+                            depth + 1,
+                            &mut ignores,
+                            &mut chunks,
+                        );
+                        self.idle.fetch_add(1, Ordering::SeqCst);
+                        result.map(|()| chunks)
+                    })));
+                } else if child_type.is_dir() || child_type.is_symlink() {
+                    self.scan(scope, &child_path, depth + 1, ignores, out)?;
+                } else if options.renames(&child_path, depth + 1, false) {
+                    out.push(Chunk::Step(self.rename(child_path)));
+                }
+            }
+            ignores.leave(entered);
+        }
+
+        if options.renames(path, depth, file_type.is_dir()) {
+            out.push(Chunk::Step(self.rename(path.to_path_buf())));
+        }
+        Ok(())
+    }
+
+    fn rename(&self, path: PathBuf) -> Step {
+        let new = crate::sanitized_path(&path, self.options);
+        Step::Rename { path, new }
+    }
+
+    /// Take an idle thread, if there is one.
+    fn claim_thread(&self) -> bool {
+        self.idle
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                n.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Whether the directory `path` is on a pseudo-filesystem or, with
+    /// `--one-file-system`, on another filesystem than the root.
+    fn is_other_mount(&self, path: &Path) -> io::Result<bool> {
+        let Some(device) = safety::device(&fs::metadata(path)?) else {
+            return Ok(false);
+        };
+        Ok(self.pseudo_devices.contains(&device)
+            || self.root_device.is_some_and(|root| root != device))
+    }
+
+    /// Whether the directory `path` has not been entered before, which
+    /// only links can lead to.
+    fn first_visit(&self, path: &Path) -> io::Result<bool> {
+        if self.options.symlinks != SymlinkPolicy::Follow {
+            return Ok(true);
+        }
+        let canonical = fs::canonicalize(path)?;
+        Ok(self
+            .visited
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(canonical))
+    }
+}
+
+/// The entries of the directory `path`, in `sort` order.
+fn read_entries(path: &Path, sort: SortOrder) -> io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    if sort == SortOrder::Name {
+        entries.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    }
+    Ok(entries)
+}
@@ -0,0 +1,102 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn temp_dir() -> PathBuf {
+    let mut base = env::temp_dir();
+    let unique = format!(
+        "sanitize_filenames_cli_{}_{}",
+        std::process::id(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    );
+    base.push(unique);
+    fs::create_dir_all(&base).unwrap();
+    base
+}
+
+fn run_cli(args: &[&str], target: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sanitize_filenames"))
+        .arg("--no-config")
+        .args(args)
+        .arg(target)
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("failed to run sanitize_filenames")
+}
+
+/// Every structured format emits one record per planned path, including
+/// unchanged names and collisions, with stable action names.
+#[test]
+fn structured_formats_report_every_path() {
+    let tmp = temp_dir();
+    let root = tmp.join("root");
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("clean.txt"), "test").unwrap();
+    fs::write(root.join("a b.txt"), "test").unwrap();
+    fs::write(root.join("a_b.txt"), "test").unwrap();
+
+    let out = run_cli(&["-r", "-n", "--format", "ndjson"], &root);
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "unexpected output: {stdout}");
+    assert!(lines.iter().all(|l| l.starts_with('{') && l.ends_with('}')));
+    assert!(stdout.contains("\"action\":\"collision\""));
+    assert!(stdout.contains("\"action\":\"unchanged\""));
+    assert!(stdout.contains("\"dry_run\":true"));
+
+    let out = run_cli(&["-r", "-n", "--format=json"], &root);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.starts_with('[') && stdout.trim_end().ends_with(']'));
+    assert_eq!(stdout.matches("\"old\":").count(), 4);
+
+    let out = run_cli(&["--format", "tsv"], &root.join("a b.txt"));
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("old\tnew\taction\treason\tdry_run"));
+    let fields: Vec<&str> = lines.next().unwrap().split('\t').collect();
+    assert_eq!(fields[2], "collision");
+    assert_eq!(fields[3], "new name already exists");
+
+    fs::remove_dir_all(tmp).unwrap();
+}
+
+#[test]
+fn structured_output_reports_performed_renames() {
+    let tmp = temp_dir();
+    let file = tmp.join("file name.txt");
+    fs::write(&file, "test").unwrap();
+
+    let out = run_cli(&["--format", "ndjson"], &file);
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("\"action\":\"renamed\""));
+    assert!(stdout.contains("\"dry_run\":false"));
+    assert!(tmp.join("file_name.txt").exists());
+
+    fs::remove_dir_all(tmp).unwrap();
+}
+
+#[test]
+fn structured_output_reports_conflict_failures() {
+    let tmp = temp_dir();
+    let file = tmp.join("a b.txt");
+    fs::write(&file, "test").unwrap();
+    fs::write(tmp.join("a_b.txt"), "test").unwrap();
+
+    for format in ["ndjson", "json", "tsv"] {
+        let out = run_cli(&["--format", format, "--on-conflict=fail"], &file);
+        assert!(!out.status.success());
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        assert!(stdout.contains("error"), "no error record: {stdout}");
+        assert!(stdout.contains("already exists"));
+    }
+    assert!(file.exists());
+
+    fs::remove_dir_all(tmp).unwrap();
+}
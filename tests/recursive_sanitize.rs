@@ -55,27 +55,27 @@ fn recursively_sanitizes_diverse_characters() {
     }
 
     let sanitized_root =
-        sanitize_directory_tree(&root, false, '_', SanitizeMode::Legacy)
+        sanitize_directory_tree(&root, false, "_", SanitizeMode::Legacy)
             .unwrap();
 
     let expected_root = PathBuf::from(sanitized_filename(
         root.to_str().unwrap(),
-        '_',
+        "_",
         SanitizeMode::Legacy,
     ));
     let expected_child_one = PathBuf::from(sanitized_filename(
         expected_root.join("[Child Project] && Mixes?").to_str().unwrap(),
-        '_',
+        "_",
         SanitizeMode::Legacy,
     ));
     let expected_child_two = PathBuf::from(sanitized_filename(
         expected_root.join("Second-Child (Drafts) #2").to_str().unwrap(),
-        '_',
+        "_",
         SanitizeMode::Legacy,
     ));
     let expected_grand_one = PathBuf::from(sanitized_filename(
         expected_child_one.join("Grand ?Child* [v1]").to_str().unwrap(),
-        '_',
+        "_",
         SanitizeMode::Legacy,
     ));
     let expected_grand_two = PathBuf::from(sanitized_filename(
@@ -83,7 +83,7 @@ fn recursively_sanitizes_diverse_characters() {
             .join("Grand Child×Final (Take #1)")
             .to_str()
             .unwrap(),
-        '_',
+        "_",
         SanitizeMode::Legacy,
     ));
 
@@ -109,7 +109,7 @@ fn recursively_sanitizes_diverse_characters() {
             let joined = expected.join(comp);
             expected = PathBuf::from(sanitized_filename(
                 joined.to_str().unwrap(),
-                '_',
+                "_",
                 SanitizeMode::Legacy,
             ));
         }
@@ -153,12 +153,12 @@ fn recursively_sanitizes_all_ascii_filename_characters() {
     }
 
     let sanitized_root =
-        sanitize_directory_tree(&root, false, '_', SanitizeMode::Full)
+        sanitize_directory_tree(&root, false, "_", SanitizeMode::Full)
             .unwrap();
 
     let expected_root = PathBuf::from(sanitized_filename(
         root.to_str().unwrap(),
-        '_',
+        "_",
         SanitizeMode::Full,
     ));
 
@@ -177,7 +177,7 @@ fn recursively_sanitizes_all_ascii_filename_characters() {
             let joined = expected.join(comp);
             expected = PathBuf::from(sanitized_filename(
                 joined.to_str().unwrap(),
-                '_',
+                "_",
                 SanitizeMode::Full,
             ));
         }